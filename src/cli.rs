@@ -0,0 +1,91 @@
+//! Command line arguments.
+
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+
+const USAGE: &str = "Usage: gitools [options] [<path>]
+
+Opens the git repository <path> is in, or the one of the current directory.
+
+Options:
+    --config <file>  Use <file> instead of the user config
+    -h, --help       Print this help
+    -V, --version    Print the version";
+
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    /// Where to look for the repository, it can be any directory in it
+    pub repo: PathBuf,
+    /// Config file to use instead of the user config
+    pub config: Option<PathBuf>,
+}
+
+/// What the command line asks for
+#[derive(Debug, PartialEq)]
+pub enum Cli {
+    Run(Args),
+    /// Print the text and exit, for `--help` and `--version`
+    Print(String),
+}
+
+pub fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Cli> {
+    let mut repo = None;
+    let mut config = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Cli::Print(USAGE.to_owned())),
+            "-V" | "--version" => {
+                let version = format!("gitools {}", env!("CARGO_PKG_VERSION"));
+                return Ok(Cli::Print(version));
+            }
+            "--config" => {
+                let path = args.next().ok_or(anyhow!("--config needs a file"))?;
+                config = Some(PathBuf::from(path));
+            }
+            option if option.starts_with('-') => {
+                return Err(anyhow!("Unknown option {}\n\n{}", option, USAGE));
+            }
+            _ if repo.is_some() => return Err(anyhow!("Too many paths\n\n{}", USAGE)),
+            _ => repo = Some(PathBuf::from(arg)),
+        }
+    }
+
+    Ok(Cli::Run(Args {
+        repo: repo.unwrap_or_else(|| PathBuf::from(".")),
+        config,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn path_should_be_optional_and_options_checked() {
+        let expected = Args {
+            repo: PathBuf::from("."),
+            config: Some(PathBuf::from("my.toml")),
+        };
+        assert_eq!(parse(&["--config", "my.toml"]).unwrap(), Cli::Run(expected));
+
+        let expected = Args {
+            repo: PathBuf::from("src/widgets"),
+            config: None,
+        };
+        assert_eq!(parse(&["src/widgets"]).unwrap(), Cli::Run(expected));
+
+        match parse(&["src", "-V"]).unwrap() {
+            Cli::Print(text) => assert!(text.starts_with("gitools ")),
+            cli => panic!("{:?}", cli),
+        }
+        assert_eq!(parse(&["--help"]).unwrap(), Cli::Print(USAGE.to_owned()));
+
+        assert!(parse(&["--config"]).is_err());
+        assert!(parse(&["--verbose"]).is_err());
+        assert!(parse(&["a", "b"]).is_err());
+    }
+}
@@ -0,0 +1,768 @@
+//! Loading and validating the config.
+//!
+//! The config is made of layers, each one merged key by key over the ones
+//! before it:
+//!
+//! 1. the built-in defaults
+//! 2. `$XDG_CONFIG_HOME/gitools/config.toml`, or the file given with `--config`
+//! 3. `gitools.toml` in the repository's git directory
+//! 4. `gitools.*` keys of the git config, e.g.
+//!    `git config gitools.keymap.map.b.next.b.command TagCheckout`
+//!
+//! The merged config is checked as a whole before it's used, every problem is
+//! reported with the file and line it was found at. A config with errors is
+//! replaced by the built-in defaults, so a typo never keeps the app from
+//! starting.
+
+use crate::keymap::Key;
+use crate::shell;
+use crate::state::{CmdArgs, Command, Config};
+use crate::theme::{AppearanceConfig, Palette, Rgb};
+use crate::xdg;
+use git2::Repository;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use toml::value::{Table, Value};
+
+pub const CONFIG_FILE: &str = "config.toml";
+const REPO_CONFIG_FILE: &str = "gitools.toml";
+const DEFAULT_CONFIG: &str = include_str!("../config.toml");
+const GIT_CONFIG_SECTION: &str = "gitools.";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigError {
+    pub file: String,
+    /// 1-based line of the problem, if it could be found
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.file, line, self.message),
+            None => write!(f, "{}: {}", self.file, self.message),
+        }
+    }
+}
+
+/// Settings from one place, merged over the layers before it
+struct Layer {
+    /// File name or other description used in errors
+    name: String,
+    /// Text the layer was parsed from, to find the lines of errors in
+    source: Option<String>,
+    value: Value,
+}
+
+impl Layer {
+    fn parse(name: &str, source: &str) -> Result<Layer, ConfigError> {
+        let value = source.parse::<Value>().map_err(|err| ConfigError {
+            file: name.to_owned(),
+            line: err.line_col().map(|(line, _)| line + 1),
+            message: format!("{}", err),
+        })?;
+
+        Ok(Layer {
+            name: name.to_owned(),
+            source: Some(source.to_owned()),
+            value,
+        })
+    }
+
+    /// Read a config file. Files that don't exist are skipped unless
+    /// `required`.
+    fn read(path: &Path, required: bool) -> Result<Option<Layer>, ConfigError> {
+        let name = path.display().to_string();
+        match std::fs::read_to_string(path) {
+            Ok(source) => Layer::parse(&name, &source).map(Some),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound && !required => Ok(None),
+            Err(err) => Err(ConfigError {
+                file: name,
+                line: None,
+                message: format!("Failed to read the config: {}", err),
+            }),
+        }
+    }
+
+    /// The `gitools.*` keys of the git config, None if there aren't any
+    fn git_config(repo: &Repository) -> Result<Option<Layer>, ConfigError> {
+        let to_error = |err: git2::Error| ConfigError {
+            file: "git config".to_owned(),
+            line: None,
+            message: format!("{}", err),
+        };
+
+        let config = repo.config().map_err(to_error)?;
+        let entries = config.entries(Some("^gitools\\.")).map_err(to_error)?;
+        let mut value = Value::Table(Table::new());
+        for entry in entries {
+            let entry = entry.map_err(to_error)?;
+            let (name, entry_value) = match (entry.name(), entry.value()) {
+                (Some(name), Some(entry_value)) => (name, entry_value),
+                _ => continue,
+            };
+
+            let name = &name[GIT_CONFIG_SECTION.len()..];
+            // Keymap fields are all strings, a key can be a digit
+            let entry_value = if name.starts_with("keymap.") {
+                Value::String(entry_value.to_owned())
+            } else {
+                git_value(entry_value)
+            };
+            insert(&mut value, name.split('.'), entry_value);
+        }
+
+        if value.as_table().map_or(true, |table| table.is_empty()) {
+            return Ok(None);
+        }
+        Ok(Some(Layer {
+            name: "git config".to_owned(),
+            source: None,
+            value,
+        }))
+    }
+}
+
+/// Config files read for `repo`, in the order they're merged. `explicit` is
+/// the file given with `--config`, it replaces the user config.
+fn config_files(repo: &Repository, explicit: Option<&Path>) -> Vec<PathBuf> {
+    let user = match explicit {
+        Some(path) => Some(path.to_owned()),
+        None => xdg::config_dir().map(|dir| dir.join(CONFIG_FILE)),
+    };
+    user.into_iter()
+        .chain(Some(repo.path().join(REPO_CONFIG_FILE)))
+        .collect()
+}
+
+/// Files the config of `repo` is read from, including the git config, to be
+/// watched for changes
+pub fn watched_files(repo: &Repository, explicit: Option<&Path>) -> Vec<PathBuf> {
+    let mut files = config_files(repo, explicit);
+    files.push(repo.path().join("config"));
+    files
+}
+
+/// Load and merge all the layers of the config of `repo`, or use the defaults
+/// if any of them has errors. The errors are returned along with the config
+/// used instead.
+pub fn load(repo: &Repository, explicit: Option<&Path>) -> (Config, Vec<ConfigError>) {
+    match try_load(repo, explicit) {
+        Ok(config) => (config, vec![]),
+        Err(errors) => (defaults(), errors),
+    }
+}
+
+/// Load and merge all the layers of the config of `repo`
+pub fn try_load(repo: &Repository, explicit: Option<&Path>) -> Result<Config, Vec<ConfigError>> {
+    let mut errors = vec![];
+    let mut layers = vec![default_layer()];
+
+    for path in config_files(repo, explicit) {
+        let required = Some(path.as_path()) == explicit;
+        match Layer::read(&path, required) {
+            Ok(layer) => layers.extend(layer),
+            Err(err) => errors.push(err),
+        }
+    }
+    match Layer::git_config(repo) {
+        Ok(layer) => layers.extend(layer),
+        Err(err) => errors.push(err),
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    build(&layers)
+}
+
+pub fn defaults() -> Config {
+    build(&[default_layer()]).expect("Invalid built-in config")
+}
+
+fn default_layer() -> Layer {
+    Layer::parse("defaults", DEFAULT_CONFIG).expect("Invalid built-in config")
+}
+
+/// Parse and validate a single config, `file` is only used in the errors
+pub fn parse(source: &str, file: &str) -> Result<Config, Vec<ConfigError>> {
+    let layer = Layer::parse(file, source).map_err(|err| vec![err])?;
+    build(&[layer])
+}
+
+/// Merge the layers and validate the result
+fn build(layers: &[Layer]) -> Result<Config, Vec<ConfigError>> {
+    let mut value = Value::Table(Table::new());
+    for layer in layers {
+        merge(&mut value, layer.value.clone());
+    }
+
+    let mut validator = Validator {
+        layers,
+        errors: vec![],
+    };
+    validator.validate(&value);
+    if !validator.errors.is_empty() {
+        return Err(validator.errors);
+    }
+
+    Config::deserialize(value).map_err(|err| {
+        vec![ConfigError {
+            file: layers
+                .last()
+                .map(|layer| layer.name.clone())
+                .unwrap_or_default(),
+            line: None,
+            message: format!("{}", err),
+        }]
+    })
+}
+
+/// Merge `overlay` into `base`, tables key by key, anything else replaces
+/// the value in `base`
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Table(base), Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Type a git config value the way git does: booleans and integers, anything
+/// else is a string
+fn git_value(value: &str) -> Value {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" => return Value::Boolean(true),
+        "false" | "no" | "off" => return Value::Boolean(false),
+        _ => (),
+    }
+    let digits = value.trim_start_matches('-');
+    if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+        if let Ok(n) = value.parse() {
+            return Value::Integer(n);
+        }
+    }
+    Value::String(value.to_owned())
+}
+
+/// Set the value at a dotted path, creating the tables on the way
+fn insert<'a>(table: &mut Value, mut path: impl Iterator<Item = &'a str>, value: Value) {
+    let name = match path.next() {
+        Some(name) => name,
+        None => {
+            *table = value;
+            return;
+        }
+    };
+
+    if !table.is_table() {
+        *table = Value::Table(Table::new());
+    }
+    if let Value::Table(table) = table {
+        let entry = table
+            .entry(name.to_owned())
+            .or_insert_with(|| Value::Table(Table::new()));
+        insert(entry, path, value);
+    }
+}
+
+struct Validator<'a> {
+    layers: &'a [Layer],
+    errors: Vec<ConfigError>,
+}
+
+impl<'a> Validator<'a> {
+    fn validate(&mut self, value: &Value) {
+        let path = vec!["keymap".to_owned(), "map".to_owned()];
+        match value.get("keymap").and_then(|keymap| keymap.get("map")) {
+            Some(Value::Table(map)) => self.validate_keymap(map, &path),
+            Some(_) => self.error(&path, None, "keymap.map has to be a table".to_owned()),
+            None => self.error(&path, None, "Missing the [keymap.map] table".to_owned()),
+        }
+        self.validate_appearance(value);
+    }
+
+    fn validate_appearance(&mut self, value: &Value) {
+        let mut themes = vec![];
+        match value.get("themes") {
+            Some(Value::Table(table)) => {
+                for (name, palette) in table.iter() {
+                    if self.validate_palette(name, palette) {
+                        themes.push(name.as_str());
+                    }
+                }
+            }
+            Some(_) => {
+                let path = vec!["themes".to_owned()];
+                self.error(&path, None, "themes has to be a table".to_owned());
+            }
+            // Only missing from a config parsed on its own, loaded ones are
+            // merged over the defaults
+            None => (),
+        }
+
+        let path = vec!["appearance".to_owned()];
+        if let Some(appearance) = value.get("appearance") {
+            match AppearanceConfig::deserialize(appearance.clone()) {
+                Ok(appearance) if !themes.contains(&appearance.theme.as_str()) => {
+                    let message = format!("Unknown theme '{}'", appearance.theme);
+                    self.error(&path, Some("theme"), message);
+                }
+                Ok(_) => (),
+                Err(err) => self.error(&path, None, format!("Invalid appearance: {}", err)),
+            }
+        }
+    }
+
+    /// Check the theme `name`, returns whether it can be used
+    fn validate_palette(&mut self, name: &str, palette: &Value) -> bool {
+        let path = vec!["themes".to_owned(), name.to_owned()];
+        let colors = match palette {
+            Value::Table(colors) => colors,
+            _ => {
+                self.error(&path, None, format!("'{}' has to be a table", name));
+                return false;
+            }
+        };
+
+        // Blame bad colors on their own line, serde doesn't tell which one it is
+        let mut valid = true;
+        for (field, color) in colors.iter() {
+            if let Value::String(color) = color {
+                if let Err(err) = Rgb::parse(color) {
+                    self.error(&path, Some(field), format!("{:#}", err));
+                    valid = false;
+                }
+            }
+        }
+        if valid {
+            if let Err(err) = Palette::deserialize(palette.clone()) {
+                self.error(&path, None, format!("Invalid theme: {}", err));
+                valid = false;
+            }
+        }
+        valid
+    }
+
+    fn validate_keymap(&mut self, map: &Table, path: &[String]) {
+        // Different names can stand for the same key, e.g. RET and Enter
+        let mut seen = BTreeMap::<Key, &str>::new();
+
+        for (name, node) in map.iter() {
+            let mut path = path.to_vec();
+            path.push(name.clone());
+
+            let node = match node {
+                Value::Table(node) => node,
+                _ => {
+                    self.error(&path, None, format!("'{}' has to be a table", name));
+                    continue;
+                }
+            };
+
+            let key = match Key::parse(name) {
+                Ok(key) => Some(key),
+                Err(err) => {
+                    self.error(&path, None, format!("{:#}", err));
+                    None
+                }
+            };
+            if let Some(key) = &key {
+                if let Some(other) = seen.insert(key.clone(), name) {
+                    self.error(
+                        &path,
+                        None,
+                        format!("'{}' is the same key as '{}'", name, other),
+                    );
+                }
+            }
+
+            match node.get("key") {
+                Some(Value::String(node_key)) => match Key::parse(node_key) {
+                    Ok(node_key) if key.as_ref().map_or(false, |key| *key != node_key) => {
+                        let message = format!("key = \"{}\" doesn't match '{}'", node_key, name);
+                        self.error(&path, Some("key"), message);
+                    }
+                    Ok(_) => (),
+                    Err(err) => self.error(&path, Some("key"), format!("{:#}", err)),
+                },
+                Some(_) => self.error(&path, Some("key"), "key has to be a string".to_owned()),
+                None => self.error(&path, None, "Missing key".to_owned()),
+            }
+
+            match node.get("name") {
+                Some(Value::String(_)) => (),
+                Some(_) => self.error(&path, Some("name"), "name has to be a string".to_owned()),
+                None => self.error(&path, None, "Missing name".to_owned()),
+            }
+
+            match node.get("command") {
+                Some(Value::String(cmd))
+                    if Command::deserialize(Value::String(cmd.clone())).is_err() =>
+                {
+                    let message = format!("Unknown command '{}'", cmd);
+                    self.error(&path, Some("command"), message);
+                }
+                Some(Value::String(_)) | None => (),
+                Some(_) => {
+                    let message = "command has to be a string".to_owned();
+                    self.error(&path, Some("command"), message);
+                }
+            }
+
+            for field in ["source", "description", "group"].iter() {
+                if node.get(*field).map_or(false, |value| !value.is_str()) {
+                    let message = format!("{} has to be a string", field);
+                    self.error(&path, Some(field), message);
+                }
+            }
+
+            if let Some(args) = node.get("args") {
+                if let Err(err) = CmdArgs::deserialize(args.clone()) {
+                    self.error(&path, Some("args"), format!("Invalid args: {}", err));
+                }
+            }
+
+            match node.get("shell") {
+                Some(Value::String(shell))
+                    if shell::needs_selection(shell)
+                        && node.get("command").is_none()
+                        && node.get("source").is_none() =>
+                {
+                    let message = "shell uses {selection} but there's neither a command nor a \
+                                   source to select from"
+                        .to_owned();
+                    self.error(&path, Some("shell"), message);
+                }
+                Some(Value::String(_)) | None => (),
+                Some(_) => self.error(&path, Some("shell"), "shell has to be a string".to_owned()),
+            }
+
+            match node.get("next") {
+                Some(Value::Table(next)) => {
+                    path.push("next".to_owned());
+                    self.validate_keymap(next, &path);
+                }
+                Some(_) => self.error(&path, Some("next"), "next has to be a table".to_owned()),
+                None if node.get("command").is_none() && node.get("shell").is_none() => self.error(
+                    &path,
+                    None,
+                    "Has neither a command, a shell command nor next keys".to_owned(),
+                ),
+                None => (),
+            }
+        }
+    }
+
+    /// Report a problem with the table at `path`, or its `field`. The problem
+    /// is blamed on the last layer setting the field, or else defining the
+    /// table.
+    fn error(&mut self, path: &[String], field: Option<&str>, message: String) {
+        let find = |field: Option<&str>| {
+            self.layers.iter().rev().find_map(|layer| {
+                let line = find_line(layer.source.as_ref()?, path, field)?;
+                Some((layer.name.clone(), Some(line)))
+            })
+        };
+        let defined = || {
+            self.layers.iter().rev().find_map(|layer| {
+                let mut value = Some(&layer.value);
+                for name in path.iter().map(String::as_str).chain(field) {
+                    value = value?.get(name);
+                }
+                value.map(|_| (layer.name.clone(), None))
+            })
+        };
+
+        let (file, line) = field
+            .and_then(|field| find(Some(field)))
+            .or_else(|| find(None))
+            .or_else(defined)
+            .unwrap_or_else(|| ("config".to_owned(), None));
+        self.errors.push(ConfigError {
+            file,
+            line,
+            message: format!("[{}] {}", path.join("."), message),
+        });
+    }
+}
+
+/// Line of the header of the table at `path`, or of `field` within it
+fn find_line(source: &str, path: &[String], field: Option<&str>) -> Option<usize> {
+    let mut lines = source.lines().enumerate();
+    let header = lines
+        .by_ref()
+        .find(|(_, line)| header_path(line).as_deref() == Some(path))?
+        .0;
+
+    let field = match field {
+        Some(field) => field,
+        None => return Some(header + 1),
+    };
+
+    lines
+        .take_while(|(_, line)| header_path(line).is_none())
+        .find(|(_, line)| {
+            let line = line.trim_start();
+            line.starts_with(field) && line[field.len()..].trim_start().starts_with('=')
+        })
+        .map(|(idx, _)| idx + 1)
+}
+
+/// Names of a `[table.header]` line, unquoted
+fn header_path(line: &str) -> Option<Vec<String>> {
+    let line = line.trim();
+    if !line.starts_with('[') || line.starts_with("[[") || !line.ends_with(']') {
+        return None;
+    }
+
+    let mut names = vec![];
+    let mut name = String::new();
+    let mut quoted = false;
+    for c in line[1..line.len() - 1].chars() {
+        match c {
+            '"' | '\'' => quoted = !quoted,
+            '.' if !quoted => names.push(std::mem::take(&mut name)),
+            c if c.is_whitespace() && !quoted => (),
+            c => name.push(c),
+        }
+    }
+    names.push(name);
+    Some(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests;
+
+    fn lines(source: &str) -> Vec<(Option<usize>, String)> {
+        parse(source, "test.toml")
+            .err()
+            .unwrap()
+            .into_iter()
+            .map(|err| (err.line, err.message))
+            .collect()
+    }
+
+    #[test]
+    fn builtin_config_should_be_valid() {
+        let config = defaults();
+        assert!(!config.keymap.map.is_empty());
+    }
+
+    #[test]
+    fn every_problem_should_be_reported_with_its_line() {
+        let source = r#"
+[keymap.map.b]
+key = "b"
+name = "Branching"
+
+[keymap.map.b.next.c]
+key = "x"
+name = "Checkout"
+command = "BranchChekout"
+
+[keymap.map.b.next.d]
+key = ""
+name = "Delete"
+command = "BranchDelete"
+
+[keymap.map.RET]
+key = "RET"
+name = "Enter"
+
+[keymap.map.Enter]
+key = "Enter"
+command = "Commit"
+
+[keymap.map.b.next.s]
+key = "s"
+name = "Show"
+shell = "git show {selection}"
+"#;
+        let errors = lines(source);
+        let expected = vec![
+            (Some(20), "[keymap.map.Enter] Missing name".to_owned()),
+            (
+                Some(16),
+                "[keymap.map.RET] 'RET' is the same key as 'Enter'".to_owned(),
+            ),
+            (
+                Some(16),
+                "[keymap.map.RET] Has neither a command, a shell command nor next keys".to_owned(),
+            ),
+            (
+                Some(7),
+                "[keymap.map.b.next.c] key = \"x\" doesn't match 'c'".to_owned(),
+            ),
+            (
+                Some(9),
+                "[keymap.map.b.next.c] Unknown command 'BranchChekout'".to_owned(),
+            ),
+            (Some(12), "[keymap.map.b.next.d] Empty key in ''".to_owned()),
+            (
+                Some(27),
+                "[keymap.map.b.next.s] shell uses {selection} but there's neither a command nor \
+                 a source to select from"
+                    .to_owned(),
+            ),
+        ];
+        assert_eq!(errors, expected);
+    }
+
+    #[test]
+    fn args_should_be_checked() {
+        let source = "[keymap.map.p]\nkey = \"p\"\nname = \"Push\"\ncommand = \"Push\"\n\
+                      args = { switches = [\"forse\"] }\n";
+        let errors = lines(source);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, Some(5));
+        assert!(
+            errors[0].1.contains("unknown variant `forse`"),
+            "{}",
+            errors[0].1
+        );
+    }
+
+    #[test]
+    fn syntax_errors_should_have_a_line() {
+        let source = "[keymap.map.b]\nkey = \"b\"\nname = \"Branching\n";
+        let errors = lines(source);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, Some(3));
+    }
+
+    #[test]
+    fn broken_config_should_fall_back_to_defaults() {
+        let (td, repo) = tests::repo_init();
+        let path = td.path().join(CONFIG_FILE);
+        std::fs::write(&path, "[keymap.map.x]\nkey = \"x\"\n").unwrap();
+
+        let (config, errors) = load(&repo, Some(&path));
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0]
+            .to_string()
+            .starts_with(&format!("{}:1: ", path.display())));
+        assert_eq!(config.keymap.map.len(), defaults().keymap.map.len());
+
+        let (_, errors) = load(&repo, Some(&td.path().join("missing.toml")));
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn layers_should_be_merged_key_by_key() {
+        let (td, repo) = tests::repo_init();
+        let user = td.path().join(CONFIG_FILE);
+        let user_config =
+            "[keymap.map.b.next.x]\nkey = \"x\"\nname = \"Tag\"\ncommand = \"TagCheckout\"\n";
+        std::fs::write(&user, user_config).unwrap();
+        let repo_config = "[keymap.map.b.next.x]\nname = \"Checkout tag\"\n";
+        std::fs::write(repo.path().join(REPO_CONFIG_FILE), repo_config).unwrap();
+        repo.config()
+            .unwrap()
+            .set_str("gitools.keymap.map.b.next.b.command", "CommitCheckout")
+            .unwrap();
+
+        let (config, errors) = load(&repo, Some(&user));
+        assert!(errors.is_empty(), "{:?}", errors);
+        let branching = config.keymap.map.get(&Key::parse("b").unwrap()).unwrap();
+        let x = branching.next.get(&Key::parse("x").unwrap()).unwrap();
+        assert_eq!(x.name, "Checkout tag");
+        assert_eq!(x.command, Some(Command::TagCheckout));
+        let b = branching.next.get(&Key::parse("b").unwrap()).unwrap();
+        assert_eq!(b.name, "Checkout");
+        assert_eq!(b.command, Some(Command::CommitCheckout));
+        assert!(branching.next.len() > 2);
+    }
+
+    #[test]
+    fn errors_should_be_blamed_on_the_overriding_layer() {
+        let (td, repo) = tests::repo_init();
+        let path = repo.path().join(REPO_CONFIG_FILE);
+        std::fs::write(&path, "\n[keymap.map.b.next.b]\ncommand = \"Nope\"\n").unwrap();
+        let user = td.path().join(CONFIG_FILE);
+        std::fs::write(&user, "").unwrap();
+
+        let (_, errors) = load(&repo, Some(&user));
+        let expected = ConfigError {
+            file: path.display().to_string(),
+            line: Some(3),
+            message: "[keymap.map.b.next.b] Unknown command 'Nope'".to_owned(),
+        };
+        assert_eq!(errors, vec![expected]);
+    }
+
+    #[test]
+    fn git_config_values_should_be_typed() {
+        let (td, repo) = tests::repo_init();
+        let user = td.path().join(CONFIG_FILE);
+        std::fs::write(&user, "").unwrap();
+        let mut git_config = repo.config().unwrap();
+        let entries = [
+            ("transient.persist", "true"),
+            ("appearance.font.size", "16"),
+            ("keymap.map.b.next.1.key", "1"),
+            ("keymap.map.b.next.1.name", "Checkout"),
+            ("keymap.map.b.next.1.command", "BranchCheckout"),
+        ];
+        for (name, value) in entries.iter() {
+            let name = format!("{}{}", GIT_CONFIG_SECTION, name);
+            git_config.set_str(&name, value).unwrap();
+        }
+
+        let (config, errors) = load(&repo, Some(&user));
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(config.transient.persist);
+        assert_eq!(config.appearance.font.size, 16.0);
+        let branching = config.keymap.map.get(&Key::parse("b").unwrap()).unwrap();
+        assert!(branching.next.contains_key(&Key::parse("1").unwrap()));
+
+        assert_eq!(git_value("On"), Value::Boolean(true));
+        assert_eq!(git_value("no"), Value::Boolean(false));
+        assert_eq!(git_value("-3"), Value::Integer(-3));
+        assert_eq!(git_value("-"), Value::String("-".to_owned()));
+        assert_eq!(git_value("1.5"), Value::String("1.5".to_owned()));
+    }
+
+    #[test]
+    fn themes_should_be_checked() {
+        let (td, repo) = tests::repo_init();
+        let user = td.path().join(CONFIG_FILE);
+        let source = "[appearance]\ntheme = \"solarized\"\n\n[themes.mine]\nbase3 = \"#fff\"\n";
+        std::fs::write(&user, source).unwrap();
+
+        let (_, errors) = load(&repo, Some(&user));
+        let errors = errors
+            .into_iter()
+            .map(|err| (err.line, err.message))
+            .collect::<Vec<_>>();
+        let expected = vec![
+            (
+                Some(5),
+                "[themes.mine] '#fff' isn't a #rrggbb color".to_owned(),
+            ),
+            (Some(2), "[appearance] Unknown theme 'solarized'".to_owned()),
+        ];
+        assert_eq!(errors, expected);
+
+        let source = "[themes.solarized-light]\nbase3 = \"#ffffff\"\n";
+        std::fs::write(&user, source).unwrap();
+        let (config, errors) = load(&repo, Some(&user));
+        assert!(errors.is_empty(), "{:?}", errors);
+        let light = &config.themes["solarized-light"];
+        assert_eq!(light.base3, Rgb(0xff, 0xff, 0xff));
+        assert_eq!(light.base2, Rgb(0xee, 0xe8, 0xd5));
+    }
+}
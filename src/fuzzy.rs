@@ -0,0 +1,307 @@
+//! Scored subsequence matching for the fuzzybar, in the spirit of fzf/skim.
+//!
+//! Every character of the query has to appear in the candidate in order. Among
+//! all the ways the query can be matched, the one with the highest score is
+//! picked: matches on word boundaries, after path separators, on camelCase
+//! humps and runs of consecutive characters score higher, gaps score lower.
+//!
+//! [`exact_match`] scores substring matches the same way, for the exact,
+//! prefix and suffix terms of a [`crate::query::Query`].
+
+use std::cell::RefCell;
+
+const SCORE_MATCH: i64 = 16;
+const GAP_START: i64 = -3;
+const GAP_EXTENSION: i64 = -1;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_PATH_SEP: i64 = 9;
+const BONUS_CAMEL: i64 = 7;
+const BONUS_CONSECUTIVE: i64 = 5;
+const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+/// Buffers of the dynamic programming in [`fuzzy_match`], kept between calls
+/// so filtering thousands of candidates doesn't allocate a grid for each
+#[derive(Default)]
+struct Scratch {
+    bonuses: Vec<i64>,
+    /// `n` rows of `m` columns, see [`best_match`]
+    scores: Vec<Option<i64>>,
+    from: Vec<usize>,
+}
+
+thread_local! {
+    static SCRATCH: RefCell<Scratch> = RefCell::new(Scratch::default());
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+    pub score: i64,
+    /// Char indices of the candidate that matched the query
+    pub positions: Vec<usize>,
+}
+
+/// Where a substring has to be found by [`exact_match`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Anchor {
+    Anywhere,
+    Start,
+    End,
+    /// The whole candidate
+    Both,
+}
+
+/// Match `query` against `candidate`. The match is case-insensitive unless the
+/// query contains an uppercase character (smart-case).
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<Match> {
+    let (query, chars, normalized) = normalize(query, candidate);
+
+    if query.is_empty() {
+        return Some(Match {
+            score: 0,
+            positions: vec![],
+        });
+    }
+
+    // Cheap enough to run on every candidate, unlike the scoring below
+    if !is_subsequence(&query, &normalized) {
+        return None;
+    }
+
+    SCRATCH.with(|scratch| {
+        let scratch = &mut *scratch.borrow_mut();
+        best_match(&query, &chars, &normalized, scratch)
+    })
+}
+
+/// The highest scoring way to match `query`, known to be a subsequence of
+/// `normalized`
+fn best_match(
+    query: &[char],
+    chars: &[char],
+    normalized: &[char],
+    scratch: &mut Scratch,
+) -> Option<Match> {
+    let (n, m) = (query.len(), chars.len());
+    let bonuses = &mut scratch.bonuses;
+    bonuses.clear();
+    bonuses.extend((0..m).map(|j| bonus(if j == 0 { None } else { Some(chars[j - 1]) }, chars[j])));
+
+    // scores[i * m + j] is the best score with query[i] matched at chars[j],
+    // from[i * m + j] the position query[i - 1] was matched at in that case
+    let scores = &mut scratch.scores;
+    scores.clear();
+    scores.resize(n * m, None);
+    let from = &mut scratch.from;
+    from.clear();
+    from.resize(n * m, 0);
+
+    for i in 0..n {
+        // Best score of matching query[i - 1] somewhere before the gap that
+        // ends at j, and where it was matched
+        let mut gap: Option<(i64, usize)> = None;
+
+        for j in 0..m {
+            if i > 0 && j > 1 {
+                if let Some(prev) = scores[(i - 1) * m + j - 2] {
+                    let extended = gap.map(|(score, k)| (score + GAP_EXTENSION, k));
+                    let started = (prev + GAP_START, j - 2);
+                    gap = match extended {
+                        Some(extended) if extended.0 >= started.0 => Some(extended),
+                        _ => Some(started),
+                    };
+                } else if let Some((score, k)) = gap {
+                    gap = Some((score + GAP_EXTENSION, k));
+                }
+            }
+
+            if query[i] != normalized[j] {
+                continue;
+            }
+
+            if i == 0 {
+                scores[j] = Some(SCORE_MATCH + bonuses[j] * BONUS_FIRST_CHAR_MULTIPLIER);
+                continue;
+            }
+
+            let consecutive = if j > 0 {
+                scores[(i - 1) * m + j - 1].map(|prev| (prev + BONUS_CONSECUTIVE, j - 1))
+            } else {
+                None
+            };
+
+            let best = match (consecutive, gap) {
+                (Some(c), Some(g)) if g.0 > c.0 => Some(g),
+                (Some(c), _) => Some(c),
+                (None, g) => g,
+            };
+
+            if let Some((prev, k)) = best {
+                scores[i * m + j] = Some(prev + SCORE_MATCH + bonuses[j]);
+                from[i * m + j] = k;
+            }
+        }
+    }
+
+    let (score, end) = scores[(n - 1) * m..]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, score)| score.map(|score| (score, j)))
+        .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))?;
+
+    let mut positions = vec![end; n];
+    for i in (1..n).rev() {
+        positions[i - 1] = from[i * m + positions[i]];
+    }
+
+    Some(Match { score, positions })
+}
+
+/// Match `query` as a substring of `candidate`, smart-case like
+/// [`fuzzy_match`]. Of several occurrences the one starting on the best
+/// boundary is picked.
+pub fn exact_match(query: &str, candidate: &str, anchor: Anchor) -> Option<Match> {
+    let (query, chars, normalized) = normalize(query, candidate);
+    let (n, m) = (query.len(), chars.len());
+    if n == 0 {
+        return Some(Match {
+            score: 0,
+            positions: vec![],
+        });
+    }
+    if n > m {
+        return None;
+    }
+
+    let starts: Vec<usize> = match anchor {
+        Anchor::Anywhere => (0..=m - n).collect(),
+        Anchor::Start => vec![0],
+        Anchor::End => vec![m - n],
+        Anchor::Both if n == m => vec![0],
+        Anchor::Both => vec![],
+    };
+
+    let bonus_at = |j: usize| bonus(if j == 0 { None } else { Some(chars[j - 1]) }, chars[j]);
+    let start = starts
+        .into_iter()
+        .filter(|&start| normalized[start..start + n] == query[..])
+        .max_by(|a, b| bonus_at(*a).cmp(&bonus_at(*b)).then(b.cmp(a)))?;
+
+    let score = SCORE_MATCH * n as i64
+        + BONUS_CONSECUTIVE * (n as i64 - 1)
+        + bonus_at(start) * BONUS_FIRST_CHAR_MULTIPLIER;
+
+    Some(Match {
+        score,
+        positions: (start..start + n).collect(),
+    })
+}
+
+/// Split query and candidate into chars, lowercased unless the query has an
+/// uppercase character. Returns the query, the candidate as is and the
+/// candidate normalized.
+fn normalize(query: &str, candidate: &str) -> (Vec<char>, Vec<char>, Vec<char>) {
+    let case_sensitive = query.chars().any(|c| c.is_uppercase());
+    let normalize = |c: char| {
+        if case_sensitive {
+            c
+        } else {
+            c.to_lowercase().next().unwrap_or(c)
+        }
+    };
+
+    let query = query.chars().map(normalize).collect();
+    let chars: Vec<char> = candidate.chars().collect();
+    let normalized = chars.iter().map(|c| normalize(*c)).collect();
+    (query, chars, normalized)
+}
+
+fn is_subsequence(query: &[char], chars: &[char]) -> bool {
+    let mut query = query.iter().peekable();
+    for c in chars {
+        if query.peek() == Some(&c) {
+            query.next();
+        }
+    }
+    query.peek().is_none()
+}
+
+fn bonus(prev: Option<char>, cur: char) -> i64 {
+    match prev {
+        None => BONUS_BOUNDARY,
+        Some('/') | Some('\\') => BONUS_PATH_SEP,
+        Some(c) if c.is_whitespace() || "-_.:".contains(c) => BONUS_BOUNDARY,
+        Some(c) if c.is_lowercase() && cur.is_uppercase() => BONUS_CAMEL,
+        Some(c) if !c.is_numeric() && cur.is_numeric() => BONUS_CAMEL,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_match_subsequence_across_separators() {
+        let m = fuzzy_match("fbar", "feature/bar").unwrap();
+        assert_eq!(m.positions, vec![0, 8, 9, 10]);
+    }
+
+    #[test]
+    fn should_not_match_out_of_order() {
+        assert_eq!(fuzzy_match("rab", "feature/bar"), None);
+    }
+
+    #[test]
+    fn should_prefer_boundaries_and_consecutive_chars() {
+        let boundary = fuzzy_match("fb", "feature/bar").unwrap();
+        let inner = fuzzy_match("fb", "fxxxxxxxbar").unwrap();
+        assert!(boundary.score > inner.score);
+
+        let consecutive = fuzzy_match("bar", "xbar").unwrap();
+        let scattered = fuzzy_match("bar", "xbxaxr").unwrap();
+        assert!(consecutive.score > scattered.score);
+
+        let camel = fuzzy_match("fb", "fooBar").unwrap();
+        let plain = fuzzy_match("fb", "foobar").unwrap();
+        assert!(camel.score > plain.score);
+    }
+
+    #[test]
+    fn should_pick_best_positions() {
+        // The first 'b' isn't on a boundary, the second one is
+        let m = fuzzy_match("fb", "fabric/bar").unwrap();
+        assert_eq!(m.positions, vec![0, 7]);
+    }
+
+    #[test]
+    fn buffers_should_be_reset_between_candidates() {
+        let short = fuzzy_match("fb", "fabric/bar").unwrap();
+        let _ = fuzzy_match("fb", "src/widgets/fuzzybar.rs and a long tail");
+        assert_eq!(fuzzy_match("fb", "fabric/bar").unwrap(), short);
+    }
+
+    #[test]
+    fn should_use_smart_case() {
+        assert!(fuzzy_match("bar", "feature/BAR").is_some());
+        assert!(fuzzy_match("Bar", "feature/bar").is_none());
+        assert!(fuzzy_match("Bar", "feature/Bar").is_some());
+    }
+
+    #[test]
+    fn exact_match_should_respect_anchors() {
+        let m = exact_match("bar", "bar/foobar", Anchor::Anywhere).unwrap();
+        assert_eq!(m.positions, vec![0, 1, 2]);
+        let m = exact_match("bar", "foobar/bar", Anchor::Anywhere).unwrap();
+        assert_eq!(m.positions, vec![7, 8, 9]);
+
+        assert!(exact_match("foo", "foobar", Anchor::Start).is_some());
+        assert!(exact_match("bar", "foobar", Anchor::Start).is_none());
+        assert_eq!(
+            exact_match("bar", "foobar", Anchor::End).unwrap().positions,
+            vec![3, 4, 5]
+        );
+        assert!(exact_match("foobar", "FooBar", Anchor::Both).is_some());
+        assert!(exact_match("foo", "foobar", Anchor::Both).is_none());
+        assert!(exact_match("fbr", "foobar", Anchor::Anywhere).is_none());
+    }
+}
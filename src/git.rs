@@ -1,14 +1,20 @@
-use crate::state::{AppState, Command};
+use crate::state::{AppState, CmdArgs, Command, Switch};
 use crate::theme;
 use anyhow::{anyhow, Context, Result};
+use chrono::{Local, TimeZone};
 use druid::widget::{Align, Container, CrossAxisAlignment, Flex, Label, SizedBox};
 use druid::{Data, Env, Widget, WidgetExt};
 use git2::{
-    BranchType, DescribeFormatOptions, DescribeOptions, Reference, Repository, Status,
-    StatusOptions, Statuses,
+    AutotagOption, BranchType, Commit, Cred, CredentialType, DescribeFormatOptions,
+    DescribeOptions, Diff, DiffFindOptions, DiffFormat, DiffOptions, DiffStatsFormat, Direction,
+    FetchOptions, FetchPrune, IndexAddOption, Oid, PushOptions, Reference, RemoteCallbacks,
+    Repository, ResetType, Revwalk, Sort, Status, StatusOptions, Statuses, SubmoduleIgnore,
+    SubmoduleStatus, Worktree, WorktreeAddOptions, WorktreeLockStatus,
 };
 use im::{vector, Vector};
-use log::{debug, info};
+use log::{debug, error, info};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 pub fn get_commit_from_ref(repo: &Repository, reference: &Reference) -> Result<(String, String)> {
     let oid = reference.target().ok_or(anyhow!("No oid on ref"))?;
@@ -25,6 +31,14 @@ pub fn get_commit_from_ref(repo: &Repository, reference: &Reference) -> Result<(
     Ok((format!("{}", oid), msg.to_owned()))
 }
 
+/// Format a commit/signature time in the local timezone
+pub fn format_time(time: &git2::Time) -> String {
+    Local
+        .timestamp(time.seconds(), 0)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
 pub fn get_latest_tag(repo: &Repository) -> String {
     let mut opts = DescribeOptions::new();
     let opts = opts.describe_tags();
@@ -69,20 +83,1415 @@ pub fn get_statuses(repo: &Repository) -> Result<Statuses> {
         .context("Failed to get status")
 }
 
+/// Paths of all files tracked in the index
+pub fn get_tracked_files(repo: &Repository) -> Vector<String> {
+    let index = repo.index();
+    if let Err(err) = index {
+        info!("Couldn't read index: {}", err);
+        return vector![];
+    }
+
+    index
+        .unwrap()
+        .iter()
+        .filter_map(|entry| String::from_utf8(entry.path).ok())
+        .collect()
+}
+
+/// A commit that changed a file. `path` is the name the file had in that
+/// commit and `old_path` is set when the commit renamed it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileCommit {
+    pub oid: Oid,
+    pub path: String,
+    pub old_path: Option<String>,
+}
+
+/// Walk the history from HEAD and collect every commit that changed `path`,
+/// following the file across renames like `git log --follow`.
+///
+/// Like `git log`, a merge is only listed if the file differs from every one
+/// of its parents, otherwise its changes are those of the merged commits. A
+/// single name is followed though: past a commit that renamed the file, the
+/// commits of other branches only count under the old name.
+pub fn get_file_history(repo: &Repository, path: &str) -> Result<Vec<FileCommit>> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push_head()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+
+    let mut path = path.to_owned();
+    let mut history = vec![];
+
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        let tree = commit.tree()?;
+        let entry_id = tree.get_path(Path::new(&path)).ok().map(|e| e.id());
+
+        let parent_trees = commit
+            .parents()
+            .map(|parent| parent.tree())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let parent_ids = parent_trees
+            .iter()
+            .map(|t| t.get_path(Path::new(&path)).ok().map(|e| e.id()))
+            .collect::<Vec<_>>();
+
+        if entry_id.is_none() || parent_ids.contains(&entry_id) {
+            continue;
+        }
+
+        if parent_ids.iter().any(Option::is_some) || parent_trees.is_empty() {
+            history.push(FileCommit {
+                oid: commit.id(),
+                path: path.clone(),
+                old_path: None,
+            });
+            continue;
+        }
+
+        // The file appeared in this commit, find out if it was renamed
+        let mut diff = repo.diff_tree_to_tree(parent_trees.first(), Some(&tree), None)?;
+        let mut find_opts = DiffFindOptions::new();
+        diff.find_similar(Some(find_opts.renames(true)))?;
+
+        let old_path = diff
+            .deltas()
+            .filter(|d| d.new_file().path() == Some(Path::new(&path)))
+            .filter_map(|d| d.old_file().path())
+            .filter_map(|p| p.to_str())
+            .find(|p| *p != path)
+            .map(|p| p.to_owned());
+
+        history.push(FileCommit {
+            oid: commit.id(),
+            path: path.clone(),
+            old_path: old_path.clone(),
+        });
+
+        match old_path {
+            Some(old_path) => path = old_path,
+            None => break,
+        }
+    }
+
+    Ok(history)
+}
+
+/// Patch of a single commit restricted to one file
+pub fn get_file_patch(repo: &Repository, file_commit: &FileCommit) -> Result<String> {
+    let commit = repo.find_commit(file_commit.oid)?;
+    let tree = commit.tree()?;
+    let parent_tree = match commit.parents().next() {
+        Some(parent) => Some(parent.tree()?),
+        None => None,
+    };
+
+    let mut diff_opts = DiffOptions::new();
+    diff_opts.pathspec(&file_commit.path);
+    if let Some(old_path) = &file_commit.old_path {
+        diff_opts.pathspec(old_path);
+    }
+
+    let mut diff =
+        repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(&mut diff_opts))?;
+    let mut find_opts = DiffFindOptions::new();
+    diff.find_similar(Some(find_opts.renames(true)))?;
+
+    diff_to_patch(&diff)
+}
+
+/// Patch of a commit against its first parent
+pub fn get_commit_patch(repo: &Repository, oid: Oid) -> Result<String> {
+    let commit = repo.find_commit(oid)?;
+    let tree = commit.tree()?;
+    let parent_tree = match commit.parents().next() {
+        Some(parent) => Some(parent.tree()?),
+        None => None,
+    };
+
+    let mut diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?;
+    let mut find_opts = DiffFindOptions::new();
+    diff.find_similar(Some(find_opts.renames(true)))?;
+
+    diff_to_patch(&diff)
+}
+
+fn diff_to_patch(diff: &Diff) -> Result<String> {
+    let mut patch = String::new();
+    diff.print(DiffFormat::Patch, |_delta, _hunk, line| {
+        if let '+' | '-' | ' ' = line.origin() {
+            patch.push(line.origin());
+        }
+        patch.push_str(&String::from_utf8_lossy(line.content()));
+        true
+    })
+    .context("Failed to print patch")?;
+
+    Ok(patch)
+}
+
+/// Oids of the latest `limit` commits reachable from HEAD
+pub fn get_log(repo: &Repository, limit: usize) -> Result<Vec<Oid>> {
+    walk_log(repo, false)?
+        .take(limit)
+        .map(|oid| oid.context("Failed to walk history"))
+        .collect()
+}
+
+/// A single reflog entry, newest entries come first
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReflogItem {
+    pub old: Oid,
+    pub new: Oid,
+    pub message: String,
+    pub time: git2::Time,
+}
+
+/// Read the reflog of `name`, which is either `HEAD` or a local branch
+pub fn get_reflog(repo: &Repository, name: &str) -> Result<Vec<ReflogItem>> {
+    let refname = if name == "HEAD" {
+        name.to_owned()
+    } else {
+        "refs/heads/".to_owned() + name
+    };
+
+    let reflog = repo
+        .reflog(&refname)
+        .with_context(|| format!("Failed to read reflog of {}", refname))?;
+
+    Ok(reflog
+        .iter()
+        .map(|entry| ReflogItem {
+            old: entry.id_old(),
+            new: entry.id_new(),
+            message: entry.message().unwrap_or_default().to_owned(),
+            time: entry.committer().when(),
+        })
+        .collect())
+}
+
+/// Check out a commit, leaving HEAD detached
+pub fn checkout_commit(repo: &Repository, oid: Oid) -> Result<()> {
+    debug!("Checking out {}", oid);
+    let commit = repo.find_commit(oid)?;
+    repo.checkout_tree(commit.as_object(), None)
+        .context("Failed to checkout tree")?;
+    repo.set_head_detached(oid)?;
+    Ok(())
+}
+
+pub fn create_branch_at(repo: &Repository, name: &str, oid: Oid) -> Result<()> {
+    debug!("Creating branch {} at {}", name, oid);
+    let commit = repo.find_commit(oid)?;
+    repo.branch(name, &commit, false)
+        .with_context(|| format!("Failed to create branch {}", name))?;
+    Ok(())
+}
+
+/// Reset the current branch to a commit
+pub fn reset_to(repo: &Repository, oid: Oid, kind: ResetType) -> Result<()> {
+    debug!("Resetting to {}", oid);
+    let commit = repo.find_commit(oid)?;
+    repo.reset(commit.as_object(), kind, None)
+        .context("Failed to reset")
+}
+
+/// Check out a reference by its full name and point HEAD at it
+pub fn checkout_ref(repo: &Repository, refname: &str) -> Result<()> {
+    debug!("Checking out {}", refname);
+    let reference = repo.find_reference(refname)?;
+    let commit = reference.peel_to_commit()?;
+    repo.checkout_tree(commit.as_object(), None)
+        .context("Failed to checkout tree")?;
+    repo.set_head(refname)?;
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BisectStep {
+    /// Test `oid` next, there are `remaining` commits that could be the first bad one
+    Next { oid: Oid, remaining: usize },
+    /// The first bad commit
+    Found(Oid),
+    /// Only skipped commits are left, the first bad commit is one of these
+    Inconclusive(Vec<Oid>),
+}
+
+/// Pick the next commit to test when bisecting between `bad` and `good`.
+///
+/// Like `git bisect` the candidate chosen is the one that splits the commits
+/// that are reachable from `bad` but not from `good` closest to half.
+pub fn bisect_next(
+    repo: &Repository,
+    bad: Oid,
+    good: &[Oid],
+    skipped: &[Oid],
+) -> Result<BisectStep> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(bad)?;
+    for oid in good {
+        revwalk.hide(*oid)?;
+    }
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+
+    let candidates = revwalk.collect::<std::result::Result<Vec<Oid>, _>>()?;
+    let index: HashMap<Oid, usize> = candidates
+        .iter()
+        .enumerate()
+        .map(|(idx, oid)| (*oid, idx))
+        .collect();
+
+    // Ancestors of every candidate within the candidates, as bitsets. Parents
+    // come first in the reversed topological order.
+    let words = (candidates.len() + 63) / 64;
+    let mut ancestors: Vec<Vec<u64>> = Vec::with_capacity(candidates.len());
+    for (idx, oid) in candidates.iter().enumerate() {
+        let mut bits = vec![0u64; words];
+        bits[idx / 64] |= 1 << (idx % 64);
+        for parent in repo.find_commit(*oid)?.parent_ids() {
+            if let Some(parent_idx) = index.get(&parent) {
+                for (word, parent_word) in bits.iter_mut().zip(&ancestors[*parent_idx]) {
+                    *word |= parent_word;
+                }
+            }
+        }
+        ancestors.push(bits);
+    }
+
+    let total = candidates.len();
+    let next = candidates
+        .iter()
+        .zip(&ancestors)
+        .filter(|(oid, _)| **oid != bad && !skipped.contains(oid))
+        .map(|(oid, bits)| {
+            let weight = bits.iter().map(|w| w.count_ones() as usize).sum::<usize>();
+            (*oid, weight.min(total - weight))
+        })
+        .max_by_key(|(_, distance)| *distance);
+
+    match next {
+        // `bad` is reachable from a good commit
+        _ if total == 0 => Err(anyhow!(
+            "The bad commit {} is an ancestor of a good one, are the marks swapped?",
+            bad
+        )),
+        _ if total == 1 => Ok(BisectStep::Found(bad)),
+        Some((oid, _)) => Ok(BisectStep::Next {
+            oid,
+            remaining: total,
+        }),
+        None => Ok(BisectStep::Inconclusive(candidates)),
+    }
+}
+
+/// Rough number of bisect steps left for `remaining` candidates
+pub fn bisect_steps(remaining: usize) -> usize {
+    let mut steps = 0;
+    while (1 << steps) < remaining {
+        steps += 1;
+    }
+    steps
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorktreeInfo {
+    /// `None` for the main worktree
+    pub name: Option<String>,
+    pub path: PathBuf,
+    pub head: String,
+    pub locked: bool,
+    pub prunable: bool,
+}
+
+/// Name of the linked worktree `repo` was opened from, `None` for the main worktree
+pub fn get_worktree_name(repo: &Repository) -> Option<String> {
+    if !repo.is_worktree() {
+        return None;
+    }
+
+    Worktree::open_from_repository(repo)
+        .ok()
+        .and_then(|worktree| worktree.name().map(|name| name.to_owned()))
+}
+
+/// Working directory of the main worktree, also when `repo` is a linked worktree
+pub fn get_main_workdir(repo: &Repository) -> Option<PathBuf> {
+    if !repo.is_worktree() {
+        return repo.workdir().map(|path| path.to_owned());
+    }
+
+    // A linked worktree's git dir is <main>/.git/worktrees/<name>
+    repo.path().ancestors().nth(3).map(|path| path.to_owned())
+}
+
+/// The main worktree followed by all linked worktrees
+pub fn get_worktrees(repo: &Repository) -> Result<Vec<WorktreeInfo>> {
+    let mut worktrees = vec![];
+
+    if let Some(path) = get_main_workdir(repo) {
+        let head = Repository::open(&path)
+            .ok()
+            .and_then(|main| head_shorthand(&main))
+            .unwrap_or_default();
+        worktrees.push(WorktreeInfo {
+            name: None,
+            path,
+            head,
+            locked: false,
+            prunable: false,
+        });
+    }
+
+    for name in repo.worktrees()?.iter().flatten() {
+        let worktree = repo.find_worktree(name)?;
+        let head = Repository::open_from_worktree(&worktree)
+            .ok()
+            .and_then(|linked| head_shorthand(&linked))
+            .unwrap_or_default();
+
+        worktrees.push(WorktreeInfo {
+            name: Some(name.to_owned()),
+            path: worktree.path().to_owned(),
+            head,
+            locked: match worktree.is_locked()? {
+                WorktreeLockStatus::Locked(_) => true,
+                WorktreeLockStatus::Unlocked => false,
+            },
+            prunable: worktree.is_prunable(None)?,
+        });
+    }
+
+    Ok(worktrees)
+}
+
+fn head_shorthand(repo: &Repository) -> Option<String> {
+    repo.head()
+        .ok()
+        .and_then(|head| head.shorthand().map(|name| name.to_owned()))
+}
+
+/// Add a worktree next to the main worktree with `branch` checked out. A
+/// remote branch gets a local tracking branch first, like `git worktree add` does.
+pub fn add_worktree(repo: &Repository, branch: &str) -> Result<PathBuf> {
+    let local = match repo.find_branch(branch, BranchType::Local) {
+        Ok(local) => local,
+        Err(_) => {
+            let remote = repo.find_branch(branch, BranchType::Remote)?;
+            let commit = remote.get().peel_to_commit()?;
+            let local_name = branch.splitn(2, '/').nth(1).unwrap_or(branch);
+            let mut local = repo.branch(local_name, &commit, false)?;
+            local.set_upstream(Some(branch))?;
+            local
+        }
+    };
+
+    let local_name = local.name()?.ok_or(anyhow!("Branch name isn't utf-8"))?;
+    let name = local_name.replace('/', "-");
+    let main = get_main_workdir(repo).ok_or(anyhow!("Bare repositories have no worktrees"))?;
+    let dir_name = format!(
+        "{}-{}",
+        main.file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("worktree"),
+        name
+    );
+    let path = main.with_file_name(dir_name);
+
+    debug!("Adding worktree {} at {:?}", name, path);
+    let reference = local.into_reference();
+    let mut opts = WorktreeAddOptions::new();
+    opts.reference(Some(&reference));
+    repo.worktree(&name, &path, Some(&opts))
+        .with_context(|| format!("Failed to add worktree {}", name))?;
+
+    Ok(path)
+}
+
+pub fn set_worktree_locked(repo: &Repository, name: &str, locked: bool) -> Result<()> {
+    let worktree = repo.find_worktree(name)?;
+    if locked {
+        worktree.lock(None)?;
+    } else {
+        worktree.unlock()?;
+    }
+    Ok(())
+}
+
+/// Prune worktrees whose working directory is gone. Returns how many were pruned.
+pub fn prune_worktrees(repo: &Repository) -> Result<usize> {
+    let mut pruned = 0;
+    for name in repo.worktrees()?.iter().flatten() {
+        let worktree = repo.find_worktree(name)?;
+        if worktree.is_prunable(None)? {
+            debug!("Pruning worktree {}", name);
+            worktree.prune(None)?;
+            pruned += 1;
+        }
+    }
+    Ok(pruned)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubmoduleInfo {
+    pub name: String,
+    pub path: String,
+    /// Commit recorded in HEAD of the parent repository
+    pub recorded: Option<Oid>,
+    /// Commit checked out in the submodule's working directory
+    pub checked_out: Option<Oid>,
+    pub initialized: bool,
+    pub dirty: bool,
+}
+
+pub fn get_submodules(repo: &Repository) -> Result<Vec<SubmoduleInfo>> {
+    let mut submodules = vec![];
+    for submodule in repo.submodules()? {
+        let name = submodule.name().unwrap_or_default().to_owned();
+        let status = repo.submodule_status(&name, SubmoduleIgnore::None)?;
+
+        submodules.push(SubmoduleInfo {
+            path: submodule.path().display().to_string(),
+            recorded: submodule.head_id(),
+            checked_out: submodule.workdir_id(),
+            initialized: status.contains(SubmoduleStatus::IN_CONFIG)
+                && !status.contains(SubmoduleStatus::WD_UNINITIALIZED),
+            dirty: status.intersects(
+                SubmoduleStatus::WD_INDEX_MODIFIED
+                    | SubmoduleStatus::WD_WD_MODIFIED
+                    | SubmoduleStatus::WD_UNTRACKED,
+            ),
+            name,
+        });
+    }
+    Ok(submodules)
+}
+
+/// Register the url of every submodule in the repository config
+pub fn init_submodules(repo: &Repository) -> Result<()> {
+    for mut submodule in repo.submodules()? {
+        debug!("Initializing submodule {:?}", submodule.name());
+        submodule.init(false)?;
+    }
+    Ok(())
+}
+
+/// Clone missing submodules and check out the recorded commits, recursing into
+/// nested submodules
+pub fn update_submodules(repo: &Repository) -> Result<()> {
+    for mut submodule in repo.submodules()? {
+        debug!("Updating submodule {:?}", submodule.name());
+        submodule
+            .update(true, None)
+            .with_context(|| format!("Failed to update {:?}", submodule.name()))?;
+        update_submodules(&submodule.open()?)?;
+    }
+    Ok(())
+}
+
+/// Copy submodule urls from .gitmodules to the repository config
+pub fn sync_submodules(repo: &Repository) -> Result<()> {
+    for mut submodule in repo.submodules()? {
+        debug!("Syncing submodule {:?}", submodule.name());
+        submodule.sync()?;
+    }
+    Ok(())
+}
+
+/// Open the repository of the submodule at `path`
+pub fn open_submodule(repo: &Repository, path: &str) -> Result<Repository> {
+    let submodule = repo
+        .submodules()?
+        .into_iter()
+        .find(|submodule| submodule.path() == Path::new(path))
+        .ok_or(anyhow!("No submodule at {}", path))?;
+    submodule
+        .open()
+        .with_context(|| format!("Failed to open submodule {}", path))
+}
+
+pub fn get_tags(repo: &Repository) -> Vector<String> {
+    match repo.tag_names(None) {
+        Ok(tags) => tags.iter().flatten().map(|tag| tag.to_owned()).collect(),
+        Err(err) => {
+            info!("No tags in repo: {}", err);
+            vector![]
+        }
+    }
+}
+
+/// Stashes formatted like `git stash list`, e.g. `stash@{0}: On master: wip`
+pub fn get_stashes(repo: &Repository) -> Vector<String> {
+    // Listing stashes needs a mutable repository
+    let mut repo = match Repository::open(repo.path()) {
+        Ok(repo) => repo,
+        Err(err) => {
+            error!("Failed to open repository: {}", err);
+            return vector![];
+        }
+    };
+
+    let mut stashes = vector![];
+    let result = repo.stash_foreach(|index, message, _oid| {
+        stashes.push_back(format!("stash@{{{}}}: {}", index, message));
+        true
+    });
+    if let Err(err) = result {
+        error!("Failed to list stashes: {}", err);
+    }
+    stashes
+}
+
+/// Index of a stash formatted by [`get_stashes`]
+pub fn parse_stash_index(stash: &str) -> Option<usize> {
+    let start = stash.find("stash@{")? + "stash@{".len();
+    let end = start + stash[start..].find('}')?;
+    stash[start..end].parse().ok()
+}
+
+/// Walk the history of HEAD, or with `unmerged` the commits on other branches
+/// that aren't on HEAD, newest first
+pub fn walk_log(repo: &Repository, unmerged: bool) -> Result<Revwalk> {
+    let mut revwalk = repo.revwalk()?;
+    if unmerged {
+        revwalk.push_glob("refs/heads")?;
+        revwalk.push_glob("refs/remotes")?;
+        revwalk.hide_head()?;
+    } else {
+        revwalk.push_head()?;
+    }
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    Ok(revwalk)
+}
+
+/// A commit formatted as `<short oid> <summary>`
+pub fn commit_line(commit: &Commit) -> String {
+    format!(
+        "{} {}",
+        &format!("{}", commit.id())[..7],
+        commit.summary().unwrap_or_default()
+    )
+}
+
+/// Names and urls of the remotes
+pub fn get_remotes(repo: &Repository) -> Result<Vec<(String, String)>> {
+    let names = repo.remotes()?;
+    names
+        .iter()
+        .flatten()
+        .map(|name| {
+            let remote = repo.find_remote(name)?;
+            Ok((name.to_owned(), remote.url().unwrap_or_default().to_owned()))
+        })
+        .collect()
+}
+
+/// Paths of untracked files and files with unstaged changes
+pub fn get_unstaged_files(repo: &Repository) -> Vector<String> {
+    let unstaged = Status::WT_NEW
+        | Status::WT_MODIFIED
+        | Status::WT_DELETED
+        | Status::WT_RENAMED
+        | Status::WT_TYPECHANGE;
+
+    match get_statuses(repo) {
+        Ok(statuses) => statuses
+            .iter()
+            .filter(|entry| entry.status().intersects(unstaged))
+            .filter_map(|entry| entry.path().map(|path| path.to_owned()))
+            .collect(),
+        Err(err) => {
+            error!("{:#}", err);
+            vector![]
+        }
+    }
+}
+
+/// Resolve a commit from anything `git rev-parse` understands. Only the first
+/// word is used, so lines from [`commit_line`] can be passed as is.
+pub fn resolve_commit(repo: &Repository, spec: &str) -> Result<Oid> {
+    let spec = spec.split_whitespace().next().unwrap_or_default();
+    let commit = repo
+        .revparse_single(spec)
+        .with_context(|| format!("Unknown revision {}", spec))?
+        .peel_to_commit()?;
+    Ok(commit.id())
+}
+
+/// Recent commits of a branch and how far it is ahead/behind HEAD and its upstream
+pub fn get_branch_summary(repo: &Repository, name: &str, limit: usize) -> Result<Vec<String>> {
+    let branch = repo
+        .find_branch(name, BranchType::Local)
+        .or_else(|_| repo.find_branch(name, BranchType::Remote))?;
+    let oid = branch.get().peel_to_commit()?.id();
+
+    let mut lines = vec![];
+    if let Ok(head) = repo.head().and_then(|head| head.peel_to_commit()) {
+        let (ahead, behind) = repo.graph_ahead_behind(oid, head.id())?;
+        lines.push(format!("{} ahead, {} behind HEAD", ahead, behind));
+    }
+    if let Ok(upstream) = branch.upstream() {
+        let upstream_oid = upstream.get().peel_to_commit()?.id();
+        let (ahead, behind) = repo.graph_ahead_behind(oid, upstream_oid)?;
+        lines.push(format!(
+            "{} ahead, {} behind {}",
+            ahead,
+            behind,
+            upstream.name()?.unwrap_or_default()
+        ));
+    }
+    lines.push("".to_owned());
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(oid)?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    for oid in revwalk.take(limit) {
+        let commit = repo.find_commit(oid?)?;
+        lines.push(format!(
+            "{} {}  {}",
+            &format!("{}", commit.id())[..7],
+            format_time(&commit.time()),
+            commit.summary().unwrap_or_default()
+        ));
+    }
+
+    Ok(lines)
+}
+
+/// Diffstat of a stash against the commit it was created on
+pub fn get_stash_diffstat(repo: &Repository, index: usize) -> Result<String> {
+    let mut stash_oid = None;
+    Repository::open(repo.path())?.stash_foreach(|idx, _message, oid| {
+        if idx == index {
+            stash_oid = Some(*oid);
+        }
+        stash_oid.is_none()
+    })?;
+
+    let stash = repo.find_commit(stash_oid.ok_or(anyhow!("No stash@{{{}}}", index))?)?;
+    let base = stash.parent(0)?;
+    let diff = repo.diff_tree_to_tree(Some(&base.tree()?), Some(&stash.tree()?), None)?;
+    let stats = diff.stats()?.to_buf(DiffStatsFormat::FULL, 80)?;
+
+    Ok(stats.as_str().unwrap_or_default().to_owned())
+}
+
+pub fn apply_stash(repo: &Repository, stash: &str) -> Result<()> {
+    let index = parse_stash_index(stash).ok_or(anyhow!("Not a stash: {}", stash))?;
+    debug!("Applying stash@{{{}}}", index);
+    Repository::open(repo.path())?
+        .stash_apply(index, None)
+        .with_context(|| format!("Failed to apply stash@{{{}}}", index))
+}
+
+/// Authenticate through the ssh agent or the git credential helper
+fn auth_callbacks(config: &git2::Config) -> RemoteCallbacks<'_> {
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        if allowed.contains(CredentialType::SSH_KEY) {
+            Cred::ssh_key_from_agent(username.unwrap_or("git"))
+        } else if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            Cred::credential_helper(config, url, username)
+        } else {
+            Cred::default()
+        }
+    });
+    callbacks
+}
+
+/// Fetch a remote with its configured refspecs. Understands the
+/// [`Switch::Prune`] and [`Switch::Tags`] switches.
+pub fn fetch_remote(repo: &Repository, name: &str, args: &CmdArgs) -> Result<()> {
+    debug!("Fetching {}", name);
+    let config = repo.config()?;
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(auth_callbacks(&config));
+    if args.has(Switch::Prune) {
+        options.prune(FetchPrune::On);
+    }
+    if args.has(Switch::Tags) {
+        options.download_tags(AutotagOption::All);
+    }
+    repo.find_remote(name)?
+        .fetch(&[] as &[&str], Some(&mut options), None)
+        .with_context(|| format!("Failed to fetch {}", name))
+}
+
+/// Push the checked out branch to the branch of the same name on a remote.
+/// Understands the [`Switch::Force`], [`Switch::ForceWithLease`] and
+/// [`Switch::SetUpstream`] switches.
+pub fn push_branch(repo: &Repository, remote_name: &str, args: &CmdArgs) -> Result<()> {
+    let head = repo.head()?;
+    if !head.is_branch() {
+        return Err(anyhow!("Can't push a detached HEAD"));
+    }
+    let branch = head
+        .shorthand()
+        .ok_or(anyhow!("Branch name isn't valid utf-8"))?
+        .to_owned();
+    let refname = format!("refs/heads/{}", branch);
+    debug!("Pushing {} to {}", branch, remote_name);
+
+    let config = repo.config()?;
+    let mut remote = repo.find_remote(remote_name)?;
+
+    let force = args.has(Switch::Force) || args.has(Switch::ForceWithLease);
+    if args.has(Switch::ForceWithLease) && !args.has(Switch::Force) {
+        // Only overwrite what was seen at the last fetch
+        let tracking = format!("refs/remotes/{}/{}", remote_name, branch);
+        let expected = repo.refname_to_id(&tracking).ok();
+        let actual = {
+            let connection =
+                remote.connect_auth(Direction::Fetch, Some(auth_callbacks(&config)), None)?;
+            let heads = connection.list()?;
+            heads
+                .iter()
+                .find(|head| head.name() == refname)
+                .map(|head| head.oid())
+        };
+        if actual.is_some() && actual != expected {
+            return Err(anyhow!(
+                "{} changed on {} since it was last fetched",
+                branch,
+                remote_name
+            ));
+        }
+    }
+
+    let mut rejection = None;
+    let mut callbacks = auth_callbacks(&config);
+    callbacks.push_update_reference(|_, status| {
+        rejection = status.map(str::to_owned);
+        Ok(())
+    });
+    let mut options = PushOptions::new();
+    options.remote_callbacks(callbacks);
+
+    let refspec = format!("{}{}:{}", if force { "+" } else { "" }, refname, refname);
+    remote
+        .push(&[refspec.as_str()], Some(&mut options))
+        .with_context(|| format!("Failed to push {} to {}", branch, remote_name))?;
+    drop(options);
+    if let Some(reason) = rejection {
+        return Err(anyhow!("{} rejected {}: {}", remote_name, branch, reason));
+    }
+
+    if args.has(Switch::SetUpstream) {
+        let upstream = format!("{}/{}", remote_name, branch);
+        repo.find_branch(&branch, BranchType::Local)?
+            .set_upstream(Some(&upstream))
+            .with_context(|| format!("Failed to set the upstream of {}", branch))?;
+    }
+    Ok(())
+}
+
+/// Add the current content of the files to the index, removing deleted ones
+pub fn stage_files(repo: &Repository, paths: &[String]) -> Result<()> {
+    debug!("Staging {:?}", paths);
+    let mut index = repo.index()?;
+    index.add_all(paths, IndexAddOption::DEFAULT, None)?;
+    index.update_all(paths, None)?;
+    index.write().context("Failed to write index")
+}
+
+/// Delete a local branch, refusing to if it isn't merged into HEAD unless
+/// `force`d
+pub fn delete_branch(repo: &Repository, name: &str, force: bool) -> Result<()> {
+    debug!("Deleting branch {}", name);
+    let mut branch = repo.find_branch(name, BranchType::Local)?;
+    let oid = branch.get().peel_to_commit()?.id();
+    let head = repo.head()?.peel_to_commit()?.id();
+    if !force && oid != head && !repo.graph_descendant_of(head, oid)? {
+        return Err(anyhow!("Branch {} isn't merged into HEAD", name));
+    }
+    branch
+        .delete()
+        .with_context(|| format!("Failed to delete branch {}", name))
+}
+
+/// Cherry-pick commits onto HEAD, oldest first. Stops at the first commit that
+/// doesn't apply cleanly, leaving its conflicts in the working directory.
+pub fn cherry_pick(repo: &Repository, specs: &[String]) -> Result<()> {
+    let picked = specs
+        .iter()
+        .map(|spec| resolve_commit(repo, spec))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Walk back from the picked commits to put them in topological order
+    let mut revwalk = repo.revwalk()?;
+    for oid in &picked {
+        revwalk.push(*oid)?;
+    }
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+    let ordered = revwalk
+        .filter_map(|oid| oid.ok())
+        .filter(|oid| picked.contains(oid))
+        .collect::<Vec<_>>();
+
+    let signature = repo.signature()?;
+    for oid in ordered {
+        let commit = repo.find_commit(oid)?;
+        debug!("Cherry-picking {}", commit.id());
+        repo.cherrypick(&commit, None)?;
+
+        let mut index = repo.index()?;
+        if index.has_conflicts() {
+            return Err(anyhow!("Cherry-picking {} caused conflicts", commit.id()));
+        }
+
+        let tree = repo.find_tree(index.write_tree()?)?;
+        let head = repo.head()?.peel_to_commit()?;
+        repo.commit(
+            Some("HEAD"),
+            &commit.author(),
+            &signature,
+            commit.message().unwrap_or_default(),
+            &tree,
+            &[&head],
+        )?;
+        repo.cleanup_state()?;
+    }
+
+    Ok(())
+}
+
+/// Check the argument typed for a prompting command, see [`Command::prompt`].
+/// An amending commit may leave the message empty to keep the old one.
+pub fn validate_arg(repo: &Repository, cmd: Command, args: &CmdArgs, arg: &str) -> Result<()> {
+    let (kind, prefix) = match cmd {
+        Command::BranchCreate => ("branch", "refs/heads/"),
+        Command::TagCreate => ("tag", "refs/tags/"),
+        Command::Commit if arg.trim().is_empty() && !args.has(Switch::Amend) => {
+            return Err(anyhow!("The commit message can't be empty"))
+        }
+        _ => return Ok(()),
+    };
+
+    if arg.is_empty() {
+        return Err(anyhow!("The {} name can't be empty", kind));
+    }
+    let refname = format!("{}{}", prefix, arg);
+    if !Reference::is_valid_name(&refname) {
+        return Err(anyhow!("'{}' isn't a valid {} name", arg, kind));
+    }
+    if repo.find_reference(&refname).is_ok() {
+        return Err(anyhow!("A {} named '{}' already exists", kind, arg));
+    }
+    Ok(())
+}
+
+/// Create a branch at HEAD
+pub fn create_branch(repo: &Repository, name: &str) -> Result<()> {
+    debug!("Creating branch {}", name);
+    let head = repo.head()?.peel_to_commit()?;
+    repo.branch(name, &head, false)
+        .with_context(|| format!("Failed to create branch {}", name))?;
+    Ok(())
+}
+
+/// Create a lightweight tag at HEAD
+pub fn create_tag(repo: &Repository, name: &str) -> Result<()> {
+    debug!("Creating tag {}", name);
+    let head = repo.head()?.peel_to_commit()?;
+    repo.tag_lightweight(name, head.as_object(), false)
+        .with_context(|| format!("Failed to create tag {}", name))?;
+    Ok(())
+}
+
+/// Stash the changes of the index and the working directory
+pub fn save_stash(repo: &Repository, message: &str) -> Result<()> {
+    debug!("Stashing changes: {}", message);
+    let signature = repo.signature()?;
+    Repository::open(repo.path())?
+        .stash_save(&signature, message.trim(), None)
+        .context("Failed to stash changes")?;
+    Ok(())
+}
+
+/// Commit the index to HEAD. Understands the [`Switch::All`] and
+/// [`Switch::Amend`] switches.
+pub fn commit(repo: &Repository, message: &str, args: &CmdArgs) -> Result<Oid> {
+    let mut index = repo.index()?;
+    if args.has(Switch::All) {
+        index.update_all(&["*"], None)?;
+        index.write().context("Failed to write index")?;
+    }
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let head = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+
+    if args.has(Switch::Amend) {
+        let head = head.ok_or(anyhow!("There's no commit to amend"))?;
+        debug!("Amending {}", head.id());
+        // Without a new message the commit keeps its own
+        let message = Some(message).filter(|message| !message.trim().is_empty());
+        return head
+            .amend(Some("HEAD"), None, None, None, message, Some(&tree))
+            .context("Failed to amend the commit");
+    }
+
+    if head.as_ref().map(|head| head.tree_id()) == Some(tree.id()) {
+        return Err(anyhow!("Nothing to commit"));
+    }
+    debug!("Committing: {}", message);
+    let signature = repo.signature()?;
+    let parents = head.iter().collect::<Vec<_>>();
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &parents,
+    )
+    .context("Failed to commit")
+}
+
 /// Handle commands from the ui. Repository state will change depending
-/// on the issued command
-pub fn execute_cmd(repo: &Repository, cmd: Command, selection: &str) {
+/// on the issued command. Commands that work on a single item use the first
+/// of the `selections`.
+pub fn execute_cmd(repo: &Repository, cmd: Command, args: &CmdArgs, selections: &[String]) {
+    let selection = selections.first().map(String::as_str).unwrap_or("");
+    let remote = args.remote.as_deref().unwrap_or(selection);
     match cmd {
         Command::BranchCheckout => {
             checkout_branch(repo, selection);
         }
+        Command::TagCheckout | Command::CommitCheckout => {
+            log_err(resolve_commit(repo, selection).and_then(|oid| checkout_commit(repo, oid)))
+        }
+        Command::StashApply => log_err(apply_stash(repo, selection)),
+        Command::RemoteFetch => log_err(fetch_remote(repo, remote, args)),
+        Command::Push => log_err(push_branch(repo, remote, args)),
+        Command::BranchDelete => {
+            for name in selections {
+                log_err(delete_branch(repo, name, args.has(Switch::Force)));
+            }
+        }
+        Command::CherryPick => log_err(cherry_pick(repo, selections)),
+        Command::StageFiles => log_err(stage_files(repo, selections)),
+        Command::BranchCreate => log_err(match &args.start {
+            Some(start) => {
+                resolve_commit(repo, start).and_then(|oid| create_branch_at(repo, selection, oid))
+            }
+            None => create_branch(repo, selection),
+        }),
+        Command::TagCreate => log_err(create_tag(repo, selection)),
+        Command::StashSave => log_err(save_stash(repo, selection)),
+        Command::Commit => log_err(commit(repo, selection, args).map(|_| ())),
+        Command::SubmoduleInit => log_err(init_submodules(repo)),
+        Command::SubmoduleUpdate => log_err(update_submodules(repo)),
+        Command::SubmoduleSync => log_err(sync_submodules(repo)),
         _ => (),
     }
 }
 
+fn log_err(result: Result<()>) {
+    if let Err(err) = result {
+        error!("{:#}", err);
+    }
+}
+
 fn checkout_branch(repo: &Repository, name: &str) {
     debug!("Checking out {}", name);
     let head = "refs/heads/".to_owned() + name;
     repo.set_head(&head);
     repo.checkout_head(None);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests;
+    use std::rc::Rc;
+
+    #[test]
+    fn file_history_should_follow_renames() {
+        let (_td, repo) = tests::repo_init();
+        let c1 = tests::commit_file(&repo, "a.txt", "one\ntwo\nthree\nfour\n");
+        let _ = tests::commit_file(&repo, "other.txt", "other");
+        let c3 = tests::rename_file(&repo, "a.txt", "b.txt");
+        let c4 = tests::commit_file(&repo, "b.txt", "one\ntwo\nthree\nfour\nfive\n");
+
+        let history = get_file_history(&repo, "b.txt").unwrap();
+        let expected = vec![
+            FileCommit {
+                oid: c4,
+                path: "b.txt".to_owned(),
+                old_path: None,
+            },
+            FileCommit {
+                oid: c3,
+                path: "b.txt".to_owned(),
+                old_path: Some("a.txt".to_owned()),
+            },
+            FileCommit {
+                oid: c1,
+                path: "a.txt".to_owned(),
+                old_path: None,
+            },
+        ];
+        assert_eq!(expected, history);
+    }
+
+    #[test]
+    fn file_history_should_go_through_merges() {
+        let (_td, repo) = tests::repo_init();
+        let base = tests::commit_file(&repo, "a.txt", "one");
+        let side = tests::commit_file(&repo, "b.txt", "added on the side");
+        reset_to(&repo, base, ResetType::Hard).unwrap();
+        let main = tests::commit_file(&repo, "a.txt", "two");
+
+        let workdir = repo.workdir().unwrap().to_owned();
+        std::fs::write(workdir.join("b.txt"), "added on the side").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("b.txt")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = repo.signature().unwrap();
+        let parents = [
+            &repo.find_commit(main).unwrap(),
+            &repo.find_commit(side).unwrap(),
+        ];
+        repo.commit(Some("HEAD"), &sig, &sig, "Merge", &tree, &parents)
+            .unwrap();
+
+        // The merge only brings in the side commit
+        let history = get_file_history(&repo, "b.txt").unwrap();
+        let oids = history.iter().map(|c| c.oid).collect::<Vec<_>>();
+        assert_eq!(oids, vec![side]);
+
+        let history = get_file_history(&repo, "a.txt").unwrap();
+        let oids = history.iter().map(|c| c.oid).collect::<Vec<_>>();
+        assert_eq!(oids, vec![main, base]);
+    }
+
+    #[test]
+    fn file_patch_should_only_contain_file() {
+        let (_td, repo) = tests::repo_init();
+        let _ = tests::commit_file(&repo, "a.txt", "one\n");
+        let _ = tests::commit_file(&repo, "other.txt", "other\n");
+        let c3 = tests::commit_file(&repo, "a.txt", "one\ntwo\n");
+
+        let file_commit = FileCommit {
+            oid: c3,
+            path: "a.txt".to_owned(),
+            old_path: None,
+        };
+        let patch = get_file_patch(&repo, &file_commit).unwrap();
+        assert!(patch.contains("+two"));
+        assert!(!patch.contains("other"));
+    }
+
+    #[test]
+    fn reflog_should_list_newest_first() {
+        let (_td, repo) = tests::repo_init();
+        let c1 = tests::commit_file(&repo, "a.txt", "a");
+        let c2 = tests::commit_file(&repo, "a.txt", "b");
+
+        let reflog = get_reflog(&repo, "HEAD").unwrap();
+        assert_eq!(reflog.len(), 3);
+        assert_eq!(reflog[0].old, c1);
+        assert_eq!(reflog[0].new, c2);
+        assert_eq!(reflog[1].new, c1);
+    }
+
+    #[test]
+    fn reset_to_should_move_branch() {
+        let (_td, repo) = tests::repo_init();
+        let c1 = tests::commit_file(&repo, "a.txt", "a");
+        let _ = tests::commit_file(&repo, "a.txt", "b");
+
+        reset_to(&repo, c1, ResetType::Hard).unwrap();
+
+        assert_eq!(repo.head().unwrap().target(), Some(c1));
+    }
+
+    #[test]
+    fn bisect_should_find_first_bad_commit() {
+        let (_td, repo) = tests::repo_init();
+        let commits = (0..10)
+            .map(|i| tests::commit_file(&repo, "a.txt", &format!("{}", i)))
+            .collect::<Vec<Oid>>();
+        let first_bad = commits[6];
+
+        let mut good = vec![commits[0]];
+        let mut bad = commits[9];
+        let mut steps = 0;
+        let found = loop {
+            match bisect_next(&repo, bad, &good, &[]).unwrap() {
+                BisectStep::Next { oid, .. } => {
+                    let idx = commits.iter().position(|c| *c == oid).unwrap();
+                    if idx >= 6 {
+                        bad = oid;
+                    } else {
+                        good.push(oid);
+                    }
+                }
+                BisectStep::Found(oid) => break oid,
+                BisectStep::Inconclusive(_) => panic!("Bisect shouldn't be inconclusive"),
+            }
+            steps += 1;
+        };
+
+        assert_eq!(found, first_bad);
+        assert!(steps <= bisect_steps(9));
+    }
+
+    #[test]
+    fn bisect_should_be_inconclusive_when_all_skipped() {
+        let (_td, repo) = tests::repo_init();
+        let c1 = tests::commit_file(&repo, "a.txt", "1");
+        let c2 = tests::commit_file(&repo, "a.txt", "2");
+        let c3 = tests::commit_file(&repo, "a.txt", "3");
+
+        let step = bisect_next(&repo, c3, &[c1], &[c2]).unwrap();
+        assert_eq!(step, BisectStep::Inconclusive(vec![c2, c3]));
+    }
+
+    #[test]
+    fn bisect_should_fail_when_marks_are_swapped() {
+        let (_td, repo) = tests::repo_init();
+        let c1 = tests::commit_file(&repo, "a.txt", "1");
+        let c2 = tests::commit_file(&repo, "a.txt", "2");
+
+        let err = bisect_next(&repo, c1, &[c2], &[]).unwrap_err();
+        assert!(err.to_string().contains("swapped"), "{}", err);
+    }
+
+    #[test]
+    fn worktrees_should_be_added_locked_and_pruned() {
+        let (_td, repo) = tests::repo_init();
+        let _ = tests::commit(&repo);
+        let _ = tests::branch(&repo, "feature/wt");
+
+        let path = add_worktree(&repo, "feature/wt").unwrap();
+        let worktrees = get_worktrees(&repo).unwrap();
+        assert_eq!(worktrees.len(), 2);
+        assert_eq!(worktrees[1].name, Some("feature-wt".to_owned()));
+        assert_eq!(worktrees[1].head, "feature/wt");
+
+        let linked = Repository::open(&path).unwrap();
+        assert_eq!(get_worktree_name(&linked), Some("feature-wt".to_owned()));
+        assert_eq!(
+            get_main_workdir(&linked).unwrap().canonicalize().unwrap(),
+            repo.workdir().unwrap().canonicalize().unwrap()
+        );
+
+        set_worktree_locked(&repo, "feature-wt", true).unwrap();
+        assert!(get_worktrees(&repo).unwrap()[1].locked);
+        set_worktree_locked(&repo, "feature-wt", false).unwrap();
+
+        std::fs::remove_dir_all(&path).unwrap();
+        assert_eq!(prune_worktrees(&repo).unwrap(), 1);
+        assert_eq!(get_worktrees(&repo).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn submodules_should_be_reported_initialized_and_updated() {
+        let (_lib_td, lib) = tests::repo_init();
+        let lib_head = tests::commit_file(&lib, "lib.txt", "lib");
+        let url = lib.workdir().unwrap().to_str().unwrap().to_owned();
+
+        let (td, repo) = tests::repo_init();
+        let mut submodule = repo.submodule(&url, Path::new("lib"), true).unwrap();
+        submodule.clone(None).unwrap();
+        submodule.add_finalize().unwrap();
+        let mut index = repo.index().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = repo.signature().unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "Add lib", &tree, &[&head])
+            .unwrap();
+
+        let expected = SubmoduleInfo {
+            name: "lib".to_owned(),
+            path: "lib".to_owned(),
+            recorded: Some(lib_head),
+            checked_out: Some(lib_head),
+            initialized: true,
+            dirty: false,
+        };
+        assert_eq!(get_submodules(&repo).unwrap(), vec![expected.clone()]);
+
+        // A fresh clone has the submodule registered but not checked out
+        let clone_td = tempfile::TempDir::new().unwrap();
+        let clone = Repository::clone(td.path().to_str().unwrap(), clone_td.path()).unwrap();
+        let submodules = get_submodules(&clone).unwrap();
+        assert_eq!(submodules[0].recorded, Some(lib_head));
+        assert_eq!(submodules[0].checked_out, None);
+        assert!(!submodules[0].initialized);
+        assert!(open_submodule(&clone, "lib").is_err());
+
+        init_submodules(&clone).unwrap();
+        let url_key = "submodule.lib.url";
+        assert_eq!(clone.config().unwrap().get_string(url_key).unwrap(), url);
+        update_submodules(&clone).unwrap();
+        sync_submodules(&clone).unwrap();
+        assert_eq!(get_submodules(&clone).unwrap(), vec![expected]);
+
+        let opened = open_submodule(&clone, "lib").unwrap();
+        assert_eq!(opened.head().unwrap().target(), Some(lib_head));
+        assert!(open_submodule(&clone, "other").is_err());
+    }
+
+    #[test]
+    fn stash_index_should_be_parsed() {
+        assert_eq!(parse_stash_index("stash@{3}: On master: wip"), Some(3));
+        assert_eq!(parse_stash_index("master"), None);
+    }
+
+    #[test]
+    fn branch_summary_should_count_ahead_and_behind() {
+        let (_td, repo) = tests::repo_init();
+        let _ = tests::branch(&repo, "b1");
+        let _ = tests::commit_file(&repo, "a.txt", "a");
+
+        let summary = get_branch_summary(&repo, "b1", 5).unwrap();
+        assert_eq!(summary[0], "0 ahead, 1 behind HEAD");
+        assert!(summary[2].ends_with("initial"));
+    }
+
+    #[test]
+    fn branches_should_only_be_deleted_when_merged() {
+        let (_td, repo) = tests::repo_init();
+        let _ = tests::branch(&repo, "merged");
+        let _ = tests::branch(&repo, "unmerged");
+        tests::checkout(&repo, "unmerged");
+        let _ = tests::commit_file(&repo, "a.txt", "a");
+        tests::checkout(&repo, "master");
+
+        let names = vec!["merged".to_owned(), "unmerged".to_owned()];
+        execute_cmd(&repo, Command::BranchDelete, &CmdArgs::default(), &names);
+
+        assert!(repo.find_branch("merged", BranchType::Local).is_err());
+        assert!(repo.find_branch("unmerged", BranchType::Local).is_ok());
+    }
+
+    #[test]
+    fn cherry_pick_should_apply_oldest_first() {
+        let (_td, repo) = tests::repo_init();
+        let _ = tests::branch(&repo, "topic");
+        tests::checkout(&repo, "topic");
+        let c1 = tests::commit_file(&repo, "a.txt", "one");
+        let c2 = tests::commit_file(&repo, "a.txt", "two");
+        tests::checkout(&repo, "master");
+
+        let unmerged = walk_log(&repo, true).unwrap().count();
+        assert_eq!(unmerged, 2);
+
+        let specs = vec![c2.to_string(), c1.to_string()];
+        cherry_pick(&repo, &specs).unwrap();
+
+        let log = get_log(&repo, 2).unwrap();
+        let head = repo.find_commit(log[0]).unwrap();
+        let parent = repo.find_commit(log[1]).unwrap();
+        assert_eq!(head.tree_id(), repo.find_commit(c2).unwrap().tree_id());
+        assert_eq!(parent.tree_id(), repo.find_commit(c1).unwrap().tree_id());
+    }
+
+    #[test]
+    fn prompted_names_should_be_validated() {
+        let (_td, repo) = tests::repo_init();
+        let _ = tests::branch(&repo, "b1");
+        let none = CmdArgs::default();
+
+        assert!(validate_arg(&repo, Command::BranchCreate, &none, "feature/new").is_ok());
+        assert!(validate_arg(&repo, Command::BranchCreate, &none, "").is_err());
+        assert!(validate_arg(&repo, Command::BranchCreate, &none, "bad..name").is_err());
+        assert!(validate_arg(&repo, Command::BranchCreate, &none, "b1").is_err());
+        assert!(validate_arg(&repo, Command::TagCreate, &none, "b1").is_ok());
+        assert!(validate_arg(&repo, Command::StashSave, &none, "").is_ok());
+
+        execute_cmd(
+            &repo,
+            Command::TagCreate,
+            &CmdArgs::default(),
+            &["v1.0".to_owned()],
+        );
+        assert!(validate_arg(&repo, Command::TagCreate, &none, "v1.0").is_err());
+    }
+
+    #[test]
+    fn force_with_lease_should_only_overwrite_what_was_fetched() {
+        let (_td, repo) = tests::repo_init();
+        let remote_dir = tempfile::TempDir::new().unwrap();
+        let bare = Repository::init_bare(remote_dir.path()).unwrap();
+        repo.remote("origin", remote_dir.path().to_str().unwrap())
+            .unwrap();
+        let with = |switches: &[Switch]| CmdArgs {
+            switches: Rc::new(switches.iter().cloned().collect()),
+            ..CmdArgs::default()
+        };
+        let remote_master = || bare.refname_to_id("refs/heads/master").unwrap();
+
+        push_branch(&repo, "origin", &with(&[Switch::SetUpstream])).unwrap();
+        assert_eq!(remote_master(), repo.refname_to_id("HEAD").unwrap());
+        let master = repo.find_branch("master", BranchType::Local).unwrap();
+        assert_eq!(
+            master.upstream().unwrap().name().unwrap(),
+            Some("origin/master")
+        );
+
+        // Someone else pushes to master
+        let _ = tests::branch(&repo, "other");
+        tests::checkout(&repo, "other");
+        let theirs = tests::commit_file(&repo, "theirs.txt", "theirs");
+        push_branch(&repo, "origin", &CmdArgs::default()).unwrap();
+        bare.reference("refs/heads/master", theirs, true, "push")
+            .unwrap();
+        tests::checkout(&repo, "master");
+        let ours = tests::commit_file(&repo, "ours.txt", "ours");
+
+        assert!(push_branch(&repo, "origin", &CmdArgs::default()).is_err());
+        assert!(push_branch(&repo, "origin", &with(&[Switch::ForceWithLease])).is_err());
+        assert_eq!(remote_master(), theirs);
+
+        fetch_remote(&repo, "origin", &CmdArgs::default()).unwrap();
+        push_branch(&repo, "origin", &with(&[Switch::ForceWithLease])).unwrap();
+        assert_eq!(remote_master(), ours);
+    }
+
+    #[test]
+    fn commit_switches_should_stage_and_amend() {
+        let (_td, repo) = tests::repo_init();
+        tests::commit_file(&repo, "a.txt", "one");
+        let workdir = repo.workdir().unwrap().to_owned();
+        std::fs::write(workdir.join("a.txt"), "two").unwrap();
+        let with = |switches: &[Switch]| CmdArgs {
+            switches: Rc::new(switches.iter().cloned().collect()),
+            ..CmdArgs::default()
+        };
+
+        assert!(commit(&repo, "unstaged", &CmdArgs::default()).is_err());
+        let oid = commit(&repo, "all", &with(&[Switch::All])).unwrap();
+        let parent = repo.find_commit(oid).unwrap().parent_id(0).unwrap();
+
+        let amended = commit(&repo, "amended", &with(&[Switch::Amend])).unwrap();
+        let amended = repo.find_commit(amended).unwrap();
+        assert_eq!(amended.message(), Some("amended"));
+        assert_eq!(amended.parent_id(0).unwrap(), parent);
+        assert_eq!(repo.head().unwrap().target(), Some(amended.id()));
+
+        let amend = with(&[Switch::All, Switch::Amend]);
+        assert!(validate_arg(&repo, Command::Commit, &CmdArgs::default(), "").is_err());
+        assert!(validate_arg(&repo, Command::Commit, &amend, "").is_ok());
+        std::fs::write(workdir.join("a.txt"), "three").unwrap();
+        let kept = commit(&repo, "", &amend).unwrap();
+        let kept = repo.find_commit(kept).unwrap();
+        assert_eq!(kept.message(), Some("amended"));
+        assert_ne!(kept.tree_id(), amended.tree_id());
+    }
+
+    #[test]
+    fn stage_files_should_add_selected_files() {
+        let (_td, repo) = tests::repo_init();
+        let workdir = repo.workdir().unwrap().to_owned();
+        std::fs::write(workdir.join("a.txt"), "a").unwrap();
+        std::fs::write(workdir.join("b.txt"), "b").unwrap();
+
+        let unstaged = get_unstaged_files(&repo);
+        assert_eq!(unstaged, vector!["a.txt".to_owned(), "b.txt".to_owned()]);
+
+        stage_files(&repo, &["a.txt".to_owned()]).unwrap();
+        assert_eq!(get_unstaged_files(&repo), vector!["b.txt".to_owned()]);
+    }
+}
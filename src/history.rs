@@ -0,0 +1,244 @@
+//! Per repository history of fuzzybar selections and queries.
+//!
+//! Items picked often and recently are ranked higher ("frecency"), and past
+//! queries can be recalled. The history of a repository is kept in
+//! `$XDG_DATA_HOME/gitools/history/<escaped workdir>.toml`.
+
+use crate::state::Command;
+use crate::xdg;
+use anyhow::{Context, Result};
+use git2::Repository;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const MAX_QUERIES: usize = 50;
+const MAX_COUNT: i64 = 10;
+const HOUR: i64 = 60 * 60;
+const DAY: i64 = 24 * HOUR;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Usage {
+    count: u32,
+    last_used: i64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct History {
+    /// Usage of the items selected per command
+    #[serde(default)]
+    selections: BTreeMap<String, BTreeMap<String, Usage>>,
+    /// Queries per command, newest first
+    #[serde(default)]
+    queries: BTreeMap<String, Vec<String>>,
+}
+
+impl History {
+    /// Load the history of a repository. A missing or broken history file
+    /// just means starting over.
+    pub fn load(repo: &Repository) -> History {
+        let path = match history_file(repo) {
+            Some(path) => path,
+            None => return History::default(),
+        };
+
+        if !path.exists() {
+            return History::default();
+        }
+
+        match History::load_from(&path) {
+            Ok(history) => history,
+            Err(err) => {
+                error!("{:#}", err);
+                History::default()
+            }
+        }
+    }
+
+    pub fn save(&self, repo: &Repository) {
+        if let Some(path) = history_file(repo) {
+            if let Err(err) = self.save_to(&path) {
+                error!("{:#}", err);
+            }
+        }
+    }
+
+    fn load_from(path: &Path) -> Result<History> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read history {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("Invalid history {}", path.display()))
+    }
+
+    fn save_to(&self, path: &Path) -> Result<()> {
+        debug!("Saving history to {}", path.display());
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let content = toml::to_string(self).context("Failed to serialize history")?;
+        std::fs::write(path, content)
+            .with_context(|| format!("Failed to write history {}", path.display()))
+    }
+
+    /// Remember that `name` was picked for `cmd` at `now` (unix seconds)
+    pub fn record_selection(&mut self, cmd: Command, name: &str, now: i64) {
+        let usage = self
+            .selections
+            .entry(cmd_key(cmd))
+            .or_default()
+            .entry(name.to_owned())
+            .or_default();
+        usage.count += 1;
+        usage.last_used = now;
+    }
+
+    /// Remember a query, moving it to the front if it was used before
+    pub fn record_query(&mut self, cmd: Command, query: &str) {
+        if query.trim().is_empty() {
+            return;
+        }
+
+        let queries = self.queries.entry(cmd_key(cmd)).or_default();
+        queries.retain(|q| q != query);
+        queries.insert(0, query.to_owned());
+        queries.truncate(MAX_QUERIES);
+    }
+
+    /// Past queries of `cmd`, newest first
+    pub fn queries(&self, cmd: Command) -> &[String] {
+        self.queries
+            .get(&cmd_key(cmd))
+            .map(|queries| queries.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Frecency of every item picked for `cmd`, to be added to match scores.
+    /// Each use counts for less the longer ago the item was last picked.
+    pub fn frecencies(&self, cmd: Command, now: i64) -> im::HashMap<String, i64> {
+        self.selections
+            .get(&cmd_key(cmd))
+            .map(|usages| {
+                usages
+                    .iter()
+                    .map(|(name, usage)| (name.clone(), frecency(usage, now)))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+fn frecency(usage: &Usage, now: i64) -> i64 {
+    let age = now - usage.last_used;
+    let weight = if age < HOUR {
+        8
+    } else if age < DAY {
+        4
+    } else if age < 7 * DAY {
+        2
+    } else if age < 30 * DAY {
+        1
+    } else {
+        0
+    };
+
+    (usage.count as i64).min(MAX_COUNT) * weight
+}
+
+fn cmd_key(cmd: Command) -> String {
+    format!("{:?}", cmd)
+}
+
+fn history_file(repo: &Repository) -> Option<PathBuf> {
+    repo_file(repo, "history")
+}
+
+/// File keeping state of `repo` in the `kind` directory of
+/// `$XDG_DATA_HOME/gitools`
+pub fn repo_file(repo: &Repository, kind: &str) -> Option<PathBuf> {
+    let repo_path = repo.workdir().unwrap_or_else(|| repo.path());
+    let repo_path = repo_path
+        .canonicalize()
+        .unwrap_or_else(|_| repo_path.to_owned());
+    xdg::data_dir().map(|dir| {
+        dir.join(kind)
+            .join(format!("{}.toml", escape_path(&repo_path)))
+    })
+}
+
+/// Turn a path into a file name by percent-encoding `%` and the path
+/// separators, so different paths always get different names
+fn escape_path(path: &Path) -> String {
+    let mut escaped = String::new();
+    for c in path.to_string_lossy().trim_end_matches('/').chars() {
+        match c {
+            '%' => escaped.push_str("%25"),
+            '/' => escaped.push_str("%2F"),
+            '\\' => escaped.push_str("%5C"),
+            ':' => escaped.push_str("%3A"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn recent_and_frequent_items_should_rank_higher() {
+        let now = 100 * DAY;
+        let mut history = History::default();
+        history.record_selection(Command::BranchCheckout, "old", now - 40 * DAY);
+        history.record_selection(Command::BranchCheckout, "often", now - 2 * DAY);
+        history.record_selection(Command::BranchCheckout, "often", now - 2 * DAY);
+        history.record_selection(Command::BranchCheckout, "often", now - 2 * DAY);
+        history.record_selection(Command::BranchCheckout, "recent", now);
+        history.record_selection(Command::Reflog, "other", now);
+
+        let frecencies = history.frecencies(Command::BranchCheckout, now);
+        assert_eq!(frecencies.get("old"), Some(&0));
+        assert_eq!(frecencies.get("often"), Some(&6));
+        assert_eq!(frecencies.get("recent"), Some(&8));
+        assert_eq!(frecencies.get("other"), None);
+    }
+
+    #[test]
+    fn queries_should_be_deduplicated_newest_first() {
+        let mut history = History::default();
+        history.record_query(Command::FileLog, "main");
+        history.record_query(Command::FileLog, "src");
+        history.record_query(Command::FileLog, " ");
+        history.record_query(Command::FileLog, "main");
+
+        assert_eq!(history.queries(Command::FileLog), ["main", "src"]);
+        assert!(history.queries(Command::Reflog).is_empty());
+    }
+
+    #[test]
+    fn history_should_survive_a_roundtrip() {
+        let td = TempDir::new().unwrap();
+        let path = td.path().join("history").join("repo.toml");
+
+        let mut history = History::default();
+        history.record_selection(Command::BranchCheckout, "feature/bar", 42);
+        history.record_query(Command::BranchCheckout, "fb");
+        history.save_to(&path).unwrap();
+
+        assert_eq!(History::load_from(&path).unwrap(), history);
+    }
+
+    #[test]
+    fn escaped_paths_should_not_collide() {
+        assert_eq!(
+            escape_path(Path::new("/home/me/repo/")),
+            "%2Fhome%2Fme%2Frepo"
+        );
+        let pairs = [("/a%b", "/a/b"), ("/a%/b", "/a/%b"), ("/a%2Fb", "/a/b")];
+        for (a, b) in pairs.iter() {
+            assert_ne!(escape_path(Path::new(a)), escape_path(Path::new(b)));
+        }
+    }
+}
@@ -0,0 +1,490 @@
+//! Keymap of the cheatsheet, a tree of key sequences leading to commands.
+//!
+//! Keys are written the way emacs does: `b`, `B`, `C-c` (Ctrl), `M-x` (Alt),
+//! `s-k` (Super/Meta), `S-Tab` (Shift, for named keys only, characters are
+//! written in their shifted form) and named keys like `Enter` or `F5`. A
+//! sequence is written as space separated keys, e.g. `C-c C-c`.
+
+use crate::fuzzy;
+use crate::state::{CmdArgs, Command};
+use anyhow::{anyhow, Result};
+use druid::{Data, KbKey, KeyEvent};
+use im::Vector;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// Named keys accepted in the config, as printed by [`KbKey`]
+const NAMED_KEYS: &[&str] = &[
+    "Enter",
+    "Tab",
+    "Escape",
+    "Backspace",
+    "Delete",
+    "Insert",
+    "Home",
+    "End",
+    "PageUp",
+    "PageDown",
+    "ArrowUp",
+    "ArrowDown",
+    "ArrowLeft",
+    "ArrowRight",
+    "F1",
+    "F2",
+    "F3",
+    "F4",
+    "F5",
+    "F6",
+    "F7",
+    "F8",
+    "F9",
+    "F10",
+    "F11",
+    "F12",
+];
+
+/// Shorter names of some keys, also accepted in the config
+const ALIASES: &[(&str, &str)] = &[
+    ("SPC", " "),
+    ("Space", " "),
+    ("RET", "Enter"),
+    ("TAB", "Tab"),
+    ("ESC", "Escape"),
+    ("DEL", "Delete"),
+    ("Up", "ArrowUp"),
+    ("Down", "ArrowDown"),
+    ("Left", "ArrowLeft"),
+    ("Right", "ArrowRight"),
+];
+
+/// A key pressed with modifiers. `name` is either a single character or one of
+/// the [`NAMED_KEYS`].
+#[derive(Clone, Data, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Key {
+    pub name: String,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub meta: bool,
+    /// Only set for named keys
+    pub shift: bool,
+}
+
+impl Key {
+    pub fn parse(key: &str) -> Result<Key> {
+        let mut rest = key;
+        let mut parsed = Key {
+            name: "".to_owned(),
+            ctrl: false,
+            alt: false,
+            meta: false,
+            shift: false,
+        };
+
+        // A lone "C" or "-" is a key, "C-" followed by something a modifier
+        while rest.chars().count() > 2 && rest.is_char_boundary(1) && rest[1..].starts_with('-') {
+            match &rest[..1] {
+                "C" => parsed.ctrl = true,
+                "M" => parsed.alt = true,
+                "s" => parsed.meta = true,
+                "S" => parsed.shift = true,
+                _ => break,
+            }
+            rest = &rest[2..];
+        }
+
+        let name = ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == rest)
+            .map(|(_, name)| *name)
+            .unwrap_or(rest);
+
+        if name.chars().count() == 1 {
+            if parsed.shift {
+                parsed.name = name.to_uppercase();
+                parsed.shift = false;
+            } else {
+                parsed.name = name.to_owned();
+            }
+        } else if NAMED_KEYS.contains(&name) {
+            parsed.name = name.to_owned();
+        } else if name.is_empty() {
+            return Err(anyhow!("Empty key in '{}'", key));
+        } else {
+            return Err(anyhow!("Unknown key '{}'", key));
+        }
+
+        Ok(parsed)
+    }
+
+    /// The key of a key event, None for a modifier or unknown key on its own
+    pub fn from_event(event: &KeyEvent) -> Option<Key> {
+        let (name, shift) = match &event.key {
+            KbKey::Character(c) => (c.clone(), false),
+            key => {
+                let name = key.to_string();
+                if !NAMED_KEYS.contains(&name.as_str()) {
+                    return None;
+                }
+                (name, event.mods.shift())
+            }
+        };
+
+        Some(Key {
+            name,
+            ctrl: event.mods.ctrl(),
+            alt: event.mods.alt(),
+            meta: event.mods.meta(),
+            shift,
+        })
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "C-")?;
+        }
+        if self.alt {
+            write!(f, "M-")?;
+        }
+        if self.meta {
+            write!(f, "s-")?;
+        }
+        if self.shift {
+            write!(f, "S-")?;
+        }
+        match self.name.as_str() {
+            " " => write!(f, "SPC"),
+            name => write!(f, "{}", name),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D>(deserializer: D) -> Result<Key, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let key = String::deserialize(deserializer)?;
+        Key::parse(&key).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Keys pressed one after the other
+#[derive(Clone, Data, Debug, Default, PartialEq)]
+pub struct KeySeq(pub Vector<Key>);
+
+impl KeySeq {
+    pub fn parse(seq: &str) -> Result<KeySeq> {
+        seq.split_whitespace()
+            .map(Key::parse)
+            .collect::<Result<Vector<_>>>()
+            .map(KeySeq)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for KeySeq {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let keys = self.0.iter().map(Key::to_string).collect::<Vec<_>>();
+        write!(f, "{}", keys.join(" "))
+    }
+}
+
+pub type KeyMap = Rc<BTreeMap<Key, KeyNode>>;
+
+/// A node of the keymap, either running a command or leading to more keys
+#[derive(Clone, Data, Debug, Deserialize, PartialEq)]
+pub struct KeyNode {
+    pub key: Key,
+    pub name: String,
+    /// Longer explanation shown next to the name in the cheatsheet
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Header the node is listed under in the cheatsheet, along with the other
+    /// nodes of its level in the same group
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub command: Option<Command>,
+    /// Preset options of the command, e.g. switches or the remote
+    #[serde(default)]
+    pub args: CmdArgs,
+    /// Shell command whose output lines replace the fuzzybar items
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Shell command run instead of the command, on the fuzzybar selection if
+    /// it has a `{selection}`, see [`crate::shell`]
+    #[serde(default)]
+    pub shell: Option<String>,
+    #[serde(default, deserialize_with = "de_keymap")]
+    pub next: KeyMap,
+}
+
+impl KeyNode {
+    /// A node running `cmd` that isn't bound to any key, its key is empty
+    pub fn unbound(name: String, cmd: Command) -> Self {
+        KeyNode {
+            key: Key {
+                name: "".to_owned(),
+                ctrl: false,
+                alt: false,
+                meta: false,
+                shift: false,
+            },
+            name,
+            description: None,
+            group: None,
+            command: Some(cmd),
+            args: CmdArgs::default(),
+            source: None,
+            shell: None,
+            next: KeyMap::default(),
+        }
+    }
+}
+
+/// The keys following `seq`, None if `seq` doesn't lead anywhere
+pub fn children<'a>(keymap: &'a KeyMap, seq: &KeySeq) -> Option<&'a KeyMap> {
+    let mut keymap = keymap;
+    for key in seq.0.iter() {
+        keymap = &keymap.get(key)?.next;
+    }
+    Some(keymap)
+}
+
+/// Name of a group and its nodes
+pub type Group<'a> = (Option<&'a str>, Vec<(&'a Key, &'a KeyNode)>);
+
+/// The nodes of a level by their group, the ungrouped ones first and the
+/// groups in the order of their first key
+pub fn groups(keymap: &KeyMap) -> Vec<Group<'_>> {
+    let mut groups: Vec<Group> = vec![(None, vec![])];
+    for (key, node) in keymap.iter() {
+        let group = node.group.as_deref();
+        match groups.iter_mut().find(|(name, _)| *name == group) {
+            Some((_, nodes)) => nodes.push((key, node)),
+            None => groups.push((group, vec![(key, node)])),
+        }
+    }
+    groups.retain(|(_, nodes)| !nodes.is_empty());
+    groups
+}
+
+/// A node running a command or a shell command, found at any depth
+#[derive(Clone, Data, Debug)]
+pub struct Entry {
+    /// Keys leading to the node
+    pub seq: KeySeq,
+    /// Names of the nodes on the way, e.g. `Branching / Checkout`
+    pub title: String,
+    pub node: KeyNode,
+}
+
+/// All the entries of the keymap, in key order
+pub fn entries(keymap: &KeyMap) -> Vec<Entry> {
+    let mut entries = vec![];
+    collect_entries(keymap, &KeySeq::default(), "", &mut entries);
+    entries
+}
+
+fn collect_entries(keymap: &KeyMap, seq: &KeySeq, title: &str, entries: &mut Vec<Entry>) {
+    for (key, node) in keymap.iter() {
+        let mut seq = seq.clone();
+        seq.0.push_back(key.clone());
+        let title = if title.is_empty() {
+            node.name.clone()
+        } else {
+            format!("{} / {}", title, node.name)
+        };
+
+        if node.command.is_some() || node.shell.is_some() {
+            entries.push(Entry {
+                seq: seq.clone(),
+                title: title.clone(),
+                node: node.clone(),
+            });
+        }
+        collect_entries(&node.next, &seq, &title, entries);
+    }
+}
+
+/// Entries whose keys, title or description match `query`, best first
+pub fn search(keymap: &KeyMap, query: &str) -> Vec<Entry> {
+    let mut matches = entries(keymap)
+        .into_iter()
+        .filter_map(|entry| {
+            let description = entry.node.description.as_deref().unwrap_or("");
+            let text = format!("{} {} {}", entry.seq, entry.title, description);
+            fuzzy::fuzzy_match(query, &text).map(|m| (m.score, entry))
+        })
+        .collect::<Vec<_>>();
+    // The sort is stable, entries scoring the same stay in key order
+    matches.sort_by_key(|(score, _)| -score);
+    matches.into_iter().map(|(_, entry)| entry).collect()
+}
+
+/// Deserialize a table of nodes, its keys are parsed as [`Key`]s
+pub fn de_keymap<'de, D>(deserializer: D) -> Result<KeyMap, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let str_map = BTreeMap::<String, KeyNode>::deserialize(deserializer)?;
+    let result = str_map
+        .into_iter()
+        .map(|(k, v)| Ok((Key::parse(&k)?, v)))
+        .collect::<Result<BTreeMap<_, _>>>()
+        .map_err(serde::de::Error::custom)?;
+
+    Ok(Rc::new(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_should_be_parsed_with_modifiers() {
+        let key = Key::parse("C-M-x").unwrap();
+        assert!(key.ctrl && key.alt && !key.meta);
+        assert_eq!(key.name, "x");
+
+        assert_eq!(Key::parse("S-a").unwrap(), Key::parse("A").unwrap());
+        assert_ne!(Key::parse("a").unwrap(), Key::parse("A").unwrap());
+        assert!(Key::parse("S-Tab").unwrap().shift);
+        assert_eq!(Key::parse("C--").unwrap().name, "-");
+        assert_eq!(Key::parse("C").unwrap().name, "C");
+        assert_eq!(Key::parse("ä").unwrap().name, "ä");
+        assert_eq!(Key::parse("RET").unwrap(), Key::parse("Enter").unwrap());
+
+        assert!(Key::parse("").is_err());
+        assert!(Key::parse("C-").is_err());
+        assert!(Key::parse("Foo").is_err());
+    }
+
+    #[test]
+    fn sequences_should_roundtrip() {
+        let seq = KeySeq::parse("C-c  C-c SPC s-F5").unwrap();
+        assert_eq!(seq.0.len(), 4);
+        assert_eq!(seq.to_string(), "C-c C-c SPC s-F5");
+        assert_eq!(KeySeq::parse(&seq.to_string()).unwrap(), seq);
+    }
+
+    #[test]
+    fn keymap_should_nest_to_any_depth() {
+        let config = r#"
+            [b]
+            key = "b"
+            name = "Branching"
+
+            [b.next.s]
+            key = "s"
+            name = "Spinoff"
+
+            [b.next.s.next.c]
+            key = "c"
+            name = "Create"
+            command = "BranchCreate"
+
+            [C-c]
+            key = "C-c"
+            name = "Committing"
+
+            [C-c.next.C-c]
+            key = "C-c"
+            name = "Commit"
+            command = "Commit"
+        "#;
+        let table = toml::from_str::<toml::value::Table>(config).unwrap();
+        let keymap = de_keymap(toml::Value::Table(table)).unwrap();
+
+        let nodes = children(&keymap, &KeySeq::parse("b s").unwrap()).unwrap();
+        let node = nodes.get(&Key::parse("c").unwrap()).unwrap();
+        assert_eq!(node.command, Some(Command::BranchCreate));
+        assert!(children(&keymap, &KeySeq::parse("b x").unwrap()).is_none());
+
+        let nodes = children(&keymap, &KeySeq::parse("C-c").unwrap()).unwrap();
+        assert_eq!(nodes.keys().next().unwrap().to_string(), "C-c");
+    }
+
+    #[test]
+    fn entries_should_be_grouped_and_searched_at_every_depth() {
+        let config = r#"
+            [b]
+            key = "b"
+            name = "Branching"
+
+            [b.next.b]
+            key = "b"
+            name = "Checkout"
+            group = "Checkout"
+            command = "BranchCheckout"
+
+            [b.next.d]
+            key = "d"
+            name = "Delete"
+            command = "BranchDelete"
+
+            [b.next.t]
+            key = "t"
+            name = "Checkout tag"
+            group = "Checkout"
+            command = "TagCheckout"
+
+            [b.next.s]
+            key = "s"
+            name = "Spinoff"
+
+            [b.next.s.next.c]
+            key = "c"
+            name = "Create"
+            description = "New branch from the current one"
+            command = "BranchCreate"
+
+            [c]
+            key = "c"
+            name = "Commit"
+            command = "Commit"
+        "#;
+        let table = toml::from_str::<toml::value::Table>(config).unwrap();
+        let keymap = de_keymap(toml::Value::Table(table)).unwrap();
+
+        let nodes = children(&keymap, &KeySeq::parse("b").unwrap()).unwrap();
+        let groups = groups(nodes)
+            .into_iter()
+            .map(|(group, nodes)| {
+                let keys = nodes.iter().map(|(key, _)| key.to_string()).collect();
+                (group, keys)
+            })
+            .collect::<Vec<(_, Vec<_>)>>();
+        let expected = vec![
+            (None, vec!["d".to_owned(), "s".to_owned()]),
+            (Some("Checkout"), vec!["b".to_owned(), "t".to_owned()]),
+        ];
+        assert_eq!(groups, expected);
+
+        let titles = entries(&keymap)
+            .into_iter()
+            .map(|entry| (entry.seq.to_string(), entry.title))
+            .collect::<Vec<_>>();
+        assert_eq!(titles.len(), 5);
+        assert_eq!(
+            titles[2],
+            (
+                "b s c".to_owned(),
+                "Branching / Spinoff / Create".to_owned()
+            )
+        );
+
+        let found = search(&keymap, "current");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].seq, KeySeq::parse("b s c").unwrap());
+        assert_eq!(search(&keymap, "tag")[0].node.name, "Checkout tag");
+        assert_eq!(search(&keymap, "").len(), 5);
+    }
+}
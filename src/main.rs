@@ -1,37 +1,63 @@
 use anyhow::Result;
-use druid::widget::{Container, Flex, Label};
-use druid::{
-    AppLauncher, Color, DelegateCtx, Env, Event, Selector, Widget, WidgetExt, WindowDesc, WindowId,
-};
+use cli::Cli;
+use druid::widget::{Container, EnvScope, Flex, Label, ViewSwitcher};
+use druid::{AppLauncher, DelegateCtx, Event, Selector, Widget, WidgetExt, WindowDesc, WindowId};
 use git2::Repository;
 use im::{vector, Vector};
-use log::info;
-use state::{AppState, CheatSheetState, Command, Config, FuzzybarState, GitState, KeyMapLevel};
+use keymap::KeySeq;
+use log::{error, info};
+use state::{AppState, CheatSheetState, Command, FuzzybarState, GitState, View};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use theme::ThemeState;
+use widgets::bisect::Bisect;
+use widgets::filelog::FileLog;
+use widgets::norepo::NoRepo;
+use widgets::output::ShellOutput;
+use widgets::reflog::Reflog;
+use widgets::worktrees::Worktrees;
 
 #[cfg(test)]
 mod tests;
 
+mod cli;
+mod config;
+mod fuzzy;
 mod git;
+mod history;
+mod keymap;
+mod palette;
+mod provider;
+mod query;
+mod shell;
 mod state;
 mod theme;
+mod transient;
 mod widgets;
+mod xdg;
 
 const WINDOW_SIZE: (f64, f64) = (1000.0, 800.0);
+const NO_REPO_WINDOW_SIZE: (f64, f64) = (600.0, 200.0);
 
 fn main() -> Result<()> {
     setup_logger().expect("Failed to setup logger");
 
-    let window = WindowDesc::new(build_root)
-        .window_size(WINDOW_SIZE)
-        .resizable(true)
-        .title("Git Tools");
-
-    let args: Vec<String> = std::env::args().collect();
-    let repo = Rc::new(Repository::open(&args[1])?);
+    let args = match cli::parse_args(std::env::args().skip(1))? {
+        Cli::Run(args) => args,
+        Cli::Print(text) => {
+            println!("{}", text);
+            return Ok(());
+        }
+    };
+    let repo = match Repository::discover(&args.repo) {
+        Ok(repo) => Rc::new(repo),
+        Err(err) => return no_repo(&args.repo, err),
+    };
 
-    let config_str = std::fs::read_to_string("./config.toml").unwrap();
-    let config: Config = toml::from_str(&config_str).unwrap();
+    let (config, config_errors) = config::load(&repo, args.config.as_deref());
+    for err in config_errors.iter() {
+        error!("{}", err);
+    }
 
     let (local, remote) = git::get_branches(&repo);
 
@@ -47,80 +73,113 @@ fn main() -> Result<()> {
         win_size: WINDOW_SIZE.into(),
         repo_header: header,
         repo_status: status,
+        view: View::Status,
+        file_log: FileLog::default(),
+        reflog: Reflog::default(),
+        bisect: Bisect::default(),
+        worktrees: Worktrees::default(),
         cheatsheet: CheatSheetState {
             is_hidden: true,
             keymap: config.keymap.map,
-            current_node: 0,
-            current_level: KeyMapLevel::L1,
-        },
-        fuzzybar: FuzzybarState {
-            is_hidden: true,
-            cmd: Command::ShowMenu,
-            query: "".to_owned(),
-            source: all_branches.clone(),
-            filtered: vector![],
+            path: KeySeq::default(),
+            transient: None,
+            search: None,
+            picked: None,
+            remembered: im::HashMap::new(),
+            persist_args: config.transient.persist,
         },
+        fuzzybar: FuzzybarState::new(Command::ShowMenu),
         git: GitState {
             local_branches: local,
             remote_branches: remote,
             all_branches,
         },
+        notifications: vector![],
+        config_errors: config_errors.iter().map(|err| err.to_string()).collect(),
+        output: ShellOutput::default(),
+        theme: ThemeState::new(config.appearance, config.themes),
     };
 
     app_state.fuzzybar.filter();
 
+    let config_path = args.config;
+    let window = WindowDesc::new(move || build_root(config_path.clone()))
+        .window_size(WINDOW_SIZE)
+        .resizable(true)
+        .title("Git Tools");
+
     info!("Starting application...");
     AppLauncher::with_window(window)
-        .configure_env(|env, state| configure_env(env, state))
+        .configure_env(|env, state| theme::apply(env, &state.theme))
         .launch(app_state)
         .expect("Failed to launch app");
     Ok(())
 }
 
-fn build_root() -> impl Widget<AppState> {
+/// Show why there's no repository at `path` instead of the app, with the
+/// choice to create one if `path` is a directory outside of any repository
+fn no_repo(path: &Path, err: git2::Error) -> Result<()> {
+    error!("No repository at {}: {}", path.display(), err);
+    let state = NoRepo::new(path.to_owned(), &err);
+    let created = state.created.clone();
+
+    let config = config::defaults();
+    let theme = ThemeState::new(config.appearance, config.themes);
+    let window = WindowDesc::new(widgets::norepo::widget)
+        .window_size(NO_REPO_WINDOW_SIZE)
+        .title("Git Tools");
+    AppLauncher::with_window(window)
+        .configure_env(move |env, _state| theme::apply(env, &theme))
+        .launch(state)
+        .expect("Failed to launch app");
+
+    if !created.get() {
+        return Ok(());
+    }
+    // An app can only be launched once per process, the new repository is
+    // opened by a fresh one
+    info!("Restarting to open the new repository");
+    let status = std::process::Command::new(std::env::current_exe()?)
+        .args(std::env::args().skip(1))
+        .status()?;
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+fn build_root(config_path: Option<PathBuf>) -> impl Widget<AppState> {
     let fuzzybar = widgets::fuzzybar::Fuzzybar::new();
     let cheatsheet = widgets::cheatsheet::CheatSheet::new(WINDOW_SIZE.into());
     let header = widgets::header::RepoHeader::widget();
-    let status = widgets::status::RepoStatusDetail::widget();
+    let view = ViewSwitcher::new(
+        |data: &AppState, _env| data.view,
+        |view, _data, _env| -> Box<dyn Widget<AppState>> {
+            match view {
+                View::Status => Box::new(widgets::status::RepoStatusDetail::widget()),
+                View::FileLog => Box::new(widgets::filelog::FileLog::widget()),
+                View::Reflog => Box::new(widgets::reflog::Reflog::widget()),
+                View::Bisect => Box::new(widgets::bisect::Bisect::widget()),
+                View::Worktrees => Box::new(widgets::worktrees::Worktrees::widget()),
+            }
+        },
+    );
     let contents = Flex::column()
+        .with_child(widgets::notifications::widget())
         .with_child(header)
         .with_spacer(24.0)
-        .with_child(status)
+        .with_child(view)
         .with_flex_spacer(1.0)
+        .with_child(widgets::output::widget())
         .with_child(cheatsheet)
         .with_child(fuzzybar);
-    let container = Container::new(contents).background(theme::BASE_3);
+    let container = Container::new(contents)
+        .background(theme::BASE_3)
+        .controller(widgets::configwatch::ConfigWatch::new(config_path))
+        .controller(widgets::output::ShellRunner::default());
     // container.debug_paint_layout().debug_widget_id()
-    container
-}
-
-fn configure_env(env: &mut Env, app: &AppState) {
-    env.set(theme::BASE_3, Color::rgb8(0xfd, 0xf6, 0xe3)); // #fdf6e3
-    env.set(theme::BASE_2, Color::rgb8(0xee, 0xe8, 0xd5)); // #eee8d5
-    env.set(theme::BASE_1, Color::rgb8(0x93, 0xa1, 0xa1)); // #93a1a1
-    env.set(theme::BASE_0, Color::rgb8(0x83, 0x94, 0x96)); // #839496
-    env.set(theme::BASE_00, Color::rgb8(0x65, 0x7b, 0x83)); // #657b83
-    env.set(theme::BASE_01, Color::rgb8(0x58, 0x6e, 0xe3)); // #586e75
-    env.set(theme::BASE_02, Color::rgb8(0x07, 0x36, 0x42)); // #073642
-    env.set(theme::BASE_03, Color::rgb8(0x00, 0x2b, 0x36)); // #002b36
-
-    env.set(theme::YELLOW, Color::rgb8(0xb5, 0x89, 0x00)); // #b58900
-    env.set(theme::ORANGE, Color::rgb8(0xcb, 0x4b, 0x16)); // #cb4b16
-    env.set(theme::RED, Color::rgb8(0xdc, 0x32, 0x2f)); // #dc322f
-    env.set(theme::MAGENTA, Color::rgb8(0xd3, 0x36, 0x82)); // #d33682
-    env.set(theme::VIOLET, Color::rgb8(0x6c, 0x71, 0xc4)); // #6c71c4
-    env.set(theme::BLUE, Color::rgb8(0x26, 0x8b, 0xd2)); // #268bd2
-    env.set(theme::CYAN, Color::rgb8(0x2a, 0xa1, 0x98)); // #2aa198
-    env.set(theme::GREEN, Color::rgb8(0x85, 0x99, 0x00)); // #859900
-
-    // Overrides
-    env.set(druid::theme::FONT_NAME, "Rec Mono Duotone");
-    // env.set(druid::theme::FONT_NAME, "RecursiveSansLnr-Regular");
-    env.set(druid::theme::TEXT_SIZE_NORMAL, 12.0);
-    env.set(druid::theme::BACKGROUND_LIGHT, env.get(theme::BASE_3));
-    env.set(druid::theme::LABEL_COLOR, env.get(theme::BASE_00));
-    env.set(druid::theme::PRIMARY_LIGHT, env.get(theme::BASE_3));
-    env.set(druid::theme::BORDER_DARK, env.get(theme::BASE_3));
+    // Re-apply the theme below the root so switching it takes effect at once
+    EnvScope::new(
+        |env, data: &AppState| theme::apply(env, &data.theme),
+        container,
+    )
 }
 
 fn setup_logger() -> Result<()> {
@@ -144,4 +203,6 @@ fn setup_logger() -> Result<()> {
 mod consts {
     use druid::Selector;
     pub const CS_TAKE_FOCUS: Selector = Selector::new("gitools.cs.take-focus");
+    pub const VIEW_TAKE_FOCUS: Selector = Selector::new("gitools.view.take-focus");
+    pub const FB_TAKE_FOCUS: Selector = Selector::new("gitools.fb.take-focus");
 }
@@ -0,0 +1,122 @@
+//! The command palette, opened with `C-P` from the cheatsheet. It lists every
+//! entry of the keymap along with its keys, and the commands the keymap
+//! doesn't bind, to be picked in the fuzzybar without knowing their keys.
+
+use crate::keymap::{self, Entry, KeyMap, KeyNode, KeySeq};
+use crate::provider::{ItemKind, SourceItem};
+use crate::state::Command;
+use im::Vector;
+
+/// Key opening the palette from the cheatsheet
+pub const PALETTE_KEY: &str = "C-P";
+
+/// The entries of the keymap, followed by the commands without a key
+pub fn actions(keymap: &KeyMap) -> Vec<Entry> {
+    let mut actions = keymap::entries(keymap);
+    let bound = actions
+        .iter()
+        .filter(|entry| entry.node.shell.is_none())
+        .filter_map(|entry| entry.node.command)
+        .collect::<Vec<_>>();
+
+    for cmd in Command::ALL.iter() {
+        // Neither does anything on its own
+        if bound.contains(cmd) || *cmd == Command::ShowMenu || *cmd == Command::Palette {
+            continue;
+        }
+        let title = title(*cmd);
+        actions.push(Entry {
+            seq: KeySeq::default(),
+            title: title.clone(),
+            node: KeyNode::unbound(title, *cmd),
+        });
+    }
+    actions
+}
+
+/// Candidates of the fuzzybar, matched by their title and described by their
+/// keys
+pub fn items(keymap: &KeyMap) -> Vector<SourceItem> {
+    actions(keymap)
+        .into_iter()
+        .map(|entry| {
+            let mut item = SourceItem::new(&entry.title, ItemKind::Line);
+            let mut description = entry.seq.to_string();
+            if let Some(text) = &entry.node.description {
+                if !description.is_empty() {
+                    description.push_str("  ");
+                }
+                description.push_str(text);
+            }
+            item.description = description;
+            item
+        })
+        .collect()
+}
+
+/// The action picked in the fuzzybar
+pub fn find(keymap: &KeyMap, title: &str) -> Option<Entry> {
+    actions(keymap)
+        .into_iter()
+        .find(|entry| entry.title == title)
+}
+
+/// `BranchCheckout` as `Branch checkout`
+fn title(cmd: Command) -> String {
+    let mut title = String::new();
+    for c in format!("{:?}", cmd).chars() {
+        if c.is_uppercase() && !title.is_empty() {
+            title.push(' ');
+            title.extend(c.to_lowercase());
+        } else {
+            title.push(c);
+        }
+    }
+    title
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_should_list_entries_then_unbound_commands() {
+        let config = r#"
+            [b]
+            key = "b"
+            name = "Branching"
+
+            [b.next.c]
+            key = "c"
+            name = "Checkout commit"
+            description = "Detached at any commit"
+            command = "CommitCheckout"
+
+            [l]
+            key = "l"
+            name = "Graph"
+            shell = "git log --graph"
+        "#;
+        let table = toml::from_str::<toml::value::Table>(config).unwrap();
+        let keymap = keymap::de_keymap(toml::Value::Table(table)).unwrap();
+        let items = items(&keymap);
+
+        assert_eq!(items[0].name, "Branching / Checkout commit");
+        assert_eq!(items[0].description, "b c  Detached at any commit");
+        assert_eq!(items[1].name, "Graph");
+        assert_eq!(items[1].description, "l");
+
+        let names = items
+            .iter()
+            .map(|item| item.name.as_str())
+            .collect::<Vec<_>>();
+        assert!(names.contains(&"Submodule open"));
+        assert!(!names.contains(&"Commit checkout"));
+        assert!(!names.contains(&"Palette"));
+        assert_eq!(names.len(), Command::ALL.len() - 3 + 2);
+
+        let picked = find(&keymap, "Branch checkout").unwrap();
+        assert!(picked.seq.is_empty());
+        assert_eq!(picked.node.command, Some(Command::BranchCheckout));
+    }
+}
@@ -0,0 +1,459 @@
+//! Candidate sources of the fuzzybar.
+//!
+//! Every command that needs a selection has a [`Provider`] listing its
+//! candidates. Small sources are listed right away, large ones (the history,
+//! output of shell commands) are streamed in chunks from a separate thread so
+//! the fuzzybar can show the first items while the rest are still loading.
+
+use crate::git;
+use crate::state::{Command, SourceFilter};
+use anyhow::{anyhow, Result};
+use druid::Data;
+use git2::{BranchType, Commit, Repository};
+use log::{debug, error};
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+const COMMIT_LIMIT: usize = 5000;
+const CHUNK_SIZE: usize = 256;
+
+#[derive(Clone, Copy, PartialEq, Data, Debug)]
+pub enum ItemKind {
+    Branch,
+    RemoteBranch,
+    Tag,
+    Commit,
+    Stash,
+    File,
+    Remote,
+    Submodule,
+    Ref,
+    Line,
+}
+
+/// A candidate of the fuzzybar. Only `name` is matched against the query and
+/// passed to the command, the rest describes the item.
+#[derive(Clone, Data, Debug, PartialEq)]
+pub struct SourceItem {
+    pub name: String,
+    pub kind: ItemKind,
+    /// Commit the item points to, if any
+    pub oid: Option<String>,
+    /// Author of the commit, for commit sources
+    pub author: Option<String>,
+    pub description: String,
+}
+
+impl SourceItem {
+    pub fn new(name: &str, kind: ItemKind) -> Self {
+        SourceItem {
+            name: name.to_owned(),
+            kind,
+            oid: None,
+            author: None,
+            description: "".to_owned(),
+        }
+    }
+
+    /// Describe the item by the commit it points to
+    fn with_commit(mut self, commit: &Commit) -> Self {
+        self.oid = Some(commit.id().to_string());
+        self.description = format!(
+            "{} {}",
+            &commit.id().to_string()[..7],
+            commit.summary().unwrap_or_default()
+        );
+        self
+    }
+}
+
+pub enum Source {
+    Ready(Vec<SourceItem>),
+    /// Chunks of items, the channel is closed after the last one
+    Stream(Receiver<Vec<SourceItem>>),
+}
+
+pub trait Provider {
+    fn load(&self, repo: &Repository) -> Source;
+}
+
+/// Provider of the candidates of `cmd`. A shell command configured on the
+/// keymap entry replaces the built-in source.
+pub fn provider(
+    cmd: Command,
+    shell: Option<&str>,
+    filter: Option<SourceFilter>,
+) -> Option<Box<dyn Provider>> {
+    if let Some(shell) = shell {
+        return Some(Box::new(ShellProvider(shell.to_owned())));
+    }
+
+    let branches = |remote| match filter {
+        Some(SourceFilter::Local) => BranchProvider {
+            local: true,
+            remote: false,
+        },
+        Some(SourceFilter::Remote) => BranchProvider {
+            local: false,
+            remote: true,
+        },
+        None => BranchProvider {
+            local: true,
+            remote,
+        },
+    };
+
+    let provider: Box<dyn Provider> = match cmd {
+        Command::BranchCheckout | Command::WorktreeAdd => Box::new(branches(true)),
+        Command::BranchDelete => Box::new(branches(false)),
+        Command::TagCheckout => Box::new(TagProvider),
+        Command::CommitCheckout => Box::new(CommitProvider { unmerged: false }),
+        Command::CherryPick => Box::new(CommitProvider { unmerged: true }),
+        Command::StashApply => Box::new(StashProvider),
+        Command::FileLog => Box::new(FileProvider { unstaged: false }),
+        Command::StageFiles => Box::new(FileProvider { unstaged: true }),
+        Command::Reflog => Box::new(RefProvider),
+        Command::SubmoduleOpen => Box::new(SubmoduleProvider),
+        Command::RemoteFetch | Command::Push => Box::new(RemoteProvider),
+        _ => return None,
+    };
+    Some(provider)
+}
+
+struct BranchProvider {
+    local: bool,
+    remote: bool,
+}
+
+impl Provider for BranchProvider {
+    fn load(&self, repo: &Repository) -> Source {
+        let (local, remote) = git::get_branches(repo);
+        let mut branches = vec![];
+        if self.local {
+            branches.extend(
+                local
+                    .iter()
+                    .map(|name| (name, BranchType::Local, ItemKind::Branch)),
+            );
+        }
+        if self.remote {
+            branches.extend(
+                remote
+                    .iter()
+                    .map(|name| (name, BranchType::Remote, ItemKind::RemoteBranch)),
+            );
+        }
+
+        let items = branches
+            .into_iter()
+            .map(|(name, typ, kind)| {
+                let item = SourceItem::new(name, kind);
+                match repo
+                    .find_branch(name, typ)
+                    .and_then(|branch| branch.get().peel_to_commit())
+                {
+                    Ok(commit) => item.with_commit(&commit),
+                    Err(_) => item,
+                }
+            })
+            .collect();
+        Source::Ready(items)
+    }
+}
+
+struct TagProvider;
+
+impl Provider for TagProvider {
+    fn load(&self, repo: &Repository) -> Source {
+        let items = git::get_tags(repo)
+            .iter()
+            .map(|name| {
+                let item = SourceItem::new(name, ItemKind::Tag);
+                match repo
+                    .revparse_single(&format!("refs/tags/{}", name))
+                    .and_then(|tag| tag.peel_to_commit())
+                {
+                    Ok(commit) => item.with_commit(&commit),
+                    Err(_) => item,
+                }
+            })
+            .collect();
+        Source::Ready(items)
+    }
+}
+
+struct CommitProvider {
+    unmerged: bool,
+}
+
+impl Provider for CommitProvider {
+    fn load(&self, repo: &Repository) -> Source {
+        let unmerged = self.unmerged;
+        stream(repo.path().to_owned(), move |path, tx| {
+            let repo = Repository::open(path)?;
+            let mut chunk = vec![];
+            for oid in git::walk_log(&repo, unmerged)?.take(COMMIT_LIMIT) {
+                let commit = repo.find_commit(oid?)?;
+                let author = commit.author();
+                let mut item = SourceItem::new(&git::commit_line(&commit), ItemKind::Commit);
+                item.oid = Some(commit.id().to_string());
+                item.author = author.name().map(|name| name.to_owned());
+                item.description = format!(
+                    "{}, {}",
+                    author.name().unwrap_or_default(),
+                    git::format_time(&commit.time())
+                );
+                chunk.push(item);
+
+                if chunk.len() == CHUNK_SIZE && !send(&tx, &mut chunk) {
+                    break;
+                }
+            }
+            send(&tx, &mut chunk);
+            Ok(())
+        })
+    }
+}
+
+struct StashProvider;
+
+impl Provider for StashProvider {
+    fn load(&self, repo: &Repository) -> Source {
+        let items = git::get_stashes(repo)
+            .iter()
+            .map(|name| SourceItem::new(name, ItemKind::Stash))
+            .collect();
+        Source::Ready(items)
+    }
+}
+
+struct FileProvider {
+    unstaged: bool,
+}
+
+impl Provider for FileProvider {
+    fn load(&self, repo: &Repository) -> Source {
+        let files = if self.unstaged {
+            git::get_unstaged_files(repo)
+        } else {
+            git::get_tracked_files(repo)
+        };
+        let items = files
+            .iter()
+            .map(|name| SourceItem::new(name, ItemKind::File))
+            .collect();
+        Source::Ready(items)
+    }
+}
+
+/// HEAD and the local branches, the refs with a reflog
+struct RefProvider;
+
+impl Provider for RefProvider {
+    fn load(&self, repo: &Repository) -> Source {
+        let (local, _) = git::get_branches(repo);
+        let mut items = vec![SourceItem::new("HEAD", ItemKind::Ref)];
+        items.extend(
+            local
+                .iter()
+                .map(|name| SourceItem::new(name, ItemKind::Branch)),
+        );
+        Source::Ready(items)
+    }
+}
+
+/// Initialized submodules, the ones that can be opened
+struct SubmoduleProvider;
+
+impl Provider for SubmoduleProvider {
+    fn load(&self, repo: &Repository) -> Source {
+        let submodules = match git::get_submodules(repo) {
+            Ok(submodules) => submodules,
+            Err(err) => {
+                error!("{:#}", err);
+                vec![]
+            }
+        };
+
+        let items = submodules
+            .into_iter()
+            .filter(|submodule| submodule.initialized)
+            .map(|submodule| {
+                let mut item = SourceItem::new(&submodule.path, ItemKind::Submodule);
+                item.oid = submodule.checked_out.map(|oid| oid.to_string());
+                item.description = submodule.name;
+                item
+            })
+            .collect();
+        Source::Ready(items)
+    }
+}
+
+struct RemoteProvider;
+
+impl Provider for RemoteProvider {
+    fn load(&self, repo: &Repository) -> Source {
+        let remotes = match git::get_remotes(repo) {
+            Ok(remotes) => remotes,
+            Err(err) => {
+                error!("Failed to list remotes: {:#}", err);
+                vec![]
+            }
+        };
+
+        let items = remotes
+            .into_iter()
+            .map(|(name, url)| {
+                let mut item = SourceItem::new(&name, ItemKind::Remote);
+                item.description = url;
+                item
+            })
+            .collect();
+        Source::Ready(items)
+    }
+}
+
+/// Lines printed by a shell command run in the working directory
+struct ShellProvider(String);
+
+impl Provider for ShellProvider {
+    fn load(&self, repo: &Repository) -> Source {
+        let workdir = repo.workdir().unwrap_or_else(|| repo.path()).to_owned();
+        let cmd = self.0.clone();
+        stream(workdir, move |workdir, tx| {
+            debug!("Running source command: {}", cmd);
+            let mut child = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(&cmd)
+                .current_dir(workdir)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .map_err(|err| anyhow!("Failed to run {}: {}", cmd, err))?;
+
+            let stdout = child
+                .stdout
+                .take()
+                .ok_or(anyhow!("No output from {}", cmd))?;
+            let mut chunk = vec![];
+            for line in BufReader::new(stdout).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                chunk.push(SourceItem::new(&line, ItemKind::Line));
+
+                if chunk.len() == CHUNK_SIZE && !send(&tx, &mut chunk) {
+                    let _ = child.kill();
+                    break;
+                }
+            }
+            send(&tx, &mut chunk);
+            child.wait()?;
+            Ok(())
+        })
+    }
+}
+
+/// Load items on a separate thread, `load` sends them in chunks
+fn stream<F>(path: PathBuf, load: F) -> Source
+where
+    F: FnOnce(PathBuf, Sender<Vec<SourceItem>>) -> Result<()> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        if let Err(err) = load(path, tx) {
+            error!("Failed to load fuzzybar items: {:#}", err);
+        }
+    });
+    Source::Stream(rx)
+}
+
+/// Send a chunk of items. Returns false once nobody is listening anymore.
+fn send(tx: &Sender<Vec<SourceItem>>, chunk: &mut Vec<SourceItem>) -> bool {
+    if chunk.is_empty() {
+        return true;
+    }
+    tx.send(std::mem::replace(chunk, vec![])).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests;
+
+    fn collect(source: Source) -> Vec<SourceItem> {
+        match source {
+            Source::Ready(items) => items,
+            Source::Stream(rx) => rx.iter().flatten().collect(),
+        }
+    }
+
+    #[test]
+    fn branches_should_be_described_by_their_tip() {
+        let (_td, repo) = tests::repo_init();
+        let _ = tests::branch(&repo, "b1");
+
+        let items = collect(
+            provider(Command::BranchCheckout, None, None)
+                .unwrap()
+                .load(&repo),
+        );
+        let b1 = items.iter().find(|item| item.name == "b1").unwrap();
+        assert_eq!(b1.kind, ItemKind::Branch);
+        assert!(b1.oid.is_some());
+        assert!(b1.description.ends_with("initial"));
+    }
+
+    #[test]
+    fn branches_should_be_filtered() {
+        let (_td, repo) = tests::repo_init();
+        let head = repo.refname_to_id("HEAD").unwrap();
+        repo.reference("refs/remotes/origin/r1", head, false, "test")
+            .unwrap();
+
+        let names = |filter| {
+            collect(
+                provider(Command::BranchCheckout, None, filter)
+                    .unwrap()
+                    .load(&repo),
+            )
+            .into_iter()
+            .map(|item| item.name)
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(names(None), vec!["master", "origin/r1"]);
+        assert_eq!(names(Some(SourceFilter::Local)), vec!["master"]);
+        assert_eq!(names(Some(SourceFilter::Remote)), vec!["origin/r1"]);
+    }
+
+    #[test]
+    fn commits_should_be_streamed() {
+        let (_td, repo) = tests::repo_init();
+        let oid = tests::commit_file(&repo, "a.txt", "a");
+
+        let items = collect(
+            provider(Command::CommitCheckout, None, None)
+                .unwrap()
+                .load(&repo),
+        );
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].oid, Some(oid.to_string()));
+        assert!(items[0].name.ends_with("Update a.txt"));
+    }
+
+    #[test]
+    fn shell_output_should_replace_the_source() {
+        let (_td, repo) = tests::repo_init();
+
+        let provider =
+            provider(Command::BranchCheckout, Some("printf 'a\\n\\nb\\n'"), None).unwrap();
+        let names = collect(provider.load(&repo))
+            .into_iter()
+            .map(|item| item.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["a", "b"]);
+    }
+}
@@ -0,0 +1,233 @@
+//! Query syntax of the fuzzybar, after fzf's extended search.
+//!
+//! A query is a list of space separated terms, all of which have to match:
+//!
+//! | Term          | Matches items                              |
+//! |---------------|--------------------------------------------|
+//! | `foo`         | fuzzy matching `foo`                       |
+//! | `'foo`        | containing `foo`                           |
+//! | `^foo`        | starting with `foo`                        |
+//! | `foo$`        | ending with `foo`                          |
+//! | `!foo`        | not containing `foo` (or `!^foo`, `!foo$`) |
+//! | `remote:name` | branches of the remote `name`              |
+//! | `author:name` | commits by an author containing `name`     |
+
+use crate::fuzzy::{exact_match, fuzzy_match, Anchor, Match};
+use crate::provider::{ItemKind, SourceItem};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Remote,
+    Author,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Term {
+    Fuzzy(String),
+    Exact(String, Anchor),
+    Field(Field, String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Atom {
+    pub term: Term,
+    pub negated: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Query {
+    pub atoms: Vec<Atom>,
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Query {
+        let atoms = query.split_whitespace().filter_map(parse_atom).collect();
+        Query { atoms }
+    }
+
+    /// Match the item against every term. The score and positions of the
+    /// terms matching the name are combined.
+    pub fn matches(&self, item: &SourceItem) -> Option<Match> {
+        let mut score = 0;
+        let mut positions = vec![];
+
+        for atom in self.atoms.iter() {
+            let m = match &atom.term {
+                Term::Fuzzy(text) => fuzzy_match(text, &item.name),
+                Term::Exact(text, anchor) => exact_match(text, &item.name, *anchor),
+                Term::Field(field, value) => match_field(item, *field, value),
+            };
+
+            match (m, atom.negated) {
+                (Some(_), true) | (None, false) => return None,
+                (None, true) => (),
+                (Some(m), false) => {
+                    score += m.score;
+                    positions.extend(m.positions);
+                }
+            }
+        }
+
+        positions.sort();
+        positions.dedup();
+        Some(Match { score, positions })
+    }
+}
+
+fn parse_atom(word: &str) -> Option<Atom> {
+    let (negated, word) = match word.strip_prefix('!') {
+        Some(word) => (true, word),
+        None => (false, word),
+    };
+
+    if let Some((field, value)) = parse_field(word) {
+        return Some(Atom {
+            term: Term::Field(field, value.to_owned()),
+            negated,
+        });
+    }
+
+    let (exact, word) = match word.strip_prefix('\'') {
+        Some(word) => (true, word),
+        None => (false, word),
+    };
+    let (start, word) = match word.strip_prefix('^') {
+        Some(word) => (true, word),
+        None => (false, word),
+    };
+    let (end, word) = match word.strip_suffix('$') {
+        Some(word) if !word.is_empty() => (true, word),
+        _ => (false, word),
+    };
+
+    if word.is_empty() {
+        return None;
+    }
+
+    let anchor = match (start, end) {
+        (true, true) => Anchor::Both,
+        (true, false) => Anchor::Start,
+        (false, true) => Anchor::End,
+        (false, false) => Anchor::Anywhere,
+    };
+
+    // Like in fzf a negated term is always matched exactly
+    let term = if exact || negated || anchor != Anchor::Anywhere {
+        Term::Exact(word.to_owned(), anchor)
+    } else {
+        Term::Fuzzy(word.to_owned())
+    };
+    Some(Atom { term, negated })
+}
+
+/// Split `field:value`. Words with an unknown field name are plain terms.
+fn parse_field(word: &str) -> Option<(Field, &str)> {
+    let idx = word.find(':')?;
+    let value = &word[idx + 1..];
+    if value.is_empty() {
+        return None;
+    }
+
+    match &word[..idx] {
+        "remote" => Some((Field::Remote, value)),
+        "author" => Some((Field::Author, value)),
+        _ => None,
+    }
+}
+
+/// Field filters don't highlight anything in the name
+fn match_field(item: &SourceItem, field: Field, value: &str) -> Option<Match> {
+    let found = match field {
+        Field::Remote => match item.kind {
+            ItemKind::RemoteBranch => item.name.split('/').next(),
+            ItemKind::Remote => Some(item.name.as_str()),
+            _ => None,
+        },
+        Field::Author => item.author.as_deref(),
+    }?;
+
+    let matches = match field {
+        Field::Remote => exact_match(value, found, Anchor::Both).is_some(),
+        Field::Author => exact_match(value, found, Anchor::Anywhere).is_some(),
+    };
+    if matches {
+        Some(Match {
+            score: 0,
+            positions: vec![],
+        })
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names<'a>(query: &str, items: &'a [SourceItem]) -> Vec<&'a str> {
+        let query = Query::parse(query);
+        items
+            .iter()
+            .filter(|item| query.matches(item).is_some())
+            .map(|item| item.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn query_should_be_parsed_into_atoms() {
+        let query = Query::parse("fb 'bar ^feat !wip rc$ !^tmp ^main$ author:kim ! '");
+        let atom = |term, negated| Atom { term, negated };
+        let exact = |text: &str, anchor| Term::Exact(text.to_owned(), anchor);
+        assert_eq!(
+            query.atoms,
+            vec![
+                atom(Term::Fuzzy("fb".to_owned()), false),
+                atom(exact("bar", Anchor::Anywhere), false),
+                atom(exact("feat", Anchor::Start), false),
+                atom(exact("wip", Anchor::Anywhere), true),
+                atom(exact("rc", Anchor::End), false),
+                atom(exact("tmp", Anchor::Start), true),
+                atom(exact("main", Anchor::Both), false),
+                atom(Term::Field(Field::Author, "kim".to_owned()), false),
+            ]
+        );
+        assert_eq!(Query::parse("fix: foo:bar").atoms.len(), 2);
+    }
+
+    #[test]
+    fn all_terms_should_match() {
+        let items = ["feature/bar", "feature/bar-wip", "fix/bar", "main"]
+            .iter()
+            .map(|name| SourceItem::new(name, ItemKind::Branch))
+            .collect::<Vec<_>>();
+
+        assert_eq!(names("", &items).len(), 4);
+        assert_eq!(names("bar !wip", &items), vec!["feature/bar", "fix/bar"]);
+        assert_eq!(
+            names("^feat 'bar", &items),
+            vec!["feature/bar", "feature/bar-wip"]
+        );
+        assert_eq!(names("bar$", &items), vec!["feature/bar", "fix/bar"]);
+        assert_eq!(names("^main$", &items), vec!["main"]);
+
+        let m = Query::parse("^fe bar$").matches(&items[0]).unwrap();
+        assert_eq!(m.positions, vec![0, 1, 8, 9, 10]);
+    }
+
+    #[test]
+    fn field_filters_should_match_metadata() {
+        let mut commit = SourceItem::new("1234567 Fix it", ItemKind::Commit);
+        commit.author = Some("Kim Doe".to_owned());
+        let items = vec![
+            SourceItem::new("origin/main", ItemKind::RemoteBranch),
+            SourceItem::new("upstream/main", ItemKind::RemoteBranch),
+            SourceItem::new("origin", ItemKind::Branch),
+            commit,
+        ];
+
+        assert_eq!(names("remote:origin", &items), vec!["origin/main"]);
+        assert_eq!(names("main !remote:origin", &items), vec!["upstream/main"]);
+        assert_eq!(names("author:kim fix", &items), vec!["1234567 Fix it"]);
+        assert!(names("author:alex", &items).is_empty());
+    }
+}
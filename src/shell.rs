@@ -0,0 +1,87 @@
+//! Shell commands defined on keymap entries, e.g.
+//! `shell = "git log --oneline {selection}"`. Placeholders are replaced by
+//! quoted values:
+//!
+//! - `{selection}`: the fuzzybar items the command runs on
+//! - `{branch}`: the checked out branch, or `HEAD` if it's detached
+//! - `{repo_root}`: the working directory of the repository
+//! - `{head_oid}`: the id of the checked out commit
+
+use anyhow::{anyhow, Result};
+use git2::Repository;
+
+const SELECTION: &str = "{selection}";
+const BRANCH: &str = "{branch}";
+const REPO_ROOT: &str = "{repo_root}";
+const HEAD_OID: &str = "{head_oid}";
+
+/// Whether the command runs on fuzzybar items, which have to be picked first
+pub fn needs_selection(template: &str) -> bool {
+    template.contains(SELECTION)
+}
+
+/// The command with its placeholders replaced
+pub fn expand(template: &str, repo: &Repository, selections: &[String]) -> Result<String> {
+    let mut cmd = template.to_owned();
+
+    if cmd.contains(SELECTION) {
+        let selection = selections
+            .iter()
+            .map(|sel| quote(sel))
+            .collect::<Vec<_>>()
+            .join(" ");
+        cmd = cmd.replace(SELECTION, &selection);
+    }
+    if cmd.contains(BRANCH) {
+        let head = repo.head()?;
+        let branch = if repo.head_detached()? {
+            "HEAD"
+        } else {
+            head.shorthand()
+                .ok_or(anyhow!("Branch name isn't valid utf-8"))?
+        };
+        cmd = cmd.replace(BRANCH, &quote(branch));
+    }
+    if cmd.contains(REPO_ROOT) {
+        let root = repo.workdir().unwrap_or_else(|| repo.path());
+        let root = root.to_str().ok_or(anyhow!("Path isn't valid utf-8"))?;
+        cmd = cmd.replace(REPO_ROOT, &quote(root));
+    }
+    if cmd.contains(HEAD_OID) {
+        let oid = repo.head()?.peel_to_commit()?.id();
+        cmd = cmd.replace(HEAD_OID, &oid.to_string());
+    }
+
+    Ok(cmd)
+}
+
+/// Quote `arg` so the shell passes it on as is
+fn quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests;
+
+    #[test]
+    fn placeholders_should_be_replaced_quoted() {
+        let (_td, repo) = tests::repo_init();
+        let head = repo.head().unwrap().peel_to_commit().unwrap().id();
+        let root = repo.workdir().unwrap().to_str().unwrap().to_owned();
+
+        let selections = vec!["a b".to_owned(), "it's".to_owned()];
+        let cmd = expand(
+            "echo {selection} {branch} {head_oid} {repo_root}",
+            &repo,
+            &selections,
+        )
+        .unwrap();
+        let expected = format!("echo 'a b' 'it'\\''s' 'master' {} '{}'", head, root);
+        assert_eq!(cmd, expected);
+
+        assert!(needs_selection("git show {selection}"));
+        assert!(!needs_selection("git fetch --all"));
+    }
+}
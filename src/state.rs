@@ -1,10 +1,24 @@
+use crate::git;
+use crate::keymap::{self, KeyMap, KeyNode, KeySeq};
+use crate::provider::{ItemKind, SourceItem};
+use crate::query::Query;
+use crate::shell;
+use crate::theme::{self, ThemeState};
+use crate::widgets::bisect::Bisect;
+use crate::widgets::filelog::FileLog;
+use crate::widgets::fuzzybar::FUZZYBAR_ROWS;
 use crate::widgets::header::RepoHeader;
+use crate::widgets::listview::{ListRow, ListView};
+use crate::widgets::output::ShellOutput;
+use crate::widgets::reflog::Reflog;
 use crate::widgets::status::RepoStatusDetail;
+use crate::widgets::worktrees::Worktrees;
 use druid::{Data, Lens, Size};
 use git2::Repository;
 use im::{vector, Vector};
-use serde::{Deserialize, Deserializer};
-use std::collections::BTreeMap;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::rc::Rc;
 
 #[derive(Clone, Data, Lens)]
@@ -13,17 +27,117 @@ pub struct AppState {
     pub win_size: Size,
     pub repo_header: RepoHeader,
     pub repo_status: RepoStatusDetail,
+    pub view: View,
+    pub file_log: FileLog,
+    pub reflog: Reflog,
+    pub bisect: Bisect,
+    pub worktrees: Worktrees,
     pub cheatsheet: CheatSheetState,
     pub fuzzybar: FuzzybarState,
     pub git: GitState,
+    /// Messages for the user that don't belong to a view
+    pub notifications: Vector<String>,
+    /// Problems with the config, shown along with the notifications until the
+    /// config loads again
+    pub config_errors: Vector<String>,
+    pub output: ShellOutput,
+    pub theme: ThemeState,
+}
+
+impl AppState {
+    /// Point the app at another repository, e.g. a worktree or a submodule
+    pub fn switch_repo(&mut self, repo: Repository) {
+        info!(
+            "Switching to {:?}",
+            repo.workdir().unwrap_or_else(|| repo.path())
+        );
+        self.repo = Rc::new(repo);
+        self.refresh();
+        self.view = View::Status;
+    }
+
+    /// Run a shell command of the keymap on `selections`, its output is shown
+    /// in the output panel
+    pub fn run_shell(&mut self, template: &str, selections: &[String]) {
+        match shell::expand(template, &self.repo, selections) {
+            Ok(cmd) => self.output = ShellOutput::start(cmd),
+            Err(err) => {
+                error!("Failed to expand {}: {:#}", template, err);
+                self.notifications
+                    .push_back(format!("Can't run {}: {:#}", template, err));
+            }
+        }
+    }
+
+    /// Use the settings of a newly loaded config. The cheatsheet is only reset
+    /// if the keymap changed, its keys may lead nowhere anymore.
+    pub fn apply_config(&mut self, config: Config) {
+        if config.keymap.map != self.cheatsheet.keymap {
+            self.cheatsheet.keymap = config.keymap.map;
+            self.cheatsheet.path = KeySeq::default();
+            self.cheatsheet.transient = None;
+            self.cheatsheet.search = None;
+            self.cheatsheet.picked = None;
+        }
+        self.cheatsheet.persist_args = config.transient.persist;
+        self.theme.reload(config.appearance, config.themes);
+    }
+
+    /// Reload everything derived from the repository after a command changed it
+    pub fn refresh(&mut self) {
+        match RepoHeader::new(&self.repo) {
+            Ok(header) => self.repo_header = header,
+            Err(err) => error!("Failed to refresh header: {}", err),
+        }
+        self.repo_status = RepoStatusDetail::new(&self.repo);
+
+        let (local, remote) = git::get_branches(&self.repo);
+        let mut all_branches = vector![];
+        all_branches.extend(local.iter().cloned());
+        all_branches.extend(remote.iter().cloned());
+        self.git = GitState {
+            local_branches: local,
+            remote_branches: remote,
+            all_branches,
+        };
+    }
 }
 
 #[derive(Clone, Data, Lens, Debug)]
 pub struct CheatSheetState {
     pub is_hidden: bool,
     pub keymap: KeyMap,
-    pub current_node: u8,
-    pub current_level: KeyMapLevel,
+    /// Keys pressed so far, leading to the shown part of the keymap
+    pub path: KeySeq,
+    /// Menu of the switches of the command about to run, see
+    /// [`crate::transient`]
+    pub transient: Option<Transient>,
+    /// Search of all the entries of the keymap, started with `/`
+    pub search: Option<CheatSearch>,
+    /// Entry picked in the command palette, run once the cheatsheet has the
+    /// focus back
+    pub picked: Option<keymap::Entry>,
+    /// Args last used per keymap entry, by its key sequence
+    pub remembered: im::HashMap<String, CmdArgs>,
+    /// Keep the remembered args across sessions
+    pub persist_args: bool,
+}
+
+#[derive(Clone, Data, Lens, Debug)]
+pub struct Transient {
+    /// Keys leading to `node`
+    pub seq: KeySeq,
+    pub node: KeyNode,
+    pub args: CmdArgs,
+    /// Remotes to choose from if the command takes one
+    pub remotes: Vector<String>,
+}
+
+#[derive(Clone, Data, Lens, Debug, Default)]
+pub struct CheatSearch {
+    pub query: String,
+    /// Index of the result run with `RET`
+    pub selected: usize,
 }
 
 #[derive(Clone, Data, Lens, Debug)]
@@ -31,44 +145,150 @@ pub struct FuzzybarState {
     pub is_hidden: bool,
     pub cmd: Command,
     pub query: String,
-    pub source: Vector<String>,
-    pub filtered: Vector<ListItem>,
+    pub source: Vector<SourceItem>,
+    /// Shell command listing the items instead of the command's provider
+    pub shell_source: Option<String>,
+    /// Shell command run on the selections instead of the command
+    pub shell_cmd: Option<String>,
+    pub args: CmdArgs,
+    /// Matches of the query, of which only a window of
+    /// [`FUZZYBAR_ROWS`] rows is laid out
+    pub filtered: ListView<ListItem>,
+    /// Names of the items marked for commands that accept several of them
+    pub marked: Vector<String>,
+    /// How often and recently items were picked before, see [`crate::history`]
+    pub frecencies: im::HashMap<String, i64>,
+    /// Lines describing the selected item, empty if the command has no preview
+    pub preview: Vector<String>,
+    /// Why the argument typed for a prompting command is rejected
+    pub error: Option<String>,
 }
 
 #[derive(Clone, Data, Lens, Debug, PartialEq)]
 pub struct ListItem {
     pub name: String,
+    pub kind: ItemKind,
+    pub oid: Option<String>,
+    pub description: String,
     pub selected: bool,
+    pub marked: bool,
+    /// Char indices of `name` matched by the query
+    pub positions: Vector<usize>,
 }
 
-impl FuzzybarState {
-    pub fn filter(&mut self) {
-        let mut new = vector![];
+impl ListRow for ListItem {
+    fn text(&self) -> String {
+        self.name.clone()
+    }
 
-        let mut selected = false;
-        let mut found = true;
+    fn is_selected(&self) -> bool {
+        self.selected
+    }
 
-        for name in self.source.iter() {
-            // if new.len() >= 20 {
-            //     break;
-            // }
+    fn set_selected(&mut self, selected: bool) {
+        self.selected = selected;
+    }
+}
 
-            if name.contains(&self.query) {
-                if found && !selected {
-                    selected = true;
-                    found = false;
-                }
+impl FuzzybarState {
+    /// A hidden fuzzybar for `cmd`, with nothing to pick from yet
+    pub fn new(cmd: Command) -> Self {
+        FuzzybarState {
+            is_hidden: true,
+            cmd,
+            query: "".to_owned(),
+            source: vector![],
+            shell_source: None,
+            shell_cmd: None,
+            args: CmdArgs::default(),
+            filtered: ListView::with_rows(vector![], FUZZYBAR_ROWS),
+            marked: vector![],
+            frecencies: im::HashMap::new(),
+            preview: vector![],
+            error: None,
+        }
+    }
 
-                new.push_back(ListItem {
-                    name: name.to_owned(),
-                    selected,
-                });
+    /// Match the query against the source and rank the matches by score,
+    /// boosted by the frecency of the items. See [`crate::query`] for the
+    /// query syntax.
+    pub fn filter(&mut self) {
+        let query = Query::parse(&self.query);
+        let mut matches = self
+            .source
+            .iter()
+            .filter_map(|item| query.matches(item).map(|m| (item, m)))
+            .map(|(item, mut m)| {
+                m.score += self.frecencies.get(&item.name).cloned().unwrap_or(0);
+                (item, m)
+            })
+            .collect::<Vec<_>>();
+
+        // Stable, so equally scored items stay in source order
+        matches.sort_by(|a, b| b.1.score.cmp(&a.1.score));
+
+        let filtered = matches
+            .into_iter()
+            .map(|(item, m)| ListItem {
+                name: item.name.clone(),
+                kind: item.kind,
+                oid: item.oid.clone(),
+                description: item.description.clone(),
+                selected: false,
+                marked: self.marked.contains(&item.name),
+                positions: m.positions.into_iter().collect(),
+            })
+            .collect();
+        self.filtered.set_items(filtered);
+    }
 
-                selected = false;
+    /// Mark or unmark the selected item
+    pub fn toggle_mark(&mut self) {
+        let idx = self.filtered.selected_idx;
+        let item = match self.filtered.items.get_mut(idx) {
+            Some(item) => item,
+            None => return,
+        };
+
+        item.marked = !item.marked;
+        if item.marked {
+            self.marked.push_back(item.name.clone());
+        } else {
+            self.marked.retain(|name| *name != item.name);
+        }
+        self.filtered.update_visible();
+    }
+
+    /// Mark every item matching the query
+    pub fn mark_all(&mut self) {
+        for item in self.filtered.items.iter_mut() {
+            if !item.marked {
+                item.marked = true;
+                self.marked.push_back(item.name.clone());
             }
         }
+        self.filtered.update_visible();
+    }
+
+    pub fn unmark_all(&mut self) {
+        self.marked.clear();
+        for item in self.filtered.items.iter_mut() {
+            item.marked = false;
+        }
+        self.filtered.update_visible();
+    }
 
-        self.filtered = new;
+    /// The marked items in the order they were marked, or the selected item
+    /// if nothing is marked
+    pub fn selections(&self) -> Vec<String> {
+        if self.marked.is_empty() {
+            self.filtered
+                .selected()
+                .map(|item| vec![item.name.clone()])
+                .unwrap_or_default()
+        } else {
+            self.marked.iter().cloned().collect()
+        }
     }
 }
 
@@ -76,167 +296,395 @@ impl FuzzybarState {
 pub enum Command {
     ShowMenu,
     BranchCheckout,
+    TagCheckout,
+    CommitCheckout,
     Commit,
+    FileLog,
+    Reflog,
+    Bisect,
+    Worktrees,
+    WorktreeAdd,
+    SubmoduleInit,
+    SubmoduleUpdate,
+    SubmoduleSync,
+    SubmoduleOpen,
+    StashApply,
+    BranchDelete,
+    CherryPick,
+    StageFiles,
+    RemoteFetch,
+    Push,
+    BranchCreate,
+    TagCreate,
+    StashSave,
+    ThemeSwitch,
+    Palette,
 }
 
-#[derive(Clone, Data, Lens, Debug)]
-pub struct GitState {
-    pub local_branches: Vector<String>,
-    pub remote_branches: Vector<String>,
-    pub all_branches: Vector<String>,
-}
+impl Command {
+    /// Every command, listed by the command palette if the keymap doesn't
+    /// bind it
+    pub const ALL: &'static [Command] = &[
+        Command::ShowMenu,
+        Command::BranchCheckout,
+        Command::TagCheckout,
+        Command::CommitCheckout,
+        Command::Commit,
+        Command::FileLog,
+        Command::Reflog,
+        Command::Bisect,
+        Command::Worktrees,
+        Command::WorktreeAdd,
+        Command::SubmoduleInit,
+        Command::SubmoduleUpdate,
+        Command::SubmoduleSync,
+        Command::SubmoduleOpen,
+        Command::StashApply,
+        Command::BranchDelete,
+        Command::CherryPick,
+        Command::StageFiles,
+        Command::RemoteFetch,
+        Command::Push,
+        Command::BranchCreate,
+        Command::TagCreate,
+        Command::StashSave,
+        Command::ThemeSwitch,
+        Command::Palette,
+    ];
+
+    /// Whether the command can run on several fuzzybar items at once
+    pub fn accepts_many(&self) -> bool {
+        match self {
+            Command::BranchDelete | Command::CherryPick | Command::StageFiles => true,
+            _ => false,
+        }
+    }
 
-pub type KeyMap = Rc<BTreeMap<u8, L1Node>>;
-pub type KeyMapL2 = Rc<BTreeMap<u8, L2Node>>;
+    /// Prompt of commands that take a typed argument instead of a fuzzybar
+    /// item, the query is submitted as is
+    pub fn prompt(&self) -> Option<&'static str> {
+        match self {
+            Command::BranchCreate => Some("Branch name"),
+            Command::TagCreate => Some("Tag name"),
+            Command::StashSave => Some("Stash message"),
+            Command::Commit => Some("Commit message"),
+            _ => None,
+        }
+    }
 
-#[derive(Clone, Data, Debug, Deserialize)]
-pub struct L1Node {
-    #[serde(deserialize_with = "de_u8_from_string")]
-    pub key: u8,
-    pub name: String,
-    #[serde(deserialize_with = "de_keymap_l2")]
-    pub next: KeyMapL2,
-}
+    /// Switches the command understands, toggled in its transient menu
+    pub fn switches(&self) -> &'static [Switch] {
+        match self {
+            Command::Commit => &[Switch::All, Switch::Amend],
+            Command::Push => &[Switch::ForceWithLease, Switch::Force, Switch::SetUpstream],
+            Command::RemoteFetch => &[Switch::Prune, Switch::Tags],
+            Command::BranchDelete => &[Switch::Force],
+            _ => &[],
+        }
+    }
 
-#[derive(Clone, Data, Debug, Deserialize)]
-pub struct L2Node {
-    #[serde(deserialize_with = "de_u8_from_string")]
-    pub key: u8,
-    pub name: String,
-    pub command: Command,
-}
+    /// Whether the command talks to a remote, which can be preset instead of
+    /// picked in the fuzzybar
+    pub fn takes_remote(&self) -> bool {
+        match self {
+            Command::Push | Command::RemoteFetch => true,
+            _ => false,
+        }
+    }
 
-#[derive(Clone, PartialEq, Data, Debug)]
-pub enum KeyMapLevel {
-    L1,
-    L2(u8),
+    /// Whether the cheatsheet shows a transient menu before running the command
+    pub fn has_transient(&self) -> bool {
+        !self.switches().is_empty() || self.takes_remote()
+    }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct KeyMapConfig {
-    #[serde(deserialize_with = "de_keymap")]
-    pub map: KeyMap,
+/// Options of a keymap node passed on to its command
+#[derive(Clone, Data, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CmdArgs {
+    #[serde(default)]
+    pub switches: Rc<BTreeSet<Switch>>,
+    /// Remote to fetch or push, instead of picking one in the fuzzybar
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    /// Narrow down the branches listed in the fuzzybar
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<SourceFilter>,
+    /// Commit a created branch starts at, instead of HEAD
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct Config {
-    pub keymap: KeyMapConfig,
+impl CmdArgs {
+    pub fn has(&self, switch: Switch) -> bool {
+        self.switches.contains(&switch)
+    }
 }
 
-fn de_u8_from_string<'de, D>(deserializer: D) -> Result<u8, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    struct DeStrAsU8;
-
-    impl<'de> serde::de::Visitor<'de> for DeStrAsU8 {
-        type Value = u8;
-
-        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-            formatter.write_str("single ascii char")
-        }
+/// Flags changing what a command does, named like the git options they stand
+/// for. Commands ignore the ones they don't know.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Data, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Switch {
+    /// Push: overwrite the remote branch, if it's where it was last fetched
+    ForceWithLease,
+    /// Push: overwrite the remote branch. BranchDelete: delete unmerged
+    /// branches too.
+    Force,
+    /// Push: make the remote branch the upstream of the pushed one
+    SetUpstream,
+    /// RemoteFetch: remove remote branches that are gone from the remote
+    Prune,
+    /// RemoteFetch: fetch all tags
+    Tags,
+    /// Commit: stage the changes of all tracked files first
+    All,
+    /// Commit: replace the last commit
+    Amend,
+}
 
-        fn visit_string<E>(self, value: String) -> Result<u8, E>
-        where
-            E: serde::de::Error,
-        {
-            Ok(*value.as_bytes().get(0).unwrap())
+impl Switch {
+    /// The git option the switch stands for
+    pub fn flag(&self) -> &'static str {
+        match self {
+            Switch::ForceWithLease => "--force-with-lease",
+            Switch::Force => "--force",
+            Switch::SetUpstream => "--set-upstream",
+            Switch::Prune => "--prune",
+            Switch::Tags => "--tags",
+            Switch::All => "--all",
+            Switch::Amend => "--amend",
         }
+    }
 
-        fn visit_str<E>(self, value: &str) -> Result<u8, E>
-        where
-            E: serde::de::Error,
-        {
-            Ok(*value.as_bytes().get(0).unwrap())
+    /// Key toggling the switch in a transient menu
+    pub fn key(&self) -> &'static str {
+        match self {
+            Switch::ForceWithLease => "f",
+            Switch::Force => "F",
+            Switch::SetUpstream => "u",
+            Switch::Prune => "p",
+            Switch::Tags => "t",
+            Switch::All => "a",
+            Switch::Amend => "A",
         }
     }
+}
+
+#[derive(Clone, Copy, PartialEq, Data, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceFilter {
+    /// Only local branches
+    Local,
+    /// Only remote branches
+    Remote,
+}
 
-    deserializer.deserialize_str(DeStrAsU8)
+/// The main panel shown below the repository header
+#[derive(Clone, Copy, PartialEq, Data, Debug)]
+pub enum View {
+    Status,
+    FileLog,
+    Reflog,
+    Bisect,
+    Worktrees,
 }
 
-fn de_keymap<'de, D>(deserializer: D) -> Result<Rc<BTreeMap<u8, L1Node>>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let str_map = BTreeMap::<String, L1Node>::deserialize(deserializer)?;
-    let result = str_map
-        .into_iter()
-        .map(|(k, v)| {
-            let ku8 = *k.as_bytes().get(0).unwrap();
-            (ku8, v)
-        })
-        .collect();
+#[derive(Clone, Data, Lens, Debug)]
+pub struct GitState {
+    pub local_branches: Vector<String>,
+    pub remote_branches: Vector<String>,
+    pub all_branches: Vector<String>,
+}
 
-    Ok(Rc::new(result))
+#[derive(Debug, Deserialize)]
+pub struct KeyMapConfig {
+    #[serde(deserialize_with = "keymap::de_keymap")]
+    pub map: KeyMap,
 }
 
-fn de_keymap_l2<'de, D>(deserializer: D) -> Result<Rc<BTreeMap<u8, L2Node>>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let str_map = BTreeMap::<String, L2Node>::deserialize(deserializer)?;
-    let result = str_map
-        .into_iter()
-        .map(|(k, v)| {
-            let ku8 = *k.as_bytes().get(0).unwrap();
-            (ku8, v)
-        })
-        .collect();
+#[derive(Debug, Default, Deserialize)]
+pub struct TransientConfig {
+    /// Remember the args of transient menus across sessions
+    #[serde(default)]
+    pub persist: bool,
+}
 
-    Ok(Rc::new(result))
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    pub keymap: KeyMapConfig,
+    #[serde(default)]
+    pub transient: TransientConfig,
+    pub appearance: theme::AppearanceConfig,
+    pub themes: BTreeMap<String, theme::Palette>,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn items(names: &[&str]) -> Vector<SourceItem> {
+        names
+            .iter()
+            .map(|name| SourceItem::new(name, ItemKind::Line))
+            .collect()
+    }
+
     #[test]
     fn fuzzybarstate_should_filter() {
-        let mut s = FuzzybarState {
-            is_hidden: true,
-            cmd: Command::ShowMenu,
-            query: "b".to_owned(),
-            source: vector![
-                "aa".to_owned(),
-                "ab".to_owned(),
-                "bc".to_owned(),
-                "bca".to_owned()
-            ],
-            filtered: vector![],
-        };
+        let mut s = FuzzybarState::new(Command::ShowMenu);
+        s.query = "b".to_owned();
+        s.source = items(&["aa", "ab", "bc", "bca"]);
 
         s.filter();
         let expected = vector![
-            ListItem {
-                name: "ab".to_owned(),
-                selected: true
-            },
             ListItem {
                 name: "bc".to_owned(),
-                selected: false
+                kind: ItemKind::Line,
+                oid: None,
+                description: "".to_owned(),
+                selected: true,
+                marked: false,
+                positions: vector![0],
             },
             ListItem {
                 name: "bca".to_owned(),
-                selected: false
+                kind: ItemKind::Line,
+                oid: None,
+                description: "".to_owned(),
+                selected: false,
+                marked: false,
+                positions: vector![0],
+            },
+            ListItem {
+                name: "ab".to_owned(),
+                kind: ItemKind::Line,
+                oid: None,
+                description: "".to_owned(),
+                selected: false,
+                marked: false,
+                positions: vector![1],
             },
         ];
-        assert_eq!(expected, s.filtered);
+        assert_eq!(expected, s.filtered.items);
+    }
+
+    #[test]
+    fn fuzzybarstate_should_rank_fuzzy_matches() {
+        let mut s = FuzzybarState::new(Command::ShowMenu);
+        s.query = "fbar".to_owned();
+        s.source = items(&["master", "fix/xbxaxr", "feature/bar"]);
+
+        s.filter();
+        let names = s
+            .filtered
+            .items
+            .iter()
+            .map(|i| i.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["feature/bar", "fix/xbxaxr"]);
+        assert_eq!(s.filtered.items[0].positions, vector![0, 8, 9, 10]);
+    }
+
+    #[test]
+    fn frecent_items_should_come_first() {
+        let mut s = FuzzybarState::new(Command::BranchCheckout);
+        s.source = items(&["master", "develop"]);
+        s.frecencies = im::hashmap! {"develop".to_owned() => 8};
+
+        s.filter();
+        assert_eq!(s.filtered.items[0].name, "develop");
+
+        // A much better match still wins
+        s.query = "mas".to_owned();
+        s.source.push_back(SourceItem::new("mxaxs", ItemKind::Line));
+        s.frecencies.insert("mxaxs".to_owned(), 8);
+        s.filter();
+        assert_eq!(s.filtered.items[0].name, "master");
     }
 
     #[test]
-    fn filtered_should_have_limited_items() {
+    fn marks_should_survive_filtering() {
+        let mut s = FuzzybarState::new(Command::BranchDelete);
+        s.source = items(&["a", "b", "c"]);
+
+        s.filter();
+        s.filtered.select(1);
+        assert_eq!(s.selections(), vec!["b".to_owned()]);
+
+        s.filtered.select(2);
+        s.toggle_mark();
+        s.filtered.select(0);
+        s.toggle_mark();
+        s.query = "c".to_owned();
+        s.filter();
+        assert_eq!(s.filtered.visible[0].marked, true);
+        assert_eq!(s.selections(), vec!["c".to_owned(), "a".to_owned()]);
+
+        s.toggle_mark();
+        assert_eq!(s.selections(), vec!["a".to_owned()]);
+
+        s.query = "".to_owned();
+        s.filter();
+        s.mark_all();
+        assert_eq!(s.marked.len(), 3);
+        s.unmark_all();
+        assert!(s.filtered.items.iter().all(|item| !item.marked));
+    }
+
+    #[test]
+    fn filter_should_keep_every_match() {
         let source = (1..200)
-            .map(|s| format!("Item {}", s))
-            .collect::<Vector<String>>();
-        let mut s = FuzzybarState {
-            is_hidden: true,
-            cmd: Command::ShowMenu,
-            query: "2".to_owned(),
-            source,
-            filtered: vector![],
-        };
+            .map(|s| SourceItem::new(&format!("Item {}", s), ItemKind::Line))
+            .collect::<Vector<SourceItem>>();
+        let mut s = FuzzybarState::new(Command::ShowMenu);
+        s.query = "2".to_owned();
+        s.source = source;
 
         s.filter();
-        assert_eq!(20, s.filtered.len());
+        let expected = (1..200).filter(|n| n.to_string().contains('2')).count();
+        assert_eq!(expected, s.filtered.items.len());
+    }
+
+    #[test]
+    fn every_command_should_be_in_all() {
+        // Doesn't compile once a command is added, until it's numbered here.
+        // Add it to Command::ALL too.
+        let number = |cmd: Command| match cmd {
+            Command::ShowMenu => 0,
+            Command::BranchCheckout => 1,
+            Command::TagCheckout => 2,
+            Command::CommitCheckout => 3,
+            Command::Commit => 4,
+            Command::FileLog => 5,
+            Command::Reflog => 6,
+            Command::Bisect => 7,
+            Command::Worktrees => 8,
+            Command::WorktreeAdd => 9,
+            Command::SubmoduleInit => 10,
+            Command::SubmoduleUpdate => 11,
+            Command::SubmoduleSync => 12,
+            Command::SubmoduleOpen => 13,
+            Command::StashApply => 14,
+            Command::BranchDelete => 15,
+            Command::CherryPick => 16,
+            Command::StageFiles => 17,
+            Command::RemoteFetch => 18,
+            Command::Push => 19,
+            Command::BranchCreate => 20,
+            Command::TagCreate => 21,
+            Command::StashSave => 22,
+            Command::ThemeSwitch => 23,
+            Command::Palette => 24,
+        };
+        let count = 25;
+
+        let mut numbers = Command::ALL
+            .iter()
+            .map(|cmd| number(*cmd))
+            .collect::<Vec<_>>();
+        numbers.sort_unstable();
+        numbers.dedup();
+        assert_eq!(numbers, (0..count).collect::<Vec<_>>());
     }
 }
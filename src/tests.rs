@@ -1,9 +1,15 @@
+use crate::config;
 use crate::git;
-use crate::state::{
-    AppState, CheatSheetState, Command, Config, FuzzybarState, GitState, KeyMapLevel,
-};
+use crate::keymap::KeySeq;
+use crate::state::{AppState, CheatSheetState, Command, FuzzybarState, GitState, View};
+use crate::theme::ThemeState;
+use crate::widgets::bisect::Bisect;
+use crate::widgets::filelog::FileLog;
+use crate::widgets::output::ShellOutput;
+use crate::widgets::reflog::Reflog;
+use crate::widgets::worktrees::Worktrees;
 use anyhow::Result;
-use git2::{Branch, Oid, Repository};
+use git2::{Branch, Index, Oid, Repository};
 use im::vector;
 use std::fs::File;
 use std::io;
@@ -26,6 +32,8 @@ macro_rules! res {
 pub fn repo_init() -> (TempDir, Repository) {
     let td = TempDir::new().unwrap();
     let repo = Repository::init(td.path()).unwrap();
+    // init.defaultBranch may name it otherwise, but the tests expect master
+    repo.set_head("refs/heads/master").unwrap();
     {
         let mut config = repo.config().unwrap();
         config.set_str("user.name", "name").unwrap();
@@ -42,9 +50,7 @@ pub fn repo_init() -> (TempDir, Repository) {
 }
 
 pub fn state_init(repo: Repository) -> AppState {
-    let config_str = std::fs::read_to_string("./config.toml").unwrap();
-
-    let config: Config = toml::from_str(&config_str).unwrap();
+    let config = config::defaults();
 
     let (local, remote) = git::get_branches(&repo);
 
@@ -58,24 +64,31 @@ pub fn state_init(repo: Repository) -> AppState {
         repo: repo.clone(),
         win_size: crate::WINDOW_SIZE.into(),
         repo_header: res!(git::get_repo_header(&repo)),
+        view: View::Status,
+        file_log: FileLog::default(),
+        reflog: Reflog::default(),
+        bisect: Bisect::default(),
+        worktrees: Worktrees::default(),
         cheatsheet: CheatSheetState {
             is_hidden: true,
             keymap: config.keymap.map,
-            current_node: 0,
-            current_level: KeyMapLevel::L1,
-        },
-        fuzzybar: FuzzybarState {
-            is_hidden: true,
-            cmd: Command::ShowMenu,
-            query: "".to_owned(),
-            source: all_branches.clone(),
-            filtered: vector![],
+            path: KeySeq::default(),
+            transient: None,
+            search: None,
+            picked: None,
+            remembered: im::HashMap::new(),
+            persist_args: config.transient.persist,
         },
+        fuzzybar: FuzzybarState::new(Command::ShowMenu),
         git: GitState {
             local_branches: local,
             remote_branches: remote,
             all_branches,
         },
+        notifications: vector![],
+        config_errors: vector![],
+        output: ShellOutput::default(),
+        theme: ThemeState::new(config.appearance, config.themes),
     };
 
     app_state.fuzzybar.filter();
@@ -98,12 +111,48 @@ pub fn commit(repo: &Repository) -> (Oid, Oid) {
     (commit, tree_id)
 }
 
+pub fn commit_file(repo: &Repository, path: &str, content: &str) -> Oid {
+    let root = repo.path().parent().unwrap();
+    res!(std::fs::write(root.join(path), content));
+
+    let mut index = res!(repo.index());
+    res!(index.add_path(Path::new(path)));
+    commit_index(repo, &mut index, &format!("Update {}", path))
+}
+
+pub fn rename_file(repo: &Repository, from: &str, to: &str) -> Oid {
+    let root = repo.path().parent().unwrap();
+    res!(std::fs::rename(root.join(from), root.join(to)));
+
+    let mut index = res!(repo.index());
+    res!(index.remove_path(Path::new(from)));
+    res!(index.add_path(Path::new(to)));
+    commit_index(repo, &mut index, &format!("Rename {} to {}", from, to))
+}
+
+fn commit_index(repo: &Repository, index: &mut Index, msg: &str) -> Oid {
+    res!(index.write());
+    let tree_id = res!(index.write_tree());
+    let tree = res!(repo.find_tree(tree_id));
+    let sig = res!(repo.signature());
+    let head_id = res!(repo.refname_to_id("HEAD"));
+    let parent = res!(repo.find_commit(head_id));
+    res!(repo.commit(Some("HEAD"), &sig, &sig, msg, &tree, &[&parent]))
+}
+
 pub fn branch<'a>(repo: &'a Repository, branch_name: &str) -> Branch<'a> {
     let head_id = res!(repo.refname_to_id("HEAD"));
     let target = res!(repo.find_commit(head_id));
     res!(repo.branch(branch_name, &target, false))
 }
 
+pub fn checkout(repo: &Repository, branch_name: &str) {
+    let refname = format!("refs/heads/{}", branch_name);
+    let target = res!(repo.revparse_single(&refname));
+    res!(repo.checkout_tree(&target, None));
+    res!(repo.set_head(&refname));
+}
+
 lazy_static::lazy_static! {
     pub static ref HAS_LOGGER: bool = setup_test_logger();
 }
@@ -1,4 +1,14 @@
-use druid::{Color, Key};
+//! Colors and fonts of the ui. Themes are palettes defined in the config
+//! under `[themes.<name>]`, their colors named after the Solarized palette the
+//! ui was designed with: `base03` to `base3` from dark to light (or the other
+//! way round for a dark theme) and the accent colors. The theme in use and the
+//! font are set in `[appearance]`.
+
+use anyhow::{anyhow, Result};
+use druid::{Color, Data, Env, Key};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::rc::Rc;
 
 pub const BASE_3: Key<Color> = Key::new("theme_base_3");
 pub const BASE_2: Key<Color> = Key::new("theme_base_2");
@@ -17,3 +27,207 @@ pub const VIOLET: Key<Color> = Key::new("theme_violet");
 pub const BLUE: Key<Color> = Key::new("theme_blue");
 pub const CYAN: Key<Color> = Key::new("theme_cyan");
 pub const GREEN: Key<Color> = Key::new("theme_green");
+
+/// Characters matched by the fuzzybar query
+pub const HIGHLIGHT: Key<Color> = Key::new("theme_highlight");
+
+/// A color written as `#rrggbb`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    pub fn parse(hex: &str) -> Result<Rgb> {
+        let digits = if hex.starts_with('#') { &hex[1..] } else { "" };
+        if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(anyhow!("'{}' isn't a #rrggbb color", hex));
+        }
+        let channel = |idx: usize| u8::from_str_radix(&digits[idx..idx + 2], 16).unwrap_or(0);
+        Ok(Rgb(channel(0), channel(2), channel(4)))
+    }
+
+    pub fn color(&self) -> Color {
+        Color::rgb8(self.0, self.1, self.2)
+    }
+}
+
+impl<'de> Deserialize<'de> for Rgb {
+    fn deserialize<D>(deserializer: D) -> Result<Rgb, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let hex = String::deserialize(deserializer)?;
+        Rgb::parse(&hex).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Palette {
+    /// Background
+    pub base3: Rgb,
+    /// Background of panels and bars
+    pub base2: Rgb,
+    /// Secondary text
+    pub base1: Rgb,
+    pub base0: Rgb,
+    /// Text
+    pub base00: Rgb,
+    pub base01: Rgb,
+    pub base02: Rgb,
+    pub base03: Rgb,
+    pub yellow: Rgb,
+    pub orange: Rgb,
+    pub red: Rgb,
+    pub magenta: Rgb,
+    pub violet: Rgb,
+    pub blue: Rgb,
+    pub cyan: Rgb,
+    pub green: Rgb,
+    /// Characters matched by the fuzzybar query, orange if not set
+    #[serde(default)]
+    pub highlight: Option<Rgb>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FontConfig {
+    pub family: String,
+    pub size: f64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AppearanceConfig {
+    /// Name of the theme to start with
+    pub theme: String,
+    pub font: FontConfig,
+}
+
+/// The themes of the config and the one in use, which can be switched at
+/// runtime
+#[derive(Clone, Data, Debug)]
+pub struct ThemeState {
+    pub current: String,
+    /// Theme set in `[appearance]`, switching themes only changes `current`
+    pub configured: String,
+    pub themes: Rc<BTreeMap<String, Palette>>,
+    pub font: Rc<FontConfig>,
+}
+
+impl ThemeState {
+    pub fn new(appearance: AppearanceConfig, themes: BTreeMap<String, Palette>) -> Self {
+        ThemeState {
+            current: appearance.theme.clone(),
+            configured: appearance.theme,
+            themes: Rc::new(themes),
+            font: Rc::new(appearance.font),
+        }
+    }
+
+    /// Take the settings of a reloaded config. A theme switched to at runtime
+    /// is kept, unless the config now sets another theme or it's gone.
+    pub fn reload(&mut self, appearance: AppearanceConfig, themes: BTreeMap<String, Palette>) {
+        if appearance.theme != self.configured {
+            *self = ThemeState::new(appearance, themes);
+            return;
+        }
+        if *self.themes != themes {
+            if !themes.contains_key(&self.current) {
+                self.current = self.configured.clone();
+            }
+            self.themes = Rc::new(themes);
+        }
+        if *self.font != appearance.font {
+            self.font = Rc::new(appearance.font);
+        }
+    }
+
+    pub fn palette(&self) -> Option<&Palette> {
+        self.themes.get(&self.current)
+    }
+
+    /// Switch to the theme `name`, returns false if there's no such theme
+    pub fn select(&mut self, name: &str) -> bool {
+        if !self.themes.contains_key(name) {
+            return false;
+        }
+        self.current = name.to_owned();
+        true
+    }
+}
+
+/// Set the colors and font of `theme` in the environment
+pub fn apply(env: &mut Env, theme: &ThemeState) {
+    if let Some(palette) = theme.palette() {
+        env.set(BASE_3, palette.base3.color());
+        env.set(BASE_2, palette.base2.color());
+        env.set(BASE_1, palette.base1.color());
+        env.set(BASE_0, palette.base0.color());
+        env.set(BASE_00, palette.base00.color());
+        env.set(BASE_01, palette.base01.color());
+        env.set(BASE_02, palette.base02.color());
+        env.set(BASE_03, palette.base03.color());
+
+        env.set(YELLOW, palette.yellow.color());
+        env.set(ORANGE, palette.orange.color());
+        env.set(RED, palette.red.color());
+        env.set(MAGENTA, palette.magenta.color());
+        env.set(VIOLET, palette.violet.color());
+        env.set(BLUE, palette.blue.color());
+        env.set(CYAN, palette.cyan.color());
+        env.set(GREEN, palette.green.color());
+
+        env.set(
+            HIGHLIGHT,
+            palette.highlight.unwrap_or(palette.orange).color(),
+        );
+    }
+
+    // Overrides
+    env.set(druid::theme::FONT_NAME, theme.font.family.as_str());
+    env.set(druid::theme::TEXT_SIZE_NORMAL, theme.font.size);
+    env.set(druid::theme::BACKGROUND_LIGHT, env.get(BASE_3));
+    env.set(druid::theme::LABEL_COLOR, env.get(BASE_00));
+    env.set(druid::theme::PRIMARY_LIGHT, env.get(BASE_3));
+    env.set(druid::theme::BORDER_DARK, env.get(BASE_3));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    #[test]
+    fn colors_should_be_parsed_from_hex() {
+        assert_eq!(Rgb::parse("#fdf6e3").unwrap(), Rgb(0xfd, 0xf6, 0xe3));
+        assert_eq!(Rgb::parse("#00FF10").unwrap(), Rgb(0, 0xff, 0x10));
+        assert!(Rgb::parse("fdf6e3").is_err());
+        assert!(Rgb::parse("#fdf6e").is_err());
+        assert!(Rgb::parse("#gdf6e3").is_err());
+        assert!(Rgb::parse("#fdf6éé").is_err());
+        assert!(Rgb::parse("#+00000").is_err());
+    }
+
+    #[test]
+    fn switched_theme_should_survive_a_reload() {
+        let config = config::defaults();
+        let mut theme = ThemeState::new(config.appearance.clone(), config.themes.clone());
+        assert!(theme.select("solarized-dark"));
+
+        let mut appearance = config.appearance.clone();
+        appearance.font.size = 14.0;
+        theme.reload(appearance.clone(), config.themes.clone());
+        assert_eq!(theme.current, "solarized-dark");
+        assert_eq!(theme.font.size, 14.0);
+
+        let mut themes = config.themes.clone();
+        themes.remove("solarized-dark");
+        theme.reload(appearance.clone(), themes.clone());
+        assert_eq!(theme.current, "solarized-light");
+
+        assert!(theme.select("high-contrast"));
+        appearance.theme = "solarized-dark".to_owned();
+        theme.reload(appearance, config.themes);
+        assert_eq!(theme.current, "solarized-dark");
+    }
+}
@@ -0,0 +1,258 @@
+//! Transient menus, the way magit does them: a command with switches doesn't
+//! run right away, the cheatsheet lists its switches to be toggled first and
+//! `RET` runs it.
+//!
+//! The args a keymap entry ran with are remembered for the session. With
+//! `transient.persist` set in the config they're also kept in
+//! `$XDG_DATA_HOME/gitools/transient/<escaped workdir>.toml`.
+
+use crate::history;
+use crate::keymap::{Key, KeyNode, KeySeq};
+use crate::state::{CheatSheetState, CmdArgs, Switch, Transient};
+use anyhow::{Context, Result};
+use git2::Repository;
+use log::{debug, error};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::rc::Rc;
+
+/// Key running the command of a transient menu
+pub const RUN_KEY: &str = "Enter";
+/// Key picking the next remote
+pub const REMOTE_KEY: &str = "r";
+
+/// Args remembered per keymap entry, by its key sequence
+type Remembered = BTreeMap<String, CmdArgs>;
+
+/// Name the args of a menu are remembered by: its keys, or its command if it
+/// was picked in the command palette without having any
+fn remembered_name(seq: &KeySeq, node: &KeyNode) -> String {
+    match node.command {
+        Some(cmd) if seq.is_empty() => format!("{:?}", cmd),
+        _ => seq.to_string(),
+    }
+}
+
+/// Open the menu of `node`, with the args it was last run with
+pub fn open(repo: &Repository, state: &CheatSheetState, seq: KeySeq, node: KeyNode) -> Transient {
+    let key = remembered_name(&seq, &node);
+    let args = state
+        .remembered
+        .get(&key)
+        .cloned()
+        .or_else(|| {
+            if state.persist_args {
+                load(repo).remove(&key)
+            } else {
+                None
+            }
+        })
+        .unwrap_or_else(|| node.args.clone());
+
+    let remotes = match node.command {
+        Some(cmd) if cmd.takes_remote() => repo
+            .remotes()
+            .map(|remotes| remotes.iter().flatten().map(str::to_owned).collect())
+            .unwrap_or_default(),
+        _ => im::Vector::new(),
+    };
+
+    Transient {
+        seq,
+        node,
+        args,
+        remotes,
+    }
+}
+
+/// Remember the args `transient` is run with
+pub fn remember(repo: &Repository, state: &mut CheatSheetState, transient: &Transient) {
+    let key = remembered_name(&transient.seq, &transient.node);
+    state.remembered.insert(key.clone(), transient.args.clone());
+
+    if state.persist_args {
+        let mut remembered = load(repo);
+        remembered.insert(key, transient.args.clone());
+        if let Some(path) = history::repo_file(repo, "transient") {
+            if let Err(err) = save_to(&path, &remembered) {
+                error!("{:#}", err);
+            }
+        }
+    }
+}
+
+/// Toggle a switch or pick the next remote. Returns false if `key` isn't
+/// in the menu.
+pub fn press_key(transient: &mut Transient, key: &Key) -> bool {
+    let cmd = match transient.node.command {
+        Some(cmd) => cmd,
+        None => return false,
+    };
+
+    if let Some(switch) = cmd
+        .switches()
+        .iter()
+        .find(|switch| Key::parse(switch.key()).ok().as_ref() == Some(key))
+    {
+        toggle(&mut transient.args, *switch);
+        return true;
+    }
+
+    if cmd.takes_remote() && Key::parse(REMOTE_KEY).ok().as_ref() == Some(key) {
+        transient.args.remote = next_remote(transient.args.remote.as_deref(), &transient.remotes);
+        return true;
+    }
+    false
+}
+
+fn toggle(args: &mut CmdArgs, switch: Switch) {
+    let switches = Rc::make_mut(&mut args.switches);
+    if !switches.remove(&switch) {
+        switches.insert(switch);
+    }
+}
+
+/// Cycle through the remotes, and back to picking one in the fuzzybar
+fn next_remote(current: Option<&str>, remotes: &im::Vector<String>) -> Option<String> {
+    let next = match current {
+        None => 0,
+        Some(current) => match remotes.iter().position(|remote| remote == current) {
+            Some(idx) => idx + 1,
+            None => 0,
+        },
+    };
+    remotes.get(next).cloned()
+}
+
+/// Keys and descriptions of the entries of the menu
+pub fn menu(transient: &Transient) -> Vec<(String, String)> {
+    let cmd = match transient.node.command {
+        Some(cmd) => cmd,
+        None => return vec![],
+    };
+
+    let mut entries = cmd
+        .switches()
+        .iter()
+        .map(|switch| {
+            let mark = if transient.args.has(*switch) {
+                "x"
+            } else {
+                " "
+            };
+            (
+                switch.key().to_owned(),
+                format!("[{}] {}", mark, switch.flag()),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    if cmd.takes_remote() {
+        let remote = transient.args.remote.as_deref().unwrap_or("(pick)");
+        entries.push((REMOTE_KEY.to_owned(), format!("remote = {}", remote)));
+    }
+
+    if let Ok(key) = Key::parse(RUN_KEY) {
+        entries.push((key.to_string(), transient.node.name.clone()));
+    }
+    entries
+}
+
+fn load(repo: &Repository) -> Remembered {
+    let path = match history::repo_file(repo, "transient") {
+        Some(path) if path.exists() => path,
+        _ => return Remembered::new(),
+    };
+
+    match load_from(&path) {
+        Ok(remembered) => remembered,
+        Err(err) => {
+            error!("{:#}", err);
+            Remembered::new()
+        }
+    }
+}
+
+fn load_from(path: &Path) -> Result<Remembered> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    toml::from_str(&content).with_context(|| format!("Invalid transient args {}", path.display()))
+}
+
+fn save_to(path: &Path, remembered: &Remembered) -> Result<()> {
+    debug!("Saving transient args to {}", path.display());
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let content = toml::to_string(remembered).context("Failed to serialize transient args")?;
+    std::fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Command;
+    use im::vector;
+    use tempfile::TempDir;
+
+    fn transient(cmd: Command) -> Transient {
+        let node = toml::from_str::<KeyNode>(&format!(
+            "key = \"p\"\nname = \"Push\"\ncommand = \"{:?}\"\n",
+            cmd
+        ))
+        .unwrap();
+        Transient {
+            seq: KeySeq::parse("r p").unwrap(),
+            node,
+            args: CmdArgs::default(),
+            remotes: vector!["origin".to_owned(), "fork".to_owned()],
+        }
+    }
+
+    #[test]
+    fn keys_should_toggle_switches_and_cycle_remotes() {
+        let mut push = transient(Command::Push);
+        let f = Key::parse("f").unwrap();
+        let r = Key::parse("r").unwrap();
+
+        assert!(press_key(&mut push, &f));
+        assert!(push.args.has(Switch::ForceWithLease));
+        assert!(press_key(&mut push, &f));
+        assert!(!push.args.has(Switch::ForceWithLease));
+        assert!(!press_key(&mut push, &Key::parse("p").unwrap()));
+
+        let remotes = (0..3)
+            .map(|_| {
+                press_key(&mut push, &r);
+                push.args.remote.clone()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            remotes,
+            vec![Some("origin".to_owned()), Some("fork".to_owned()), None]
+        );
+
+        let mut fetch = transient(Command::RemoteFetch);
+        assert!(!press_key(&mut fetch, &f));
+        assert!(press_key(&mut fetch, &Key::parse("p").unwrap()));
+        let entries = menu(&fetch);
+        assert_eq!(entries[0], ("p".to_owned(), "[x] --prune".to_owned()));
+        assert_eq!(entries.last().unwrap().0, "Enter");
+    }
+
+    #[test]
+    fn remembered_args_should_survive_a_roundtrip() {
+        let td = TempDir::new().unwrap();
+        let path = td.path().join("transient").join("repo.toml");
+        let mut push = transient(Command::Push);
+        toggle(&mut push.args, Switch::SetUpstream);
+        push.args.remote = Some("origin".to_owned());
+
+        let mut remembered = Remembered::new();
+        remembered.insert(push.seq.to_string(), push.args.clone());
+        remembered.insert("c c".to_owned(), CmdArgs::default());
+        save_to(&path, &remembered).unwrap();
+        assert_eq!(load_from(&path).unwrap(), remembered);
+    }
+}
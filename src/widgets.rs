@@ -1,4 +1,16 @@
+pub mod bisect;
 pub mod cheatsheet;
+pub mod configwatch;
+pub mod filelog;
 pub mod fuzzybar;
 pub mod header;
+pub mod listview;
+pub mod matchlabel;
+pub mod norepo;
+pub mod notifications;
+pub mod output;
+pub mod patch;
+pub mod preview;
+pub mod reflog;
 pub mod status;
+pub mod worktrees;
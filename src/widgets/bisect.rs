@@ -0,0 +1,532 @@
+use crate::git::{self, BisectStep};
+use crate::state::{AppState, View};
+use crate::theme;
+use crate::widgets::listview::{ListRow, ListView};
+use crate::widgets::patch;
+use anyhow::{anyhow, Result};
+use druid::widget::{Controller, CrossAxisAlignment, Either, Flex, Label, Scroll, TextBox};
+use druid::{
+    Data, Env, Event, EventCtx, KbKey, Lens, LensExt, LifeCycle, LifeCycleCtx, TimerToken, Widget,
+    WidgetExt, WidgetId,
+};
+use git2::{Oid, Repository};
+use im::{vector, Vector};
+use log::{debug, error};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::Duration;
+
+const LOG_LIMIT: usize = 1000;
+const DETAIL_HEIGHT: f64 = 400.0;
+const TEST_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+const MARK_GOOD: &str = "good";
+const MARK_BAD: &str = "bad";
+const MARK_SKIP: &str = "skip";
+const MARK_CURRENT: &str = "testing";
+
+#[derive(Clone, Copy, PartialEq, Data, Debug)]
+pub enum BisectPhase {
+    /// Picking the initial good and bad commits from the log
+    Selecting,
+    Testing,
+    Done,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BisectMark {
+    Good,
+    Bad,
+    Skip,
+}
+
+impl BisectMark {
+    /// Interpret the exit code of a test command the same way `git bisect run` does
+    fn from_exit_code(code: Option<i32>) -> Self {
+        match code {
+            Some(0) => BisectMark::Good,
+            Some(125) => BisectMark::Skip,
+            _ => BisectMark::Bad,
+        }
+    }
+}
+
+#[derive(Clone, Data, Lens, Debug, PartialEq)]
+pub struct BisectEntry {
+    pub oid: String,
+    pub summary: String,
+    pub time: String,
+    pub mark: String,
+    pub selected: bool,
+}
+
+impl ListRow for BisectEntry {
+    fn text(&self) -> String {
+        format!(
+            "{:8} {}  {}  {}",
+            self.mark,
+            &self.oid[..7],
+            self.time,
+            self.summary
+        )
+    }
+
+    fn is_selected(&self) -> bool {
+        self.selected
+    }
+
+    fn set_selected(&mut self, selected: bool) {
+        self.selected = selected;
+    }
+}
+
+/// Guided bisect. The good and bad commits are picked from the log, after
+/// which gitools checks out the commit to test until the first bad commit is
+/// found. Optionally a test command decides whether a commit is good or bad.
+#[derive(Clone, Data, Lens)]
+pub struct Bisect {
+    phase: BisectPhase,
+    commits: ListView<BisectEntry>,
+    good: Vector<String>,
+    bad: Option<String>,
+    skipped: Vector<String>,
+    current: Option<String>,
+    orig_head: String,
+    status: String,
+    test_cmd: String,
+    auto_run: bool,
+    detail: Vector<String>,
+}
+
+impl Bisect {
+    pub fn new(repo: &Repository) -> Self {
+        let orig_head = match repo.head() {
+            Ok(head) if repo.head_detached().unwrap_or(false) => head
+                .target()
+                .map(|oid| format!("{}", oid))
+                .unwrap_or_default(),
+            Ok(head) => head.name().unwrap_or_default().to_owned(),
+            Err(_) => "".to_owned(),
+        };
+
+        let entries = match git::get_log(repo, LOG_LIMIT) {
+            Ok(oids) => oids
+                .iter()
+                .filter_map(|oid| repo.find_commit(*oid).ok())
+                .map(|commit| BisectEntry {
+                    oid: format!("{}", commit.id()),
+                    summary: commit.summary().unwrap_or_default().to_owned(),
+                    time: git::format_time(&commit.time()),
+                    mark: "".to_owned(),
+                    selected: false,
+                })
+                .collect(),
+            Err(err) => {
+                error!("Failed to read log: {}", err);
+                vector![]
+            }
+        };
+
+        Bisect {
+            phase: BisectPhase::Selecting,
+            commits: ListView::new(entries),
+            orig_head,
+            status: "Mark a bad commit and at least one good commit".to_owned(),
+            ..Self::default()
+        }
+    }
+
+    /// Mark the selected log entry as the bad commit, or toggle it as good
+    fn mark_selected(&mut self, mark: BisectMark) {
+        let oid = match self.commits.selected() {
+            Some(entry) => entry.oid.clone(),
+            None => return,
+        };
+
+        match mark {
+            BisectMark::Bad => {
+                self.good.retain(|good| *good != oid);
+                self.bad = Some(oid);
+            }
+            BisectMark::Good => {
+                if self.bad.as_ref() == Some(&oid) {
+                    self.bad = None;
+                }
+                if self.good.contains(&oid) {
+                    self.good.retain(|good| *good != oid);
+                } else {
+                    self.good.push_back(oid);
+                }
+            }
+            BisectMark::Skip => (),
+        }
+        self.update_marks();
+    }
+
+    fn start(&mut self, repo: &Repository) {
+        if self.bad.is_none() || self.good.is_empty() {
+            self.status = "A bad commit and at least one good commit are needed".to_owned();
+            return;
+        }
+        self.step(repo);
+    }
+
+    /// Mark the commit that is being tested and move on to the next one
+    fn mark_current(&mut self, repo: &Repository, mark: BisectMark) {
+        let current = match self.current.take() {
+            Some(current) => current,
+            None => return,
+        };
+
+        match mark {
+            BisectMark::Good => self.good.push_back(current),
+            BisectMark::Bad => self.bad = Some(current),
+            BisectMark::Skip => self.skipped.push_back(current),
+        }
+        self.step(repo);
+    }
+
+    fn step(&mut self, repo: &Repository) {
+        match self.next(repo) {
+            Ok(BisectStep::Next { oid, remaining }) => {
+                if let Err(err) = git::checkout_commit(repo, oid) {
+                    self.fail(&format!("Failed to checkout {}: {:#}", oid, err));
+                    return;
+                }
+                self.phase = BisectPhase::Testing;
+                self.current = Some(format!("{}", oid));
+                self.status = format!(
+                    "Testing {}: {} commits left, roughly {} steps",
+                    &format!("{}", oid)[..7],
+                    remaining,
+                    git::bisect_steps(remaining)
+                );
+            }
+            Ok(BisectStep::Found(oid)) => {
+                self.phase = BisectPhase::Done;
+                self.auto_run = false;
+                self.status = format!("{} is the first bad commit", oid);
+                self.detail = patch::commit_detail(repo, oid).unwrap_or_else(|err| {
+                    error!("Failed to get commit detail: {}", err);
+                    vector![]
+                });
+            }
+            Ok(BisectStep::Inconclusive(oids)) => {
+                self.phase = BisectPhase::Done;
+                self.auto_run = false;
+                self.status =
+                    "Only skipped commits are left, the first bad commit is one of".to_owned();
+                self.detail = oids.iter().map(|oid| format!("{}", oid)).collect();
+            }
+            Err(err) => self.fail(&format!("Bisect failed: {:#}", err)),
+        }
+        self.update_marks();
+    }
+
+    fn next(&self, repo: &Repository) -> Result<BisectStep> {
+        let bad = self.bad.as_ref().ok_or(anyhow!("No bad commit"))?;
+        let good = to_oids(&self.good)?;
+        let skipped = to_oids(&self.skipped)?;
+        git::bisect_next(repo, Oid::from_str(bad)?, &good, &skipped)
+    }
+
+    fn fail(&mut self, msg: &str) {
+        error!("{}", msg);
+        self.status = msg.to_owned();
+        self.auto_run = false;
+    }
+
+    fn update_marks(&mut self) {
+        let mut items = self.commits.items.clone();
+        for entry in items.iter_mut() {
+            entry.mark = if self.current.as_ref() == Some(&entry.oid) {
+                MARK_CURRENT
+            } else if self.bad.as_ref() == Some(&entry.oid) {
+                MARK_BAD
+            } else if self.good.contains(&entry.oid) {
+                MARK_GOOD
+            } else if self.skipped.contains(&entry.oid) {
+                MARK_SKIP
+            } else {
+                ""
+            }
+            .to_owned();
+        }
+        self.commits.items = items;
+        self.commits.select(self.commits.selected_idx);
+    }
+
+    /// Go back to where HEAD was before bisecting
+    fn reset(&self, repo: &Repository) {
+        if self.phase == BisectPhase::Selecting || self.orig_head.is_empty() {
+            return;
+        }
+
+        let result = if self.orig_head.starts_with("refs/") {
+            git::checkout_ref(repo, &self.orig_head)
+        } else {
+            Oid::from_str(&self.orig_head)
+                .map_err(anyhow::Error::from)
+                .and_then(|oid| git::checkout_commit(repo, oid))
+        };
+
+        if let Err(err) = result {
+            error!("Failed to restore {}: {:#}", self.orig_head, err);
+        }
+    }
+
+    pub fn widget() -> impl Widget<AppState> {
+        let test_cmd_id = WidgetId::next();
+
+        let header = Label::new("Bisect").with_text_color(theme::BLUE);
+        let status = Label::dynamic(|app: &AppState, _| app.bisect.status.clone());
+
+        let test_cmd = Flex::row()
+            .with_child(Label::new("Test command:").fix_width(120.0))
+            .with_child(
+                TextBox::new()
+                    .with_placeholder("Press : to enter a command")
+                    .lens(AppState::bisect.then(Bisect::test_cmd))
+                    .fix_width(400.0)
+                    .with_id(test_cmd_id),
+            );
+
+        let detail = Scroll::new(patch::widget().lens(AppState::bisect.then(Bisect::detail)))
+            .fix_height(DETAIL_HEIGHT);
+        let commits = ListView::widget().lens(AppState::bisect.then(Bisect::commits));
+        let body = Either::new(
+            |app: &AppState, _| app.bisect.phase == BisectPhase::Done,
+            detail,
+            commits,
+        );
+
+        let hints = Label::dynamic(|app: &AppState, _| {
+            match app.bisect.phase {
+                BisectPhase::Selecting => "b mark bad   g toggle good   enter start   esc back",
+                BisectPhase::Testing => {
+                    "g good   b bad   s skip   t run test   a run test until done   : edit test   esc abort"
+                }
+                BisectPhase::Done => "esc back",
+            }
+            .to_owned()
+        })
+        .with_text_color(theme::BASE_1);
+
+        Flex::column()
+            .with_child(header)
+            .with_child(status)
+            .with_spacer(8.0)
+            .with_child(test_cmd)
+            .with_spacer(8.0)
+            .with_child(body)
+            .with_spacer(8.0)
+            .with_child(hints)
+            .cross_axis_alignment(CrossAxisAlignment::Start)
+            .controller(BisectController {
+                test_cmd_id,
+                test_run: None,
+            })
+    }
+}
+
+impl Default for Bisect {
+    fn default() -> Self {
+        Bisect {
+            phase: BisectPhase::Selecting,
+            commits: ListView::default(),
+            good: vector![],
+            bad: None,
+            skipped: vector![],
+            current: None,
+            orig_head: "".to_owned(),
+            status: "".to_owned(),
+            test_cmd: "".to_owned(),
+            auto_run: false,
+            detail: vector![],
+        }
+    }
+}
+
+fn to_oids(oids: &Vector<String>) -> Result<Vec<Oid>> {
+    oids.iter()
+        .map(|oid| Oid::from_str(oid).map_err(anyhow::Error::from))
+        .collect()
+}
+
+/// Run the test command on a separate thread so the ui stays responsive
+fn spawn_test(cmd: String, workdir: PathBuf) -> Receiver<Result<BisectMark>> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        debug!("Running bisect test: {}", cmd);
+        let result = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(&cmd)
+            .current_dir(workdir)
+            .output()
+            .map(|output| BisectMark::from_exit_code(output.status.code()))
+            .map_err(|err| anyhow!("Failed to run {}: {}", cmd, err));
+        let _ = tx.send(result);
+    });
+    rx
+}
+
+struct BisectController {
+    test_cmd_id: WidgetId,
+    test_run: Option<(TimerToken, Receiver<Result<BisectMark>>)>,
+}
+
+impl BisectController {
+    fn run_test(&mut self, ctx: &mut EventCtx, data: &mut AppState) {
+        if self.test_run.is_some() {
+            return;
+        }
+
+        let workdir = match data.repo.workdir() {
+            Some(workdir) => workdir.to_owned(),
+            None => {
+                data.bisect.fail("Can't run tests in a bare repository");
+                return;
+            }
+        };
+
+        if data.bisect.test_cmd.trim().is_empty() {
+            data.bisect.fail("No test command, press : to enter one");
+            return;
+        }
+
+        data.bisect.status = format!("Running {}...", data.bisect.test_cmd);
+        let rx = spawn_test(data.bisect.test_cmd.clone(), workdir);
+        self.test_run = Some((ctx.request_timer(TEST_POLL_INTERVAL), rx));
+    }
+
+    fn poll_test(&mut self, ctx: &mut EventCtx, data: &mut AppState) {
+        let (_, rx) = match self.test_run.take() {
+            Some(test_run) => test_run,
+            None => return,
+        };
+
+        match rx.try_recv() {
+            Ok(Ok(mark)) => {
+                data.bisect.mark_current(&data.repo, mark);
+                data.refresh();
+                if data.bisect.auto_run && data.bisect.phase == BisectPhase::Testing {
+                    self.run_test(ctx, data);
+                }
+            }
+            Ok(Err(err)) => data.bisect.fail(&format!("{:#}", err)),
+            Err(TryRecvError::Empty) => {
+                self.test_run = Some((ctx.request_timer(TEST_POLL_INTERVAL), rx));
+            }
+            Err(TryRecvError::Disconnected) => data.bisect.fail("Test command didn't finish"),
+        }
+    }
+
+    fn handle_key(&mut self, ctx: &mut EventCtx, key: &KbKey, data: &mut AppState) {
+        if let KbKey::Escape = key {
+            self.test_run = None;
+            data.bisect.reset(&data.repo);
+            data.bisect = Bisect::default();
+            data.refresh();
+            data.view = View::Status;
+            ctx.resign_focus();
+            ctx.submit_command(crate::consts::CS_TAKE_FOCUS, None);
+            return;
+        }
+
+        match data.bisect.phase {
+            BisectPhase::Selecting => {
+                if data.bisect.commits.navigate(key) {
+                    return;
+                }
+                match key {
+                    KbKey::Enter => {
+                        data.bisect.start(&data.repo);
+                        data.refresh();
+                    }
+                    KbKey::Character(c) if c == "b" => data.bisect.mark_selected(BisectMark::Bad),
+                    KbKey::Character(c) if c == "g" => data.bisect.mark_selected(BisectMark::Good),
+                    _ => (),
+                }
+            }
+            BisectPhase::Testing if self.test_run.is_none() => {
+                let mark = match key {
+                    KbKey::Character(c) if c == "g" => Some(BisectMark::Good),
+                    KbKey::Character(c) if c == "b" => Some(BisectMark::Bad),
+                    KbKey::Character(c) if c == "s" => Some(BisectMark::Skip),
+                    KbKey::Character(c) if c == "t" => {
+                        self.run_test(ctx, data);
+                        None
+                    }
+                    KbKey::Character(c) if c == "a" => {
+                        data.bisect.auto_run = true;
+                        self.run_test(ctx, data);
+                        None
+                    }
+                    KbKey::Character(c) if c == ":" => {
+                        ctx.set_focus(self.test_cmd_id);
+                        None
+                    }
+                    _ => None,
+                };
+
+                if let Some(mark) = mark {
+                    data.bisect.mark_current(&data.repo, mark);
+                    data.refresh();
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+impl<W: Widget<AppState>> Controller<AppState, W> for BisectController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &Env,
+    ) {
+        match event {
+            Event::Command(cmd) if cmd.is(crate::consts::VIEW_TAKE_FOCUS) => {
+                ctx.request_focus();
+                ctx.set_handled();
+            }
+            Event::Timer(token) if self.test_run.as_ref().map(|t| t.0) == Some(*token) => {
+                self.poll_test(ctx, data);
+                ctx.set_handled();
+            }
+            // The test command textbox has focus
+            Event::KeyDown(key_event) if ctx.has_focus() && !ctx.is_focused() => {
+                match key_event.key {
+                    KbKey::Enter | KbKey::Escape => {
+                        ctx.request_focus();
+                        ctx.set_handled();
+                    }
+                    _ => child.event(ctx, event, data, env),
+                }
+            }
+            Event::KeyDown(key_event) => {
+                self.handle_key(ctx, &key_event.key, data);
+                ctx.set_handled();
+            }
+            _ => child.event(ctx, event, data, env),
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        child: &mut W,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &AppState,
+        env: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            ctx.register_for_focus();
+        }
+
+        child.lifecycle(ctx, event, data, env)
+    }
+}
@@ -1,91 +1,354 @@
-use crate::state::{AppState, CheatSheetState, Command, KeyMapLevel, L1Node, L2Node};
+use crate::git;
+use crate::keymap::{self, Key, KeyNode, KeySeq};
+use crate::palette;
+use crate::shell;
+use crate::state::{AppState, CheatSearch, CheatSheetState, CmdArgs, Command, View};
 use crate::theme;
+use crate::transient;
+use crate::widgets::bisect::Bisect;
+use crate::widgets::worktrees::Worktrees;
 use anyhow::Result;
 use druid::widget::{Flex, Label};
+use druid::Code;
 use druid::{
-    BoxConstraints, Color, Data, Env, Event, EventCtx, KeyCode, LayoutCtx, Lens, LifeCycle,
-    LifeCycleCtx, PaintCtx, Rect, RenderContext, Size, UpdateCtx, Widget, WidgetExt, WidgetPod,
+    BoxConstraints, Color, Data, Env, Event, EventCtx, KbKey, KeyCode, KeyEvent, LayoutCtx, Lens,
+    LifeCycle, LifeCycleCtx, PaintCtx, Rect, RenderContext, Size, UpdateCtx, Widget, WidgetExt,
+    WidgetPod,
 };
-use druid::{Code, KbKey};
 use std::rc::Rc;
 
-fn key_str_to_u8<T: AsRef<str>>(key: T) -> u8 {
-    *key.as_ref().as_bytes().get(0).unwrap()
+/// Run commands that don't need a selection from the fuzzybar, which either
+/// open their own view, act on the whole repository or got their argument
+/// from the keymap. Returns false for all other commands.
+fn run_direct(data: &mut AppState, cmd: Command, args: &CmdArgs) -> bool {
+    match cmd {
+        Command::SubmoduleInit | Command::SubmoduleUpdate | Command::SubmoduleSync => {
+            git::execute_cmd(&data.repo, cmd, args, &[]);
+            data.refresh();
+        }
+        Command::RemoteFetch | Command::Push if args.remote.is_some() => {
+            git::execute_cmd(&data.repo, cmd, args, &[]);
+            data.refresh();
+        }
+        Command::Bisect => {
+            data.bisect = Bisect::new(&data.repo);
+            data.view = View::Bisect;
+        }
+        Command::Worktrees => {
+            data.worktrees = Worktrees::new(&data.repo);
+            data.view = View::Worktrees;
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Key starting a search of all the entries of the keymap, unless the shown
+/// level of the keymap uses it
+const SEARCH_KEY: &str = "/";
+/// Number of search results shown
+const SEARCH_RESULTS: usize = 12;
+
+/// Whether the shown level of the keymap binds `key`, such keys reach the
+/// keymap before Space, Escape and Backspace get their own meaning
+fn is_bound(cheatsheet: &CheatSheetState, key: &Key) -> bool {
+    cheatsheet.transient.is_none()
+        && keymap::children(&cheatsheet.keymap, &cheatsheet.path)
+            .map_or(false, |nodes| nodes.contains_key(key))
+}
+
+/// Best entries matching the query of the search
+fn search_results(cheatsheet: &CheatSheetState, query: &str) -> Vec<keymap::Entry> {
+    let mut results = keymap::search(&cheatsheet.keymap, query);
+    results.truncate(SEARCH_RESULTS);
+    results
 }
 
 pub struct CheatLabel {
     key: WidgetPod<(), Label<()>>,
     desc: WidgetPod<(), Label<()>>,
-    origin: (f64, f64),
+    note: WidgetPod<(), Label<()>>,
+    /// Sizes of the labels, once laid out
+    sizes: [Size; 3],
 }
 
 impl CheatLabel {
-    pub fn new(key: String, desc: String, origin: (f64, f64)) -> Self {
+    pub fn new(key: String, desc: String, note: Option<&str>, selected: bool) -> Self {
         let lbl_key = Label::new(key).with_text_color(theme::BLUE);
         let desc = "-> ".to_owned() + &desc;
-        let lbl_desc = Label::new(desc).with_text_color(theme::GREEN);
+        let desc_color = if selected {
+            theme::HIGHLIGHT
+        } else {
+            theme::GREEN
+        };
+        let lbl_desc = Label::new(desc).with_text_color(desc_color);
+        let note = note.map(|note| format!("  {}", note)).unwrap_or_default();
+        let lbl_note = Label::new(note).with_text_color(theme::BASE_1);
 
         CheatLabel {
             key: WidgetPod::new(lbl_key),
             desc: WidgetPod::new(lbl_desc),
-            origin: origin,
+            note: WidgetPod::new(lbl_note),
+            sizes: [Size::ZERO; 3],
+        }
+    }
+
+    fn width(&self) -> f64 {
+        self.sizes.iter().map(|size| size.width).sum()
+    }
+}
+
+/// Labels of a group of the keymap, under its header
+struct Section {
+    header: Option<WidgetPod<(), Label<()>>>,
+    header_size: Size,
+    cheats: Vec<CheatLabel>,
+}
+
+impl Section {
+    fn new(header: Option<String>, cheats: Vec<CheatLabel>) -> Self {
+        Section {
+            header: header
+                .map(|header| WidgetPod::new(Label::new(header).with_text_color(theme::YELLOW))),
+            header_size: Size::ZERO,
+            cheats,
         }
     }
 }
 
 pub struct CheatSheet {
-    cheat_menu: Vec<CheatLabel>,
+    sections: Vec<Section>,
     size: Size,
 }
 
 impl CheatSheet {
     pub fn new(size: Size) -> Self {
         CheatSheet {
-            cheat_menu: vec![],
+            sections: vec![],
             size,
         }
     }
 
+    fn labels(&mut self) -> impl Iterator<Item = &mut WidgetPod<(), Label<()>>> + '_ {
+        self.sections.iter_mut().flat_map(|section| {
+            let cheats = section
+                .cheats
+                .iter_mut()
+                .flat_map(|cheat| vec![&mut cheat.key, &mut cheat.desc, &mut cheat.note]);
+            section.header.iter_mut().chain(cheats)
+        })
+    }
+
     fn update_labels(&mut self, data: &AppState) {
         if data.cheatsheet.is_hidden {
             return;
         }
 
-        self.cheat_menu.clear();
+        self.sections.clear();
+
+        if let Some(transient) = &data.cheatsheet.transient {
+            let cheats = transient::menu(transient)
+                .into_iter()
+                .map(|(key, desc)| CheatLabel::new(key, desc, None, false))
+                .collect();
+            self.sections.push(Section::new(None, cheats));
+            return;
+        }
+
+        if let Some(search) = &data.cheatsheet.search {
+            let cheats = search_results(&data.cheatsheet, &search.query)
+                .into_iter()
+                .enumerate()
+                .map(|(idx, entry)| {
+                    CheatLabel::new(
+                        entry.seq.to_string(),
+                        entry.title,
+                        entry.node.description.as_deref(),
+                        idx == search.selected,
+                    )
+                })
+                .collect();
+            let header = format!("{}{}", SEARCH_KEY, search.query);
+            self.sections.push(Section::new(Some(header), cheats));
+            return;
+        }
+
+        let nodes = keymap::children(&data.cheatsheet.keymap, &data.cheatsheet.path);
+        for (group, nodes) in nodes.map(keymap::groups).unwrap_or_default() {
+            let cheats = nodes
+                .into_iter()
+                .map(|(key, node)| {
+                    CheatLabel::new(
+                        key.to_string(),
+                        node.name.clone(),
+                        node.description.as_deref(),
+                        false,
+                    )
+                })
+                .collect();
+            self.sections
+                .push(Section::new(group.map(str::to_owned), cheats));
+        }
+    }
+
+    fn reset_menu(&self, data: &mut AppState) {
+        data.cheatsheet.path = KeySeq::default();
+        data.cheatsheet.transient = None;
+        data.cheatsheet.search = None;
+    }
+
+    /// Edit the query of the search, move through its results or run one
+    fn search_key(&mut self, ctx: &mut EventCtx, data: &mut AppState, event: &KeyEvent) {
+        let mut search = match data.cheatsheet.search.clone() {
+            Some(search) => search,
+            None => return,
+        };
 
-        match data.cheatsheet.current_level {
-            KeyMapLevel::L1 => {
-                for (key, keymap) in data.cheatsheet.keymap.iter() {
-                    self.cheat_menu.push(CheatLabel::new(
-                        std::str::from_utf8(&[*key]).unwrap().to_owned(),
-                        keymap.name.clone(),
-                        (0.0, 0.0),
-                    ))
+        match &event.key {
+            KbKey::Escape => {
+                data.cheatsheet.is_hidden = true;
+                self.reset_menu(data);
+                return;
+            }
+            KbKey::Enter => {
+                self.jump(ctx, data, &search);
+                return;
+            }
+            KbKey::Backspace => {
+                if search.query.pop().is_none() {
+                    data.cheatsheet.search = None;
+                    return;
                 }
+                search.selected = 0;
             }
-            KeyMapLevel::L2(parent_node) => {
-                if let Some(l1_node) = data.cheatsheet.keymap.get(&parent_node) {
-                    for (key, keymap) in l1_node.next.iter() {
-                        self.cheat_menu.push(CheatLabel::new(
-                            std::str::from_utf8(&[*key]).unwrap().to_owned(),
-                            keymap.name.clone(),
-                            (0.0, 0.0),
-                        ))
-                    }
+            KbKey::ArrowUp => search.selected = search.selected.saturating_sub(1),
+            KbKey::ArrowDown => search.selected += 1,
+            KbKey::Character(c)
+                if !event.mods.ctrl() && !event.mods.alt() && !event.mods.meta() =>
+            {
+                search.query.push_str(c);
+                search.selected = 0;
+            }
+            _ => return,
+        }
+
+        let results = search_results(&data.cheatsheet, &search.query).len();
+        search.selected = search.selected.min(results.saturating_sub(1));
+        data.cheatsheet.search = Some(search);
+    }
+
+    /// Run the selected result of the search, as if its keys were pressed
+    fn jump(&mut self, ctx: &mut EventCtx, data: &mut AppState, search: &CheatSearch) {
+        let entry = match search_results(&data.cheatsheet, &search.query)
+            .into_iter()
+            .nth(search.selected)
+        {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        data.cheatsheet.search = None;
+        self.run_entry(ctx, data, entry.seq, entry.node);
+    }
+
+    /// List every action in the fuzzybar, see [`crate::palette`]
+    fn open_palette(&mut self, ctx: &mut EventCtx, data: &mut AppState) {
+        let node = KeyNode::unbound("Command palette".to_owned(), Command::Palette);
+        self.run_node(ctx, data, &node, &CmdArgs::default());
+    }
+
+    /// Follow the pressed key down the keymap, running the command at its end
+    fn press_key(&mut self, ctx: &mut EventCtx, data: &mut AppState, key: Key) {
+        if let Some(mut transient) = data.cheatsheet.transient.clone() {
+            if Key::parse(transient::RUN_KEY).ok() == Some(key.clone()) {
+                transient::remember(&data.repo, &mut data.cheatsheet, &transient);
+                self.run_node(ctx, data, &transient.node, &transient.args);
+            } else if transient::press_key(&mut transient, &key) {
+                data.cheatsheet.transient = Some(transient);
+            }
+            return;
+        }
+
+        let node = match keymap::children(&data.cheatsheet.keymap, &data.cheatsheet.path)
+            .and_then(|nodes| nodes.get(&key))
+        {
+            Some(node) => node.clone(),
+            None => {
+                if Key::parse(SEARCH_KEY).ok() == Some(key) {
+                    data.cheatsheet.search = Some(CheatSearch::default());
                 }
+                return;
             }
+        };
+
+        if node.command.is_none() && node.shell.is_none() {
+            data.cheatsheet.path.0.push_back(key);
+            return;
         }
+        let mut seq = data.cheatsheet.path.clone();
+        seq.0.push_back(key);
+        self.run_entry(ctx, data, seq, node);
     }
 
-    fn reset_menu(&self, data: &mut AppState) {
-        data.cheatsheet.current_node = 0;
-        data.cheatsheet.current_level = KeyMapLevel::L1;
+    /// Run the keymap node reached with `seq`, or show its transient menu
+    fn run_entry(&mut self, ctx: &mut EventCtx, data: &mut AppState, seq: KeySeq, node: KeyNode) {
+        match (node.command, &node.shell) {
+            (Some(cmd), None) if cmd.has_transient() => {
+                let transient = transient::open(&data.repo, &data.cheatsheet, seq, node);
+                data.cheatsheet.transient = Some(transient);
+                data.cheatsheet.is_hidden = false;
+            }
+            _ => self.run_node(ctx, data, &node, &node.args),
+        }
+    }
+
+    /// Run the command or shell command of a keymap node with `args`
+    fn run_node(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut AppState,
+        node: &KeyNode,
+        args: &CmdArgs,
+    ) {
+        data.cheatsheet.is_hidden = true;
+        self.reset_menu(data);
+
+        let cmd = match (node.command, &node.shell) {
+            (Some(cmd), _) => cmd,
+            // The items to pick the selection from come from the source
+            (None, Some(shell)) if shell::needs_selection(shell) => Command::ShowMenu,
+            (None, Some(shell)) => {
+                data.run_shell(shell, &[]);
+                return;
+            }
+            (None, None) => return,
+        };
+
+        if node.shell.is_none() && run_direct(data, cmd, args) {
+            if data.view != View::Status {
+                ctx.resign_focus();
+                ctx.submit_command(crate::consts::VIEW_TAKE_FOCUS, None);
+            }
+            return;
+        }
+
+        data.fuzzybar.is_hidden = false;
+        data.fuzzybar.cmd = cmd;
+        data.fuzzybar.shell_source = node.source.clone();
+        data.fuzzybar.shell_cmd = node.shell.clone();
+        data.fuzzybar.args = args.clone();
+
+        if ctx.is_focused() {
+            ctx.focus_next();
+        }
     }
 }
 
-const CHEATSHEET_HEIGHT: f64 = 200.0;
 const PADDING_TOP: f64 = 8.0;
 const PADDING_LEFT: f64 = 8.0;
+/// Space between the columns of entries
+const COLUMN_SPACING: f64 = 24.0;
+const ROW_SPACING: f64 = 4.0;
 
 impl Widget<AppState> for CheatSheet {
     fn lifecycle(
@@ -102,9 +365,8 @@ impl Widget<AppState> for CheatSheet {
             _ => (),
         }
 
-        for cheat in self.cheat_menu.iter_mut() {
-            cheat.key.lifecycle(ctx, event, &(), _env);
-            cheat.desc.lifecycle(ctx, event, &(), _env);
+        for label in self.labels() {
+            label.lifecycle(ctx, event, &(), _env);
         }
     }
 
@@ -118,12 +380,30 @@ impl Widget<AppState> for CheatSheet {
                 ctx.request_focus();
                 ctx.set_handled();
                 self.reset_menu(data);
+                if let Some(entry) = data.cheatsheet.picked.take() {
+                    self.run_entry(ctx, data, entry.seq, entry.node);
+                }
             }
         }
 
         if let Event::KeyUp(key_event) = event {
+            if Key::from_event(key_event) == Key::parse(palette::PALETTE_KEY).ok() {
+                self.open_palette(ctx, data);
+                return;
+            }
+            if !data.cheatsheet.is_hidden && data.cheatsheet.search.is_some() {
+                self.search_key(ctx, data, key_event);
+                return;
+            }
+
+            if let Some(key) = Key::from_event(key_event) {
+                if is_bound(&data.cheatsheet, &key) {
+                    self.press_key(ctx, data, key);
+                    return;
+                }
+            }
+
             let code = key_event.code;
-            let key = &key_event.key;
 
             match code {
                 Code::Space => {
@@ -137,34 +417,18 @@ impl Widget<AppState> for CheatSheet {
                 }
                 Code::Backspace => {
                     if !data.cheatsheet.is_hidden {
-                        if let KeyMapLevel::L2(_) = data.cheatsheet.current_level {
-                            self.reset_menu(data);
-                        } else {
+                        if data.cheatsheet.transient.is_some() {
+                            data.cheatsheet.transient = None;
+                        } else if data.cheatsheet.path.is_empty() {
                             data.cheatsheet.is_hidden = true;
+                        } else {
+                            data.cheatsheet.path.0.pop_back();
                         }
                     }
                 }
                 _ => {
-                    if let KbKey::Character(c) = key {
-                        if let KeyMapLevel::L2(parent_node) = data.cheatsheet.current_level {
-                            if let Some(l1_node) = data.cheatsheet.keymap.get(&parent_node) {
-                                if let Some(l2_node) = l1_node.next.get(&key_str_to_u8(c)) {
-                                    data.cheatsheet.is_hidden = true;
-
-                                    data.fuzzybar.is_hidden = false;
-                                    data.fuzzybar.cmd = l2_node.command;
-
-                                    if ctx.is_focused() {
-                                        ctx.focus_next();
-                                    }
-                                }
-                            }
-                        }
-
-                        if let Some(l1_node) = data.cheatsheet.keymap.get(&key_str_to_u8(c)) {
-                            data.cheatsheet.current_node = l1_node.key;
-                            data.cheatsheet.current_level = KeyMapLevel::L2(l1_node.key);
-                        }
+                    if let Some(key) = Key::from_event(key_event) {
+                        self.press_key(ctx, data, key);
                     }
                 }
             }
@@ -179,9 +443,8 @@ impl Widget<AppState> for CheatSheet {
 
         self.update_labels(data);
 
-        for cheat in self.cheat_menu.iter_mut() {
-            cheat.key.update(ctx, &(), env);
-            cheat.desc.update(ctx, &(), env);
+        for label in self.labels() {
+            label.update(ctx, &(), env);
         }
         ctx.request_layout();
         ctx.request_paint();
@@ -198,55 +461,68 @@ impl Widget<AppState> for CheatSheet {
             return (0.0, 0.0).into();
         }
 
-        let mut size = bc.max();
-        size.width = size.width;
-        size.height = CHEATSHEET_HEIGHT;
-        self.size = size;
-
-        let max_width = size.width;
-
-        let font_size = env.get(druid::theme::TEXT_SIZE_NORMAL);
+        let child_bc = bc.loosen();
+        let mut column_width: f64 = 0.0;
+        let mut row_height: f64 = 0.0;
+        for section in self.sections.iter_mut() {
+            if let Some(header) = &mut section.header {
+                section.header_size = header.layout(ctx, &child_bc, &(), env);
+                row_height = row_height.max(section.header_size.height);
+            }
+            for cheat in section.cheats.iter_mut() {
+                cheat.sizes = [
+                    cheat.key.layout(ctx, &child_bc, &(), env),
+                    cheat.desc.layout(ctx, &child_bc, &(), env),
+                    cheat.note.layout(ctx, &child_bc, &(), env),
+                ];
+                column_width = column_width.max(cheat.width());
+                row_height = cheat
+                    .sizes
+                    .iter()
+                    .fold(row_height, |height, size| height.max(size.height));
+            }
+        }
 
-        let mut pos_x = 0.0 + PADDING_LEFT;
-        let mut pos_y = 0.0 + PADDING_TOP;
-        let mut newline = false;
+        // As many columns as fit, the entries of a section fill them top to
+        // bottom so resizing the window reflows them
+        let max_width = bc.max().width;
+        let columns = ((max_width - PADDING_LEFT) / (column_width + COLUMN_SPACING))
+            .floor()
+            .max(1.0) as usize;
+        let line_height = row_height + ROW_SPACING;
+
+        let mut pos_y = PADDING_TOP;
+        for section in self.sections.iter_mut() {
+            if let Some(header) = &mut section.header {
+                let rect = Rect::from_origin_size((PADDING_LEFT, pos_y), section.header_size);
+                header.set_layout_rect(ctx, &(), env, rect);
+                pos_y += line_height;
+            }
 
-        let child_bc = bc.loosen();
-        for cheat in self.cheat_menu.iter_mut() {
-            let key_size = cheat.key.layout(ctx, &child_bc, &(), env);
-            let desc_size = cheat.desc.layout(ctx, &child_bc, &(), env);
-
-            let next_width = pos_x + key_size.width + desc_size.width;
-
-            if next_width > max_width {
-                pos_y = pos_y + font_size + PADDING_TOP;
-                pos_x = 0.0 + PADDING_LEFT;
-                cheat.origin = (pos_x, pos_y);
-                newline = true;
-            } else {
-                if newline {
-                    pos_x = next_width + PADDING_LEFT;
+            let rows = (section.cheats.len() + columns - 1) / columns;
+            for (idx, cheat) in section.cheats.iter_mut().enumerate() {
+                let column = (idx / rows) as f64;
+                let mut pos_x = PADDING_LEFT + column * (column_width + COLUMN_SPACING);
+                let row_y = pos_y + (idx % rows) as f64 * line_height;
+
+                let [key_size, desc_size, note_size] = cheat.sizes;
+                for (label, size) in [
+                    (&mut cheat.key, key_size),
+                    (&mut cheat.desc, desc_size),
+                    (&mut cheat.note, note_size),
+                ]
+                .iter_mut()
+                {
+                    let rect = Rect::from_origin_size((pos_x, row_y), *size);
+                    label.set_layout_rect(ctx, &(), env, rect);
+                    pos_x += size.width;
                 }
-                cheat.origin = (pos_x, pos_y);
-                pos_x = next_width + PADDING_LEFT;
             }
-
-            cheat.key.set_layout_rect(
-                ctx,
-                &(),
-                env,
-                Rect::from_origin_size(cheat.origin, key_size),
-            );
-
-            let desc_origin = (cheat.origin.0 + key_size.width, cheat.origin.1);
-            cheat.desc.set_layout_rect(
-                ctx,
-                &(),
-                env,
-                Rect::from_origin_size(desc_origin, desc_size),
-            );
+            pos_y += rows as f64 * line_height + ROW_SPACING;
         }
 
+        let size = Size::new(max_width, (pos_y + PADDING_TOP).min(bc.max().height));
+        self.size = size;
         size
     }
 
@@ -261,9 +537,46 @@ impl Widget<AppState> for CheatSheet {
         ctx.blurred_rect(rect, 2.0, &base1_color);
         ctx.fill(rect, &bg_color);
 
-        for cheat in self.cheat_menu.iter_mut() {
-            cheat.key.paint(ctx, &(), env);
-            cheat.desc.paint(ctx, &(), env);
+        for label in self.labels() {
+            label.paint(ctx, &(), env);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests;
+
+    #[test]
+    fn space_and_backspace_should_be_bindable() {
+        let config = r#"
+            [SPC]
+            key = "SPC"
+            name = "Commit"
+            command = "Commit"
+
+            [b]
+            key = "b"
+            name = "Branching"
+
+            [b.next.Backspace]
+            key = "Backspace"
+            name = "Delete"
+            command = "BranchDelete"
+        "#;
+        let table = toml::from_str::<toml::value::Table>(config).unwrap();
+        let (_td, repo) = tests::repo_init();
+        let mut data = tests::state_init(repo);
+        data.cheatsheet.keymap = keymap::de_keymap(toml::Value::Table(table)).unwrap();
+
+        let space = Key::parse("SPC").unwrap();
+        let backspace = Key::parse("Backspace").unwrap();
+        assert!(is_bound(&data.cheatsheet, &space));
+        assert!(!is_bound(&data.cheatsheet, &backspace));
+
+        data.cheatsheet.path = KeySeq::parse("b").unwrap();
+        assert!(!is_bound(&data.cheatsheet, &space));
+        assert!(is_bound(&data.cheatsheet, &backspace));
+    }
+}
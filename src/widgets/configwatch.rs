@@ -0,0 +1,172 @@
+use crate::config;
+use crate::state::AppState;
+use druid::widget::Controller;
+use druid::{Env, Event, EventCtx, TimerToken, Widget};
+use git2::Repository;
+use log::{error, info};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Modification time and size of a file, None if it doesn't exist
+type FileState = Option<(SystemTime, u64)>;
+
+/// Reloads the config when one of its files changes. A config that fails to
+/// load is reported along with the notifications and the previous one is
+/// kept.
+pub struct ConfigWatch {
+    /// Config file given with `--config`
+    explicit: Option<PathBuf>,
+    files: Vec<(PathBuf, FileState)>,
+    timer: TimerToken,
+}
+
+impl ConfigWatch {
+    pub fn new(explicit: Option<PathBuf>) -> ConfigWatch {
+        ConfigWatch {
+            explicit,
+            files: vec![],
+            timer: TimerToken::INVALID,
+        }
+    }
+
+    /// Whether the config files changed since the last check. Switching to
+    /// another repository changes the files themselves.
+    fn changed(&mut self, repo: &Repository) -> bool {
+        let files = config::watched_files(repo, self.explicit.as_deref())
+            .into_iter()
+            .map(|path| {
+                let state = std::fs::metadata(&path)
+                    .and_then(|meta| Ok((meta.modified()?, meta.len())))
+                    .ok();
+                (path, state)
+            })
+            .collect::<Vec<_>>();
+
+        let changed = !self.files.is_empty() && files != self.files;
+        self.files = files;
+        changed
+    }
+
+    fn reload(&self, data: &mut AppState) {
+        match config::try_load(&data.repo, self.explicit.as_deref()) {
+            Ok(config) => {
+                info!("Reloaded the config");
+                // Errors of the previous attempt are fixed
+                data.config_errors.clear();
+                data.apply_config(config);
+            }
+            Err(errors) => {
+                for err in errors.iter() {
+                    error!("{}", err);
+                }
+                data.config_errors = errors.iter().map(|err| err.to_string()).collect();
+                data.config_errors
+                    .push_back("Keeping the previous config".to_owned());
+            }
+        }
+    }
+}
+
+impl<W: Widget<AppState>> Controller<AppState, W> for ConfigWatch {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &Env,
+    ) {
+        match event {
+            Event::WindowConnected => {
+                self.changed(&data.repo);
+                self.timer = ctx.request_timer(POLL_INTERVAL);
+            }
+            Event::Timer(token) if *token == self.timer => {
+                if self.changed(&data.repo) {
+                    self.reload(data);
+                }
+                self.timer = ctx.request_timer(POLL_INTERVAL);
+                ctx.set_handled();
+                return;
+            }
+            _ => (),
+        }
+        child.event(ctx, event, data, env);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::{Key, KeySeq};
+    use crate::tests;
+    use im::vector;
+
+    #[test]
+    fn changes_to_config_files_should_be_noticed() {
+        let (td, repo) = tests::repo_init();
+        let path = td.path().join(config::CONFIG_FILE);
+        std::fs::write(&path, "").unwrap();
+
+        let mut watch = ConfigWatch::new(Some(path.clone()));
+        assert!(!watch.changed(&repo));
+        assert!(!watch.changed(&repo));
+
+        std::fs::write(&path, "[keymap.map.b]\nname = \"Branches\"\n").unwrap();
+        assert!(watch.changed(&repo));
+        assert!(!watch.changed(&repo));
+
+        repo.config().unwrap().set_str("gitools.x", "y").unwrap();
+        assert!(watch.changed(&repo));
+    }
+
+    #[test]
+    fn broken_config_should_keep_the_previous_one() {
+        let (td, repo) = tests::repo_init();
+        let path = td.path().join(config::CONFIG_FILE);
+        std::fs::write(&path, "[keymap.map.b]\nname = \"Branches\"\n").unwrap();
+        let mut data = tests::state_init(repo);
+        let watch = ConfigWatch::new(Some(path.clone()));
+        let b = Key::parse("b").unwrap();
+
+        data.notifications.push_back("Can't run git log".to_owned());
+
+        watch.reload(&mut data);
+        assert_eq!(data.cheatsheet.keymap.get(&b).unwrap().name, "Branches");
+        assert!(data.config_errors.is_empty());
+
+        std::fs::write(&path, "[keymap.map.b]\nname = 1\n").unwrap();
+        watch.reload(&mut data);
+        assert_eq!(data.cheatsheet.keymap.get(&b).unwrap().name, "Branches");
+        let last = data.config_errors.back().unwrap();
+        assert_eq!(last, "Keeping the previous config");
+
+        std::fs::write(&path, "[keymap.map.b]\nname = \"Branching\"\n").unwrap();
+        watch.reload(&mut data);
+        assert_eq!(data.cheatsheet.keymap.get(&b).unwrap().name, "Branching");
+        assert!(data.config_errors.is_empty());
+        assert_eq!(data.notifications, vector!["Can't run git log".to_owned()]);
+    }
+
+    #[test]
+    fn cheatsheet_should_only_be_reset_if_the_keymap_changed() {
+        let (td, repo) = tests::repo_init();
+        let path = td.path().join(config::CONFIG_FILE);
+        std::fs::write(&path, "").unwrap();
+        let mut data = tests::state_init(repo);
+        let watch = ConfigWatch::new(Some(path.clone()));
+        let b = KeySeq::parse("b").unwrap();
+        data.cheatsheet.path = b.clone();
+
+        std::fs::write(&path, "[transient]\npersist = true\n").unwrap();
+        watch.reload(&mut data);
+        assert!(data.cheatsheet.persist_args);
+        assert_eq!(data.cheatsheet.path, b);
+
+        std::fs::write(&path, "[keymap.map.b]\nname = \"Branches\"\n").unwrap();
+        watch.reload(&mut data);
+        assert!(data.cheatsheet.path.is_empty());
+    }
+}
@@ -0,0 +1,212 @@
+use crate::git::{self, FileCommit};
+use crate::state::{AppState, View};
+use crate::theme;
+use crate::widgets::listview::{ListRow, ListView};
+use crate::widgets::patch;
+use anyhow::Result;
+use druid::widget::{Controller, CrossAxisAlignment, Flex, Label, Scroll};
+use druid::{
+    Data, Env, Event, EventCtx, KbKey, Lens, LensExt, LifeCycle, LifeCycleCtx, Widget, WidgetExt,
+};
+use git2::{Oid, Repository};
+use im::{vector, Vector};
+use log::error;
+
+const COMMITS_WIDTH: f64 = 480.0;
+const PATCH_HEIGHT: f64 = 400.0;
+
+#[derive(Clone, Data, Lens, Debug, PartialEq)]
+pub struct FileLogEntry {
+    pub oid: String,
+    pub summary: String,
+    pub author: String,
+    pub time: String,
+    pub path: String,
+    pub old_path: Option<String>,
+    pub selected: bool,
+}
+
+impl FileLogEntry {
+    fn new(repo: &Repository, file_commit: &FileCommit) -> Result<Self> {
+        let commit = repo.find_commit(file_commit.oid)?;
+        Ok(FileLogEntry {
+            oid: format!("{}", file_commit.oid),
+            summary: commit.summary().unwrap_or_default().to_owned(),
+            author: commit.author().name().unwrap_or_default().to_owned(),
+            time: git::format_time(&commit.time()),
+            path: file_commit.path.clone(),
+            old_path: file_commit.old_path.clone(),
+            selected: false,
+        })
+    }
+
+    fn file_commit(&self) -> Result<FileCommit> {
+        Ok(FileCommit {
+            oid: Oid::from_str(&self.oid)?,
+            path: self.path.clone(),
+            old_path: self.old_path.clone(),
+        })
+    }
+}
+
+impl ListRow for FileLogEntry {
+    fn text(&self) -> String {
+        let summary = match &self.old_path {
+            Some(old_path) => format!("{} (renamed from {})", self.summary, old_path),
+            None => self.summary.clone(),
+        };
+        format!(
+            "{}  {}  {}  {}",
+            &self.oid[..7],
+            self.time,
+            self.author,
+            summary
+        )
+    }
+
+    fn is_selected(&self) -> bool {
+        self.selected
+    }
+
+    fn set_selected(&mut self, selected: bool) {
+        self.selected = selected;
+    }
+}
+
+/// History of a single file, listing the commits that touched it and the patch
+/// of the selected commit restricted to that file.
+#[derive(Clone, Data, Lens)]
+pub struct FileLog {
+    path: String,
+    commits: ListView<FileLogEntry>,
+    patch: Vector<String>,
+}
+
+impl FileLog {
+    pub fn new(repo: &Repository, path: &str) -> Self {
+        let history = git::get_file_history(repo, path);
+        if let Err(err) = history {
+            error!("Failed to get history of {}: {}", path, err);
+            return FileLog {
+                path: path.to_owned(),
+                ..Self::default()
+            };
+        }
+
+        let entries = history
+            .unwrap()
+            .iter()
+            .filter_map(|file_commit| FileLogEntry::new(repo, file_commit).ok())
+            .collect();
+
+        let mut file_log = FileLog {
+            path: path.to_owned(),
+            commits: ListView::new(entries),
+            patch: vector![],
+        };
+        file_log.update_patch(repo);
+        file_log
+    }
+
+    fn update_patch(&mut self, repo: &Repository) {
+        let entry = match self.commits.selected() {
+            Some(entry) => entry,
+            None => {
+                self.patch = vector![];
+                return;
+            }
+        };
+
+        match entry
+            .file_commit()
+            .and_then(|file_commit| git::get_file_patch(repo, &file_commit))
+        {
+            Ok(patch) => self.patch = patch::to_lines(&patch),
+            Err(err) => {
+                error!("Failed to get patch: {}", err);
+                self.patch = vector![];
+            }
+        }
+    }
+
+    pub fn widget() -> impl Widget<AppState> {
+        let header =
+            Label::dynamic(|app: &AppState, _| format!("History of {}", app.file_log.path))
+                .with_text_color(theme::BLUE);
+
+        let commits = ListView::widget()
+            .lens(AppState::file_log.then(FileLog::commits))
+            .fix_width(COMMITS_WIDTH);
+
+        let patch = Scroll::new(patch::widget().lens(AppState::file_log.then(FileLog::patch)))
+            .fix_height(PATCH_HEIGHT);
+
+        let body = Flex::row()
+            .with_child(commits)
+            .with_flex_child(patch, 1.0)
+            .cross_axis_alignment(CrossAxisAlignment::Start);
+
+        Flex::column()
+            .with_child(header)
+            .with_spacer(8.0)
+            .with_child(body)
+            .cross_axis_alignment(CrossAxisAlignment::Start)
+            .controller(FileLogController)
+    }
+}
+
+impl Default for FileLog {
+    fn default() -> Self {
+        FileLog {
+            path: "".to_owned(),
+            commits: ListView::default(),
+            patch: vector![],
+        }
+    }
+}
+
+struct FileLogController;
+
+impl<W: Widget<AppState>> Controller<AppState, W> for FileLogController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &Env,
+    ) {
+        match event {
+            Event::Command(cmd) if cmd.is(crate::consts::VIEW_TAKE_FOCUS) => {
+                ctx.request_focus();
+                ctx.set_handled();
+            }
+            Event::KeyDown(key_event) => {
+                if data.file_log.commits.navigate(&key_event.key) {
+                    data.file_log.update_patch(&data.repo);
+                } else if let KbKey::Escape = key_event.key {
+                    data.view = View::Status;
+                    ctx.resign_focus();
+                    ctx.submit_command(crate::consts::CS_TAKE_FOCUS, None);
+                }
+                ctx.set_handled();
+            }
+            _ => child.event(ctx, event, data, env),
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        child: &mut W,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &AppState,
+        env: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            ctx.register_for_focus();
+        }
+
+        child.lifecycle(ctx, event, data, env)
+    }
+}
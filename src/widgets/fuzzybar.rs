@@ -1,36 +1,86 @@
 use crate::git;
-use crate::state::{AppState, Command, FuzzybarState, ListItem};
+use crate::history::History;
+use crate::palette;
+use crate::provider::{self, ItemKind, Source, SourceItem};
+use crate::state::{AppState, Command, FuzzybarState, ListItem, SourceFilter, View};
 use crate::theme;
-use druid::widget::{Label, List, Painter, Scroll, SizedBox, TextBox};
+use crate::widgets::filelog::FileLog;
+use crate::widgets::matchlabel::MatchLabel;
+use crate::widgets::patch;
+use crate::widgets::preview::{self, PreviewKind};
+use crate::widgets::reflog::Reflog;
+use crate::widgets::worktrees::Worktrees;
+use chrono::Local;
+use druid::widget::{Label, List, Padding, Painter, Scroll, SizedBox, TextBox};
 use druid::{
     BoxConstraints, Code, Color, Data, Env, Event, EventCtx, KbKey, KeyCode, LayoutCtx, Lens,
-    LensExt, LifeCycle, LifeCycleCtx, PaintCtx, Rect, RenderContext, Size, UpdateCtx, Widget,
-    WidgetExt, WidgetPod,
+    LensExt, LifeCycle, LifeCycleCtx, PaintCtx, Point, Rect, RenderContext, Size, TimerToken,
+    UpdateCtx, Widget, WidgetExt, WidgetPod,
 };
 use im::{vector, Vector};
+use log::error;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, TryRecvError};
 use std::time::{Duration, Instant};
 
-const FUZZYBAR_HEIGHT: f64 = 200.0;
+/// Number of matches shown at once, only these rows are laid out
+pub const FUZZYBAR_ROWS: usize = 8;
 const LABEL_HEIGHT: f64 = 24.0;
 const LABEL_PADDING: f64 = 3.0;
-const LABEL_SCROLL: f64 = LABEL_HEIGHT; // + (2.0 * LABEL_PADDING);
+const FUZZYBAR_HEIGHT: f64 = FUZZYBAR_ROWS as f64 * LABEL_HEIGHT;
 const DEBOUNCE_DELTA: Duration = Duration::from_millis(200);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Share of the width taken by the matches when a preview is shown
+const MATCHES_WIDTH_RATIO: f64 = 0.4;
+const MARK_WIDTH: f64 = 3.0;
 
 /// Fuzzybar is a fuzzy search bar similar to those provided by those completion
 /// frameworks provided by emacs helm. The main components are a querybar which
 /// contains the search textbox and a list of items that matches the text.
 ///
-/// A Fuzzybar whenever initiated must be provided with some source data ([`im::Vector<T>`])
-/// and a closure that will be invoked on executing a single item
+/// The items of a command are listed by its [`provider::Provider`], large sources
+/// are streamed in while the fuzzybar is already open.
 ///
 /// Fuzzybar also paints its own selection rects and highlights its background.
+/// Only the rows in view are laid out, so sources with tens of thousands of
+/// items stay fast. The selection is moved with Ctrl+J/K, PageUp/PageDown,
+/// Home/End, the mouse wheel or a click, a double click runs the command.
+///
+/// Commands that accept several items let them be marked with Tab, Alt+A marks
+/// all matches and Alt+D clears the marks. The marked items are passed to the
+/// command instead of the one under the cursor.
+///
+/// Picked items and queries are remembered per repository. Items picked often
+/// and recently rank higher, Up and Down recall the queries of the command.
+///
+/// Commands with a [`Command::prompt`] take the typed query as their argument
+/// instead of an item. The query is validated while typing and Enter only
+/// submits a valid one.
+///
+/// For commands whose items are branches, tags, commits or stashes a preview of
+/// the selected item is shown next to the matches. Previews are built in the
+/// background and cached until the source changes.
 pub struct Fuzzybar {
     querybar: WidgetPod<AppState, SizedBox<AppState>>,
-    matches: WidgetPod<Vector<ListItem>, Scroll<Vector<ListItem>, List<ListItem>>>,
+    counter: WidgetPod<FuzzybarState, Label<FuzzybarState>>,
+    error: WidgetPod<FuzzybarState, Padding<FuzzybarState>>,
+    matches: WidgetPod<Vector<ListItem>, List<ListItem>>,
+    preview: WidgetPod<Vector<String>, Box<dyn Widget<Vector<String>>>>,
+    preview_cache: HashMap<String, Vector<String>>,
+    preview_run: Option<(TimerToken, String, Receiver<anyhow::Result<Vector<String>>>)>,
+    history: History,
+    /// Repository the history was loaded from
+    history_repo: Option<PathBuf>,
+    /// Index of the recalled query in the history, None while typing
+    query_recall: Option<usize>,
     size: Size,
     ts_since_last_event: Instant,
-    selected_idx: usize,
-    scrolled: bool,
+    /// Wheel movement not yet turned into a selection change
+    wheel_delta: f64,
+    /// Command and shell source the items were loaded for, None to reload
+    source_key: Option<(Command, Option<String>, Option<SourceFilter>)>,
+    source_stream: Option<(TimerToken, Receiver<Vec<SourceItem>>)>,
 }
 
 impl Fuzzybar {
@@ -41,7 +91,32 @@ impl Fuzzybar {
             .expand_width();
         let querybar = WidgetPod::new(textbox);
 
-        let scroll = Scroll::new(List::new(|| {
+        let counter = Label::dynamic(|fuzzybar: &FuzzybarState, _env| {
+            if let Some(prompt) = fuzzybar.cmd.prompt() {
+                return prompt.to_owned();
+            }
+
+            let mut count = format!(
+                "{}/{}",
+                fuzzybar.filtered.items.len(),
+                fuzzybar.source.len()
+            );
+            if !fuzzybar.marked.is_empty() {
+                count.push_str(&format!(" ({} marked)", fuzzybar.marked.len()));
+            }
+            count
+        })
+        .with_text_color(theme::BASE_1);
+        let counter = WidgetPod::new(counter);
+
+        let error = Label::dynamic(|fuzzybar: &FuzzybarState, _env| {
+            fuzzybar.error.clone().unwrap_or_default()
+        })
+        .with_text_color(theme::RED)
+        .padding(LABEL_PADDING);
+        let error = WidgetPod::new(error);
+
+        let matches = List::new(|| {
             let painter = Painter::new(|ctx, item: &ListItem, env| {
                 let color = if item.selected {
                     env.get(theme::BASE_2)
@@ -52,125 +127,376 @@ impl Fuzzybar {
                 let bounds = ctx.size().to_rect();
 
                 ctx.fill(bounds, &color);
+
+                if item.marked {
+                    let mark = Rect::from_origin_size((0.0, 0.0), (MARK_WIDTH, bounds.height()));
+                    ctx.fill(mark, &env.get(theme::HIGHLIGHT));
+                }
             });
-            Label::new(|item: &ListItem, _env: &Env| item.name.to_owned())
+            MatchLabel::new()
                 .padding(LABEL_PADDING)
                 .fix_height(LABEL_HEIGHT)
                 .background(painter)
-        }))
-        .vertical();
+        });
+        let matches = WidgetPod::new(matches);
 
-        let matches = WidgetPod::new(scroll);
+        let preview: Box<dyn Widget<Vector<String>>> =
+            Box::new(Scroll::new(patch::widget().padding(LABEL_PADDING)));
+        let preview = WidgetPod::new(preview);
 
         let size = (0.0, FUZZYBAR_HEIGHT).into();
 
         Fuzzybar {
             querybar,
+            counter,
+            error,
             matches,
+            preview,
+            preview_cache: HashMap::new(),
+            preview_run: None,
+            history: History::default(),
+            history_repo: None,
+            query_recall: None,
             size,
             ts_since_last_event: Instant::now(),
-            selected_idx: 0,
-            scrolled: false,
+            wheel_delta: 0.0,
+            source_key: None,
+            source_stream: None,
         }
     }
 
-    fn update_source(&mut self, data: &mut AppState) {
-        let source = match data.fuzzybar.cmd {
-            Command::BranchCheckout => data.git.all_branches.clone(),
-            _ => vector![],
-        };
+    /// Load the items of the command from its provider
+    fn update_source(&mut self, ctx: &mut EventCtx, data: &mut AppState) {
+        self.preview_cache.clear();
+        self.source_stream = None;
+        self.load_history(data);
+
+        let key = source_key(data);
+        data.fuzzybar.source = vector![];
+        if data.fuzzybar.cmd == Command::ThemeSwitch && key.1.is_none() {
+            data.fuzzybar.source = data
+                .theme
+                .themes
+                .keys()
+                .map(|name| SourceItem::new(name, ItemKind::Line))
+                .collect();
+        } else if data.fuzzybar.cmd == Command::Palette && key.1.is_none() {
+            data.fuzzybar.source = palette::items(&data.cheatsheet.keymap);
+        } else if let Some(provider) = provider::provider(key.0, key.1.as_deref(), key.2) {
+            match provider.load(&data.repo) {
+                Source::Ready(items) => data.fuzzybar.source = items.into_iter().collect(),
+                Source::Stream(rx) => {
+                    self.source_stream = Some((ctx.request_timer(POLL_INTERVAL), rx));
+                }
+            }
+        }
+        self.source_key = Some(key);
 
-        data.fuzzybar.source = source;
+        data.fuzzybar.frecencies = self
+            .history
+            .frecencies(data.fuzzybar.cmd, Local::now().timestamp());
         data.fuzzybar.filter();
-        self.selected_idx = 0;
     }
 
-    fn move_selection_up(&mut self, data: &mut AppState) {
-        if self.selected_idx == 0 {
-            return;
+    /// Add the items streamed in since the last poll, keeping the selection
+    fn poll_source(&mut self, ctx: &mut EventCtx, data: &mut AppState) {
+        let (_, rx) = match self.source_stream.take() {
+            Some(source_stream) => source_stream,
+            None => return,
+        };
+
+        let mut received = false;
+        let finished = loop {
+            match rx.try_recv() {
+                Ok(items) => {
+                    data.fuzzybar.source.extend(items);
+                    received = true;
+                }
+                Err(TryRecvError::Empty) => break false,
+                Err(TryRecvError::Disconnected) => break true,
+            }
+        };
+
+        if !finished {
+            self.source_stream = Some((ctx.request_timer(POLL_INTERVAL), rx));
         }
 
-        data.fuzzybar
-            .filtered
-            .get_mut(self.selected_idx)
-            .unwrap()
-            .selected = false;
-        data.fuzzybar
-            .filtered
-            .get_mut(self.selected_idx - 1)
-            .unwrap()
-            .selected = true;
-        self.selected_idx -= 1;
+        if received {
+            let selected_idx = data.fuzzybar.filtered.selected_idx;
+            data.fuzzybar.filter();
+            data.fuzzybar.filtered.select(selected_idx);
+        }
     }
 
-    fn scroll_down(&mut self) {
-        if self.selected_idx < 8 {
-            return;
+    /// Load the history of the current repository, unless it's loaded already
+    fn load_history(&mut self, data: &AppState) {
+        let repo_path = data.repo.path().to_owned();
+        if self.history_repo.as_ref() != Some(&repo_path) {
+            self.history = History::load(&data.repo);
+            self.history_repo = Some(repo_path);
         }
+    }
 
-        let scroll_off = self.matches.widget().offset();
-        let next_off = scroll_off + druid::Vec2::new(0.0, LABEL_SCROLL);
-        let delta = next_off - scroll_off;
+    fn record_history(&mut self, data: &AppState, selections: &[String]) {
+        self.load_history(data);
+        let now = Local::now().timestamp();
+        for name in selections {
+            self.history.record_selection(data.fuzzybar.cmd, name, now);
+        }
+        // What's typed at a prompt is a name or a message, not a query to
+        // run again
+        if data.fuzzybar.cmd.prompt().is_none() {
+            self.history
+                .record_query(data.fuzzybar.cmd, &data.fuzzybar.query);
+        }
+        self.history.save(&data.repo);
+    }
 
-        let scroll_size = self.matches.widget().child_size();
-        let scroll_size = Size::new(scroll_size.width, FUZZYBAR_HEIGHT);
-        let scrolled = self.matches.widget_mut().scroll(delta, scroll_size);
+    /// Replace the query with an older (or newer) one from the history
+    fn recall_query(&mut self, data: &mut AppState, older: bool) {
+        let queries = self.history.queries(data.fuzzybar.cmd);
+        let recall = match (self.query_recall, older) {
+            (None, true) => Some(0),
+            (Some(idx), true) => Some(idx + 1),
+            (Some(idx), false) if idx > 0 => Some(idx - 1),
+            _ => None,
+        };
 
-        if !self.scrolled {
-            self.scrolled = scrolled;
+        match recall {
+            Some(idx) if idx >= queries.len() => return,
+            Some(idx) => data.fuzzybar.query = queries[idx].clone(),
+            None if self.query_recall.is_some() => data.fuzzybar.query = "".to_owned(),
+            None => return,
         }
+
+        self.query_recall = recall;
+        data.fuzzybar.filter();
     }
 
-    fn move_selection_down(&mut self, data: &mut AppState) {
-        if self.selected_idx + 1 == data.fuzzybar.filtered.len() {
+    /// Show the preview of the selected item, building it if it isn't cached yet
+    fn update_preview(&mut self, ctx: &mut EventCtx, data: &mut AppState) {
+        let kind = match preview::preview_kind(data.fuzzybar.cmd) {
+            Some(kind) => kind,
+            None => {
+                data.fuzzybar.preview = vector![];
+                return;
+            }
+        };
+
+        let name = match data.fuzzybar.filtered.selected() {
+            Some(item) => item.name.clone(),
+            None => {
+                data.fuzzybar.preview = vector![];
+                return;
+            }
+        };
+
+        if let Some(lines) = self.preview_cache.get(&name) {
+            data.fuzzybar.preview = lines.clone();
             return;
         }
-        data.fuzzybar
-            .filtered
-            .get_mut(self.selected_idx)
-            .unwrap()
-            .selected = false;
-        data.fuzzybar
-            .filtered
-            .get_mut(self.selected_idx + 1)
-            .unwrap()
-            .selected = true;
-        self.selected_idx += 1;
+
+        // Only the latest selection is worth previewing, a build still running
+        // for an earlier one is abandoned
+        match &self.preview_run {
+            Some((_, pending, _)) if *pending == name => (),
+            _ => self.spawn_preview(ctx, data, kind, name),
+        }
     }
 
-    fn scroll_up(&mut self) {
-        if self.selected_idx < 7 {
-            return;
+    fn spawn_preview(
+        &mut self,
+        ctx: &mut EventCtx,
+        data: &mut AppState,
+        kind: PreviewKind,
+        name: String,
+    ) {
+        data.fuzzybar.preview = vector!["Loading...".to_owned()];
+        let rx = preview::spawn_preview(data.repo.path().to_owned(), kind, name.clone());
+        self.preview_run = Some((ctx.request_timer(POLL_INTERVAL), name, rx));
+    }
+
+    fn poll_preview(&mut self, ctx: &mut EventCtx, data: &mut AppState) {
+        let (_, name, rx) = match self.preview_run.take() {
+            Some(preview_run) => preview_run,
+            None => return,
+        };
+
+        let lines = match rx.try_recv() {
+            Ok(Ok(lines)) => lines,
+            Ok(Err(err)) => {
+                error!("Failed to build preview of {}: {:#}", name, err);
+                vector![format!("{:#}", err)]
+            }
+            Err(TryRecvError::Empty) => {
+                self.preview_run = Some((ctx.request_timer(POLL_INTERVAL), name, rx));
+                return;
+            }
+            Err(TryRecvError::Disconnected) => vector!["Preview failed".to_owned()],
+        };
+
+        self.preview_cache.insert(name, lines);
+        self.update_preview(ctx, data);
+    }
+
+    fn move_selection_up(&mut self, data: &mut AppState) {
+        let idx = data.fuzzybar.filtered.selected_idx;
+        data.fuzzybar.filtered.select(idx.saturating_sub(1));
+    }
+
+    fn move_selection_down(&mut self, data: &mut AppState) {
+        let idx = data.fuzzybar.filtered.selected_idx;
+        data.fuzzybar.filtered.select(idx + 1);
+    }
+
+    /// Index of the item in the row at `pos`, if there is one
+    fn item_at(&self, data: &AppState, pos: Point) -> Option<usize> {
+        let rect = self.matches.layout_rect();
+        if !rect.contains(pos) {
+            return None;
         }
 
-        let scroll_off = self.matches.widget().offset();
-        let next_off = scroll_off - druid::Vec2::new(0.0, LABEL_SCROLL);
-        let delta = next_off - scroll_off;
+        let row = ((pos.y - rect.y0) / LABEL_HEIGHT) as usize;
+        let idx = data.fuzzybar.filtered.offset() + row;
+        if idx < data.fuzzybar.filtered.items.len() {
+            Some(idx)
+        } else {
+            None
+        }
+    }
+
+    /// Move the selection a row for every [`LABEL_HEIGHT`] the wheel scrolled
+    fn scroll_selection(&mut self, data: &mut AppState, delta: f64) {
+        self.wheel_delta += delta;
+        while self.wheel_delta >= LABEL_HEIGHT {
+            self.move_selection_down(data);
+            self.wheel_delta -= LABEL_HEIGHT;
+        }
+        while self.wheel_delta <= -LABEL_HEIGHT {
+            self.move_selection_up(data);
+            self.wheel_delta += LABEL_HEIGHT;
+        }
+    }
 
-        let scroll_size = self.matches.widget().child_size();
-        let scroll_size = Size::new(scroll_size.width, FUZZYBAR_HEIGHT);
-        self.scrolled = self.matches.widget_mut().scroll(delta, scroll_size);
+    /// Check the query of a prompting command, showing why it's rejected
+    fn validate_prompt(&self, data: &mut AppState) -> bool {
+        let result = git::validate_arg(
+            &data.repo,
+            data.fuzzybar.cmd,
+            &data.fuzzybar.args,
+            &data.fuzzybar.query,
+        );
+        data.fuzzybar.error = result.err().map(|err| format!("{:#}", err));
+        data.fuzzybar.error.is_none()
     }
 
-    fn execute_cmd(&mut self, data: &mut AppState) {
+    /// Run the command on the typed argument or the selected items. Returns
+    /// false if there was nothing (valid) to run it on, the fuzzybar stays
+    /// open then.
+    fn execute_cmd(&mut self, data: &mut AppState) -> bool {
+        if data.fuzzybar.cmd.prompt().is_some() {
+            if !self.validate_prompt(data) {
+                return false;
+            }
+
+            data.fuzzybar.is_hidden = true;
+            self.record_history(data, &[]);
+            let arg = data.fuzzybar.query.clone();
+            match data.fuzzybar.shell_cmd.clone() {
+                Some(shell) => data.run_shell(&shell, &[arg]),
+                None => {
+                    git::execute_cmd(&data.repo, data.fuzzybar.cmd, &data.fuzzybar.args, &[arg]);
+                    data.refresh();
+                }
+            }
+            self.reset_selection(data);
+            return true;
+        }
+
+        let selected = match data.fuzzybar.filtered.selected() {
+            Some(selected) => selected.clone(),
+            None => return false,
+        };
+
         data.fuzzybar.is_hidden = true;
         {
-            let selected = data.fuzzybar.filtered.get_mut(self.selected_idx).unwrap();
-            git::execute_cmd(&data.repo, data.fuzzybar.cmd, &selected.name);
-            data.repo_header = crate::widgets::header::RepoHeader::new(&data.repo).unwrap();
+            let selections = data.fuzzybar.selections();
+            self.record_history(data, &selections);
+            if let Some(shell) = data.fuzzybar.shell_cmd.clone() {
+                data.run_shell(&shell, &selections);
+                self.reset_selection(data);
+                return true;
+            }
+            match data.fuzzybar.cmd {
+                Command::FileLog => {
+                    data.file_log = FileLog::new(&data.repo, &selected.name);
+                    data.view = View::FileLog;
+                }
+                Command::Reflog => {
+                    data.reflog = Reflog::new(&data.repo, &selected.name);
+                    data.view = View::Reflog;
+                }
+                Command::SubmoduleOpen => match git::open_submodule(&data.repo, &selected.name) {
+                    Ok(repo) => data.switch_repo(repo),
+                    Err(err) => error!("{:#}", err),
+                },
+                Command::Palette => {
+                    data.cheatsheet.picked = palette::find(&data.cheatsheet.keymap, &selected.name);
+                }
+                Command::ThemeSwitch => {
+                    data.theme.select(&selected.name);
+                }
+                Command::WorktreeAdd => {
+                    if let Err(err) = git::add_worktree(&data.repo, &selected.name) {
+                        error!("{:#}", err);
+                    }
+                    data.refresh();
+                    data.worktrees = Worktrees::new(&data.repo);
+                    data.view = View::Worktrees;
+                }
+                cmd => {
+                    git::execute_cmd(&data.repo, cmd, &data.fuzzybar.args, &selections);
+                    data.refresh();
+                }
+            }
         }
         self.reset_selection(data);
+        true
     }
 
     fn reset_selection(&mut self, data: &mut AppState) {
-        let selected = data.fuzzybar.filtered.get_mut(self.selected_idx).unwrap();
-        selected.selected = false;
+        data.fuzzybar.filtered.select(0);
+        data.fuzzybar.error = None;
+        self.wheel_delta = 0.0;
+        data.fuzzybar.unmark_all();
+        self.query_recall = None;
+
+        // The command may have changed the repository, reload everything the
+        // next time the fuzzybar is opened
+        self.source_key = None;
+        self.source_stream = None;
+        self.preview_cache.clear();
+        self.preview_run = None;
+        data.fuzzybar.preview = vector![];
+    }
+}
 
-        let first = data.fuzzybar.filtered.get_mut(0).unwrap();
-        first.selected = true;
+fn source_key(data: &AppState) -> (Command, Option<String>, Option<SourceFilter>) {
+    (
+        data.fuzzybar.cmd,
+        data.fuzzybar.shell_source.clone(),
+        data.fuzzybar.args.filter,
+    )
+}
 
-        self.selected_idx = 0;
-        self.scrolled = false;
+/// Hand the focus back to the cheatsheet, or to the view if one is open
+fn return_focus(ctx: &mut EventCtx, data: &AppState) {
+    ctx.resign_focus();
+    // The cheatsheet runs what was picked in the command palette
+    if data.view == View::Status || data.cheatsheet.picked.is_some() {
+        ctx.submit_command(crate::consts::CS_TAKE_FOCUS, None);
+    } else {
+        ctx.submit_command(crate::consts::VIEW_TAKE_FOCUS, None);
     }
 }
 
@@ -184,9 +510,12 @@ impl Widget<AppState> for Fuzzybar {
         }
 
         self.querybar.lifecycle(ctx, event, data, env);
+        self.counter.lifecycle(ctx, event, &data.fuzzybar, env);
+        self.error.lifecycle(ctx, event, &data.fuzzybar, env);
         self.matches
-            .widget_mut()
-            .lifecycle(ctx, event, &data.fuzzybar.filtered, env);
+            .lifecycle(ctx, event, &data.fuzzybar.filtered.visible, env);
+        self.preview
+            .lifecycle(ctx, event, &data.fuzzybar.preview, env);
     }
 
     fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut AppState, env: &Env) {
@@ -196,6 +525,53 @@ impl Widget<AppState> for Fuzzybar {
 
         ctx.request_focus();
 
+        if self.source_key.as_ref() != Some(&source_key(data)) {
+            self.update_source(ctx, data);
+            self.update_preview(ctx, data);
+        }
+
+        if let Event::Command(cmd) = event {
+            if cmd.is(crate::consts::FB_TAKE_FOCUS) {
+                ctx.set_handled();
+            }
+        }
+
+        if let Event::Timer(token) = event {
+            if self.preview_run.as_ref().map(|run| run.0) == Some(*token) {
+                self.poll_preview(ctx, data);
+                ctx.set_handled();
+            }
+            if self.source_stream.as_ref().map(|stream| stream.0) == Some(*token) {
+                self.poll_source(ctx, data);
+                self.update_preview(ctx, data);
+                ctx.set_handled();
+            }
+        }
+
+        match event {
+            Event::MouseDown(mouse) => {
+                if let Some(idx) = self.item_at(data, mouse.pos) {
+                    data.fuzzybar.filtered.select(idx);
+                    if mouse.count >= 2 {
+                        if self.execute_cmd(data) {
+                            return_focus(ctx, data);
+                        }
+                    } else {
+                        self.update_preview(ctx, data);
+                    }
+                }
+                ctx.set_handled();
+                return;
+            }
+            Event::Wheel(mouse) => {
+                self.scroll_selection(data, mouse.wheel_delta.y);
+                self.update_preview(ctx, data);
+                ctx.set_handled();
+                return;
+            }
+            _ => (),
+        }
+
         if let Event::KeyDown(key_event) = event {
             let code = &key_event.code;
             let mods = &key_event.mods;
@@ -207,44 +583,82 @@ impl Widget<AppState> for Fuzzybar {
                         self.reset_selection(data);
 
                         if ctx.is_focused() {
-                            ctx.resign_focus();
-                            ctx.submit_command(crate::consts::CS_TAKE_FOCUS, None);
+                            return_focus(ctx, data);
                             ctx.set_handled();
                         }
                     }
                 }
                 Code::Enter => {
-                    self.execute_cmd(data);
-                    ctx.resign_focus();
-                    ctx.submit_command(crate::consts::CS_TAKE_FOCUS, None);
+                    if self.execute_cmd(data) {
+                        return_focus(ctx, data);
+                    }
                     ctx.set_handled();
                 }
                 Code::ControlLeft | Code::ControlRight => {
                     ctx.set_handled();
                 }
+                Code::Tab if data.fuzzybar.cmd.accepts_many() => {
+                    data.fuzzybar.toggle_mark();
+                    self.move_selection_down(data);
+                    ctx.set_handled();
+                }
+                Code::PageUp | Code::PageDown | Code::Home | Code::End => {
+                    data.fuzzybar.filtered.navigate(&key_event.key);
+                    ctx.set_handled();
+                }
+                Code::ArrowUp => {
+                    self.recall_query(data, true);
+                    ctx.set_handled();
+                }
+                Code::ArrowDown => {
+                    self.recall_query(data, false);
+                    ctx.set_handled();
+                }
+                Code::KeyA if mods.alt() && data.fuzzybar.cmd.accepts_many() => {
+                    data.fuzzybar.mark_all();
+                    ctx.set_handled();
+                }
+                Code::KeyD if mods.alt() && data.fuzzybar.cmd.accepts_many() => {
+                    data.fuzzybar.unmark_all();
+                    ctx.set_handled();
+                }
                 Code::KeyJ if mods.ctrl() => {
                     self.move_selection_down(data);
-                    self.scroll_down();
                     ctx.set_handled();
                 }
                 Code::KeyK if mods.ctrl() => {
                     self.move_selection_up(data);
-                    self.scroll_up();
                     ctx.set_handled();
                 }
                 _ if !mods.ctrl() => {
                     self.querybar.widget_mut().event(ctx, event, data, env);
+                    self.query_recall = None;
+
+                    if data.fuzzybar.cmd.prompt().is_some() {
+                        // An empty query is only an error once it's submitted
+                        if data.fuzzybar.query.is_empty() {
+                            data.fuzzybar.error = None;
+                        } else {
+                            self.validate_prompt(data);
+                        }
+                        ctx.set_handled();
+                        return;
+                    }
 
                     let now = Instant::now();
                     let duration_since = now.duration_since(self.ts_since_last_event);
                     if duration_since >= DEBOUNCE_DELTA {
                         self.ts_since_last_event = now;
-                        self.update_source(data);
+                        data.fuzzybar.filter();
                     }
                     ctx.set_handled();
                 }
                 _ => ctx.set_handled(),
             }
+
+            if !data.fuzzybar.is_hidden {
+                self.update_preview(ctx, data);
+            }
         }
     }
 
@@ -254,9 +668,13 @@ impl Widget<AppState> for Fuzzybar {
         }
 
         self.querybar.update(ctx, data, env);
+        self.counter.update(ctx, &data.fuzzybar, env);
+        self.error.update(ctx, &data.fuzzybar, env);
 
         if !old.fuzzybar.same(&data.fuzzybar) {
-            self.matches.update(ctx, &data.fuzzybar.filtered, env);
+            self.matches
+                .update(ctx, &data.fuzzybar.filtered.visible, env);
+            self.preview.update(ctx, &data.fuzzybar.preview, env);
         }
     }
 
@@ -277,21 +695,65 @@ impl Widget<AppState> for Fuzzybar {
 
         let child_bc = bc.loosen();
 
-        let qb_size = self.querybar.layout(ctx, &child_bc, data, env);
+        let counter_size = self.counter.layout(ctx, &child_bc, &data.fuzzybar, env);
+        let qb_bc = BoxConstraints::new(
+            Size::ZERO,
+            Size::new(size.width - counter_size.width, child_bc.max().height),
+        );
+        let qb_size = self.querybar.layout(ctx, &qb_bc, data, env);
 
         self.querybar
             .set_layout_rect(ctx, data, env, Rect::from_origin_size((0.0, 0.0), qb_size));
+        let counter_y = (qb_size.height - counter_size.height).max(0.0) / 2.0;
+        self.counter.set_layout_rect(
+            ctx,
+            &data.fuzzybar,
+            env,
+            Rect::from_origin_size((qb_size.width, counter_y), counter_size),
+        );
+
+        let error_size = self.error.layout(ctx, &child_bc, &data.fuzzybar, env);
+        self.error.set_layout_rect(
+            ctx,
+            &data.fuzzybar,
+            env,
+            Rect::from_origin_size((0.0, qb_size.height), error_size),
+        );
+
+        let has_preview = preview::preview_kind(data.fuzzybar.cmd).is_some();
+        let matches_width = if has_preview {
+            size.width * MATCHES_WIDTH_RATIO
+        } else {
+            size.width
+        };
 
-        if !self.scrolled {
-            let match_size = self
-                .matches
-                .layout(ctx, &child_bc, &data.fuzzybar.filtered, env);
-
-            self.matches.set_layout_rect(
+        let matches_bc = BoxConstraints::new(
+            Size::new(matches_width, 0.0),
+            Size::new(matches_width, FUZZYBAR_HEIGHT),
+        );
+        let match_size =
+            self.matches
+                .layout(ctx, &matches_bc, &data.fuzzybar.filtered.visible, env);
+        self.matches.set_layout_rect(
+            ctx,
+            &data.fuzzybar.filtered.visible,
+            env,
+            Rect::from_origin_size((0.0, qb_size.height), match_size),
+        );
+
+        if has_preview {
+            let preview_size = Size::new(size.width - matches_width, FUZZYBAR_HEIGHT);
+            self.preview.layout(
+                ctx,
+                &BoxConstraints::tight(preview_size),
+                &data.fuzzybar.preview,
+                env,
+            );
+            self.preview.set_layout_rect(
                 ctx,
-                &data.fuzzybar.filtered,
+                &data.fuzzybar.preview,
                 env,
-                Rect::from_origin_size((0.0, qb_size.height), match_size),
+                Rect::from_origin_size((matches_width, qb_size.height), preview_size),
             );
         }
 
@@ -310,13 +772,23 @@ impl Widget<AppState> for Fuzzybar {
         ctx.fill(rect, &bg_color);
 
         self.querybar.paint(ctx, data, env);
-        self.matches.paint(ctx, &data.fuzzybar.filtered, env);
+        self.counter.paint(ctx, &data.fuzzybar, env);
+        self.matches
+            .paint(ctx, &data.fuzzybar.filtered.visible, env);
+        if data.fuzzybar.error.is_some() {
+            self.error.paint(ctx, &data.fuzzybar, env);
+        }
+
+        if preview::preview_kind(data.fuzzybar.cmd).is_some() {
+            self.preview.paint(ctx, &data.fuzzybar.preview, env);
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::provider::ItemKind;
     use crate::tests;
     #[cfg(test)]
     use pretty_assertions::{assert_eq, assert_ne};
@@ -334,17 +806,31 @@ mod tests {
         tf();
     }
 
+    /// Fill the fuzzybar with the branches, as the provider would
+    fn load_branches(data: &mut AppState) {
+        data.fuzzybar.source = data
+            .git
+            .all_branches
+            .iter()
+            .map(|name| SourceItem::new(name, ItemKind::Branch))
+            .collect();
+        data.fuzzybar.filter();
+    }
+
     #[test]
     fn reset_selection_should_deselect_items_and_scroll() {
         test(|| {
             let (_td, repo) = tests::repo_init();
             let _ = tests::commit(&repo);
+            let _ = tests::branch(&repo, "b1");
             let mut data = tests::state_init(repo);
+            load_branches(&mut data);
 
             let mut fuzzybar = Fuzzybar::new();
+            fuzzybar.move_selection_down(&mut data);
             fuzzybar.reset_selection(&mut data);
-            assert_eq!(fuzzybar.selected_idx, 0);
-            assert_eq!(fuzzybar.scrolled, false);
+            assert_eq!(data.fuzzybar.filtered.selected_idx, 0);
+            assert_eq!(data.fuzzybar.filtered.offset(), 0);
         })
     }
 
@@ -356,14 +842,17 @@ mod tests {
             let _ = tests::branch(&repo, "b1");
             let mut data = tests::state_init(repo);
             data.fuzzybar.cmd = Command::BranchCheckout;
+            load_branches(&mut data);
 
             let mut fuzzybar = Fuzzybar::new();
-            fuzzybar.selected_idx = data
+            let idx = data
                 .fuzzybar
                 .filtered
+                .items
                 .iter()
                 .position(|b| b.name == "b1")
                 .unwrap();
+            data.fuzzybar.filtered.select(idx);
             fuzzybar.execute_cmd(&mut data);
 
             let head = data.repo.head().unwrap();
@@ -372,6 +861,65 @@ mod tests {
         })
     }
 
+    #[test]
+    fn shell_cmd_should_run_instead_of_the_command() {
+        let (_td, repo) = tests::repo_init();
+        let _ = tests::branch(&repo, "b1");
+        let mut data = tests::state_init(repo);
+        data.fuzzybar.cmd = Command::BranchCheckout;
+        data.fuzzybar.shell_cmd = Some("git log {selection}".to_owned());
+        load_branches(&mut data);
+
+        let mut fuzzybar = Fuzzybar::new();
+        let idx = data
+            .fuzzybar
+            .filtered
+            .items
+            .iter()
+            .position(|b| b.name == "b1")
+            .unwrap();
+        data.fuzzybar.filtered.select(idx);
+        assert!(fuzzybar.execute_cmd(&mut data));
+
+        assert_eq!(data.output.cmd, "git log 'b1'");
+        assert!(data.output.pending);
+        let head = data.repo.head().unwrap();
+        assert_eq!(head.name().unwrap(), "refs/heads/master");
+    }
+
+    #[test]
+    fn execute_cmd_without_items_should_keep_fuzzybar_open() {
+        let (_td, repo) = tests::repo_init();
+        let mut data = tests::state_init(repo);
+        data.fuzzybar.cmd = Command::BranchCheckout;
+        data.fuzzybar.is_hidden = false;
+        data.fuzzybar.filter();
+
+        let mut fuzzybar = Fuzzybar::new();
+        assert!(!fuzzybar.execute_cmd(&mut data));
+        assert!(!data.fuzzybar.is_hidden);
+    }
+
+    #[test]
+    fn prompt_should_only_submit_valid_arguments() {
+        let (_td, repo) = tests::repo_init();
+        let _ = tests::branch(&repo, "b1");
+        let mut data = tests::state_init(repo);
+        data.fuzzybar.cmd = Command::BranchCreate;
+        data.fuzzybar.is_hidden = false;
+
+        let mut fuzzybar = Fuzzybar::new();
+        data.fuzzybar.query = "b1".to_owned();
+        assert!(!fuzzybar.execute_cmd(&mut data));
+        assert!(data.fuzzybar.error.is_some());
+
+        data.fuzzybar.query = "b2".to_owned();
+        assert!(fuzzybar.execute_cmd(&mut data));
+        assert!(data.fuzzybar.is_hidden);
+        assert_eq!(data.fuzzybar.error, None);
+        assert!(data.repo.find_branch("b2", git2::BranchType::Local).is_ok());
+    }
+
     #[test]
     fn move_selection_down_should_move_selected() {
         let (_td, repo) = tests::repo_init();
@@ -379,17 +927,14 @@ mod tests {
         let _ = tests::branch(&repo, "b1");
         let mut data = tests::state_init(repo);
         data.fuzzybar.cmd = Command::BranchCheckout;
+        load_branches(&mut data);
 
         let mut fuzzybar = Fuzzybar::new();
         fuzzybar.move_selection_down(&mut data);
 
-        let selected = data
-            .fuzzybar
-            .filtered
-            .get_mut(fuzzybar.selected_idx)
-            .unwrap();
+        let selected = data.fuzzybar.filtered.selected().unwrap();
         assert_eq!(selected.selected, true);
-        assert_eq!(fuzzybar.selected_idx, 1);
+        assert_eq!(data.fuzzybar.filtered.selected_idx, 1);
     }
 
     #[test]
@@ -399,17 +944,42 @@ mod tests {
         let _ = tests::branch(&repo, "b1");
         let mut data = tests::state_init(repo);
         data.fuzzybar.cmd = Command::BranchCheckout;
+        load_branches(&mut data);
 
         let mut fuzzybar = Fuzzybar::new();
         fuzzybar.move_selection_down(&mut data);
         fuzzybar.move_selection_up(&mut data);
 
-        let selected = data
+        let selected = data.fuzzybar.filtered.selected().unwrap();
+        assert_eq!(selected.selected, true);
+        assert_eq!(data.fuzzybar.filtered.selected_idx, 0);
+    }
+
+    #[test]
+    fn only_visible_rows_should_be_laid_out() {
+        let (_td, repo) = tests::repo_init();
+        let mut data = tests::state_init(repo);
+        data.fuzzybar.source = (0..10_000)
+            .map(|i| SourceItem::new(&format!("file{}", i), ItemKind::File))
+            .collect();
+        data.fuzzybar.filter();
+        assert_eq!(data.fuzzybar.filtered.visible.len(), FUZZYBAR_ROWS);
+
+        let mut fuzzybar = Fuzzybar::new();
+        fuzzybar.scroll_selection(&mut data, LABEL_HEIGHT * 20.0);
+        assert_eq!(data.fuzzybar.filtered.selected_idx, 20);
+        assert!(data
             .fuzzybar
             .filtered
-            .get_mut(fuzzybar.selected_idx)
-            .unwrap();
-        assert_eq!(selected.selected, true);
-        assert_eq!(fuzzybar.selected_idx, 0);
+            .visible
+            .iter()
+            .any(|item| item.selected));
+
+        data.fuzzybar.filtered.navigate(&KbKey::End);
+        assert_eq!(data.fuzzybar.filtered.selected_idx, 9_999);
+        assert_eq!(
+            data.fuzzybar.filtered.visible.last().unwrap().name,
+            "file9999"
+        );
     }
 }
@@ -12,11 +12,13 @@ pub struct RepoHeader {
     local_head: (String, String),
     remote_head: (String, String),
     tag: String,
+    worktree: String,
 }
 
 impl RepoHeader {
     pub fn new(repo: &Repository) -> Result<RepoHeader> {
         let tag = git::get_latest_tag(repo);
+        let worktree = Self::worktree(repo);
 
         let head = repo.head();
         if let Err(err) = head {
@@ -28,6 +30,7 @@ impl RepoHeader {
                 ),
                 remote_head: ("<no-upstream>".to_owned(), "-".to_owned()),
                 tag: "".to_owned(),
+                worktree,
             });
         };
 
@@ -49,6 +52,7 @@ impl RepoHeader {
                 local_head: (head_short.to_owned(), head_commit_msg.1.to_owned()),
                 remote_head: (upstream_short.to_owned(), upstream_commit_msg.1.to_owned()),
                 tag: tag,
+                worktree,
             });
         }
 
@@ -58,9 +62,21 @@ impl RepoHeader {
             local_head: (head_short.to_owned(), head_commit_msg.1.to_owned()),
             remote_head: ("<no-upstream>".to_owned(), "-".to_owned()),
             tag: tag,
+            worktree,
         })
     }
 
+    fn worktree(repo: &Repository) -> String {
+        let path = repo
+            .workdir()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+        match git::get_worktree_name(repo) {
+            Some(name) => format!("{} ({})", name, path),
+            None => format!("<main> ({})", path),
+        }
+    }
+
     pub fn widget() -> impl Widget<AppState> {
         let lbl_head = Label::new("Head:")
             .with_text_color(theme::BASE_00)
@@ -97,10 +113,21 @@ impl RepoHeader {
             .with_child(lbl_tag)
             .with_child(lbl_tag_tag.with_text_color(theme::YELLOW));
 
+        let lbl_worktree = Label::new("Worktree:")
+            .with_text_color(theme::BASE_00)
+            .fix_width(80.0);
+        let lbl_worktree_name =
+            Label::dynamic(|app: &AppState, _| app.repo_header.worktree.clone());
+
+        let row_worktree = Flex::row()
+            .with_child(lbl_worktree)
+            .with_child(lbl_worktree_name.with_text_color(theme::VIOLET));
+
         Flex::column()
             .with_child(row_head)
             .with_child(row_ups)
             .with_child(row_tag)
+            .with_child(row_worktree)
             .cross_axis_alignment(CrossAxisAlignment::Start)
     }
 }
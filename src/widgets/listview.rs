@@ -0,0 +1,145 @@
+use crate::theme;
+use druid::lens::Field;
+use druid::widget::{Label, List, Painter};
+use druid::{Data, Env, KbKey, RenderContext, Widget, WidgetExt};
+use im::Vector;
+
+const ROW_HEIGHT: f64 = 24.0;
+const ROW_PADDING: f64 = 3.0;
+pub const VISIBLE_ROWS: usize = 16;
+
+/// An item that can be shown in a [`ListView`]
+pub trait ListRow: Data {
+    fn text(&self) -> String;
+    fn is_selected(&self) -> bool;
+    fn set_selected(&mut self, selected: bool);
+}
+
+/// ListView is a keyboard driven list with a single selected row. Only a window
+/// of [`VISIBLE_ROWS`] rows (or as many as given to [`ListView::with_rows`])
+/// around the selection is laid out, which keeps the selection visible without
+/// having to drive a [`druid::widget::Scroll`].
+#[derive(Clone, Data, Debug)]
+pub struct ListView<T: Clone> {
+    pub items: Vector<T>,
+    pub visible: Vector<T>,
+    pub selected_idx: usize,
+    offset: usize,
+    rows: usize,
+}
+
+impl<T: ListRow> ListView<T> {
+    pub fn new(items: Vector<T>) -> Self {
+        ListView::with_rows(items, VISIBLE_ROWS)
+    }
+
+    pub fn with_rows(items: Vector<T>, rows: usize) -> Self {
+        let mut view = ListView {
+            items,
+            visible: Vector::new(),
+            selected_idx: 0,
+            offset: 0,
+            rows,
+        };
+        view.select(0);
+        view
+    }
+
+    /// Replace the items, selecting the first one
+    pub fn set_items(&mut self, items: Vector<T>) {
+        self.items = items;
+        self.selected_idx = 0;
+        self.offset = 0;
+        self.select(0);
+    }
+
+    pub fn selected(&self) -> Option<&T> {
+        self.items.get(self.selected_idx)
+    }
+
+    /// Index of the item shown in the first visible row
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn select(&mut self, idx: usize) {
+        if self.items.is_empty() {
+            self.visible.clear();
+            return;
+        }
+
+        let idx = idx.min(self.items.len() - 1);
+        if let Some(item) = self.items.get_mut(self.selected_idx) {
+            item.set_selected(false);
+        }
+        if let Some(item) = self.items.get_mut(idx) {
+            item.set_selected(true);
+        }
+        self.selected_idx = idx;
+
+        if idx < self.offset {
+            self.offset = idx;
+        } else if idx >= self.offset + self.rows {
+            self.offset = idx + 1 - self.rows;
+        }
+
+        self.update_visible();
+    }
+
+    /// Show changes made to `items` directly
+    pub fn update_visible(&mut self) {
+        self.visible = self.items.skip(self.offset).take(self.rows);
+    }
+
+    /// Move the selection if `key` is one of the navigation keys. Returns
+    /// false when the key wasn't handled.
+    pub fn navigate(&mut self, key: &KbKey) -> bool {
+        let idx = self.selected_idx;
+        match key {
+            KbKey::ArrowDown => self.select(idx + 1),
+            KbKey::ArrowUp => self.select(idx.saturating_sub(1)),
+            KbKey::PageDown => self.select(idx + self.rows),
+            KbKey::PageUp => self.select(idx.saturating_sub(self.rows)),
+            KbKey::Home => self.select(0),
+            KbKey::End => self.select(self.items.len().saturating_sub(1)),
+            KbKey::Character(c) if c == "j" => self.select(idx + 1),
+            KbKey::Character(c) if c == "k" => self.select(idx.saturating_sub(1)),
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn widget() -> impl Widget<ListView<T>> {
+        List::new(|| {
+            let painter = Painter::new(|ctx, item: &T, env| {
+                let color = if item.is_selected() {
+                    env.get(theme::BASE_2)
+                } else {
+                    env.get(theme::BASE_3)
+                };
+
+                let bounds = ctx.size().to_rect();
+
+                ctx.fill(bounds, &color);
+            });
+            Label::new(|item: &T, _env: &Env| item.text())
+                .padding(ROW_PADDING)
+                .fix_height(ROW_HEIGHT)
+                .background(painter)
+        })
+        .lens(Field::new(
+            |view: &ListView<T>| &view.visible,
+            |view: &mut ListView<T>| &mut view.visible,
+        ))
+    }
+}
+
+impl<T: ListRow> Default for ListView<T> {
+    fn default() -> Self {
+        ListView::new(Vector::new())
+    }
+}
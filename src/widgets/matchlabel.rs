@@ -0,0 +1,215 @@
+use crate::state::ListItem;
+use crate::theme;
+use druid::widget::Label;
+use druid::{
+    BoxConstraints, Data, Env, Event, EventCtx, LayoutCtx, LifeCycle, LifeCycleCtx, PaintCtx, Rect,
+    Size, UpdateCtx, Widget, WidgetPod,
+};
+
+/// Longest item name shown before it gets truncated
+pub const MAX_LABEL_CHARS: usize = 120;
+const ELLIPSIS: &str = "…";
+
+/// Label for a fuzzybar item that paints the characters matched by the query
+/// in [`theme::HIGHLIGHT`]. Names longer than [`MAX_LABEL_CHARS`] are cut in the
+/// middle, keeping the matched characters visible where possible. The item's
+/// description follows the name in a dimmer color.
+pub struct MatchLabel {
+    segments: Vec<WidgetPod<(), Label<()>>>,
+}
+
+impl MatchLabel {
+    pub fn new() -> Self {
+        MatchLabel { segments: vec![] }
+    }
+
+    fn build(&mut self, item: &ListItem) {
+        let positions = item.positions.iter().cloned().collect::<Vec<_>>();
+        self.segments = segments(&item.name, &positions, MAX_LABEL_CHARS)
+            .into_iter()
+            .map(|(text, matched)| {
+                let label = if matched {
+                    Label::new(text).with_text_color(theme::HIGHLIGHT)
+                } else {
+                    Label::new(text)
+                };
+                WidgetPod::new(label)
+            })
+            .collect();
+
+        if !item.description.is_empty() {
+            let description = format!("  {}", item.description);
+            let label = Label::new(description).with_text_color(theme::BASE_1);
+            self.segments.push(WidgetPod::new(label));
+        }
+    }
+}
+
+impl Widget<ListItem> for MatchLabel {
+    fn event(&mut self, _ctx: &mut EventCtx, _event: &Event, _data: &mut ListItem, _env: &Env) {}
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &ListItem, env: &Env) {
+        if let LifeCycle::WidgetAdded = event {
+            self.build(data);
+        }
+
+        for segment in self.segments.iter_mut() {
+            segment.lifecycle(ctx, event, &(), env);
+        }
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old: &ListItem, data: &ListItem, env: &Env) {
+        if old.name != data.name
+            || old.description != data.description
+            || !old.positions.same(&data.positions)
+        {
+            self.build(data);
+            ctx.children_changed();
+        }
+
+        for segment in self.segments.iter_mut() {
+            segment.update(ctx, &(), env);
+        }
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        _data: &ListItem,
+        env: &Env,
+    ) -> Size {
+        let child_bc = bc.loosen();
+        let mut pos_x = 0.0;
+        let mut height: f64 = 0.0;
+
+        for segment in self.segments.iter_mut() {
+            let size = segment.layout(ctx, &child_bc, &(), env);
+            segment.set_layout_rect(ctx, &(), env, Rect::from_origin_size((pos_x, 0.0), size));
+            pos_x += size.width;
+            height = height.max(size.height);
+        }
+
+        bc.constrain((pos_x, height))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, _data: &ListItem, env: &Env) {
+        for segment in self.segments.iter_mut() {
+            segment.paint(ctx, &(), env);
+        }
+    }
+}
+
+/// Split `name` into runs of matched and unmatched characters, truncating it to
+/// `max_chars`. The name is cut in the middle, at a point that keeps all
+/// matched characters in the head or the tail. When the matches are too far
+/// apart for that, a window around the matches is shown instead.
+pub fn segments(name: &str, positions: &[usize], max_chars: usize) -> Vec<(String, bool)> {
+    let chars = name.chars().collect::<Vec<_>>();
+    let len = chars.len();
+
+    let ranges = if len <= max_chars {
+        vec![(0, len)]
+    } else {
+        let budget = max_chars.saturating_sub(1);
+        match middle_cut(len, positions, budget) {
+            Some((head, tail)) => vec![(0, head), (len - tail, len)],
+            None => {
+                let budget = max_chars.saturating_sub(2);
+                let first = positions.first().cloned().unwrap_or(0);
+                let last = positions.last().cloned().unwrap_or(0);
+                let center = (first + last) / 2;
+                let start = center.saturating_sub(budget / 2).min(len - budget);
+                vec![(start, start + budget)]
+            }
+        }
+    };
+
+    let mut segments: Vec<(String, bool)> = vec![];
+    let mut push = |text: &str, matched: bool| match segments.last_mut() {
+        Some((last, last_matched)) if *last_matched == matched => last.push_str(text),
+        _ => segments.push((text.to_owned(), matched)),
+    };
+
+    for (idx, (start, end)) in ranges.iter().enumerate() {
+        if idx > 0 || *start > 0 {
+            push(ELLIPSIS, false);
+        }
+        for pos in *start..*end {
+            push(&chars[pos].to_string(), positions.contains(&pos));
+        }
+    }
+    if let Some((_, end)) = ranges.last() {
+        if *end < len && ranges.len() == 1 {
+            push(ELLIPSIS, false);
+        }
+    }
+
+    segments
+}
+
+/// Find the lengths of a head and tail adding up to `budget` chars that
+/// together contain every position
+fn middle_cut(len: usize, positions: &[usize], budget: usize) -> Option<(usize, usize)> {
+    (0..=positions.len())
+        .filter_map(|split| {
+            let head = if split == 0 {
+                0
+            } else {
+                positions[split - 1] + 1
+            };
+            let tail = positions.get(split).map(|pos| len - pos).unwrap_or(0);
+            if head + tail > budget {
+                return None;
+            }
+            let extra = budget - head - tail;
+            let head = head + extra / 2;
+            Some((head, budget - head))
+        })
+        .next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(segments: &[(String, bool)]) -> String {
+        segments
+            .iter()
+            .map(|(text, matched)| {
+                if *matched {
+                    format!("[{}]", text)
+                } else {
+                    text.to_owned()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn should_group_matched_chars() {
+        let s = segments("feature/bar", &[0, 8, 9, 10], 40);
+        assert_eq!(render(&s), "[f]eature/[bar]");
+    }
+
+    #[test]
+    fn should_cut_in_the_middle() {
+        let s = segments("abcdefghijklmnopqrst", &[], 11);
+        assert_eq!(render(&s), "abcde…pqrst");
+    }
+
+    #[test]
+    fn should_keep_matches_when_cutting() {
+        let s = segments("abcdefghijklmnopqrst", &[1, 2], 11);
+        assert_eq!(render(&s), "a[bc]def…qrst");
+
+        let s = segments("abcdefghijklmnopqrst", &[16], 11);
+        assert_eq!(render(&s), "abc…nop[q]rst");
+    }
+
+    #[test]
+    fn should_show_window_around_spread_matches() {
+        let s = segments("abcdefghijklmnopqrst", &[6, 13], 11);
+        assert_eq!(render(&s), "…f[g]hijklm[n]…");
+    }
+}
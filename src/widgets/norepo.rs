@@ -0,0 +1,148 @@
+use crate::theme;
+use druid::widget::{Button, CrossAxisAlignment, Either, Flex, Label, SizedBox};
+use druid::{Data, Env, Widget, WidgetExt};
+use git2::{ErrorCode, Repository};
+use log::{error, info};
+use std::cell::Cell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+const PADDING: f64 = 8.0;
+
+/// Shown instead of the app when there's no repository to open
+#[derive(Clone, Data)]
+pub struct NoRepo {
+    /// Where the repository was looked for, and would be created
+    pub dir: Rc<PathBuf>,
+    pub message: String,
+    /// Whether a repository can be created in `dir`. Only if there's none in
+    /// an existing directory, not for a mistyped path or a broken repository.
+    pub can_init: bool,
+    /// Set once a repository is created, for `main` to open it
+    pub created: Rc<Cell<bool>>,
+}
+
+impl NoRepo {
+    pub fn new(dir: PathBuf, err: &git2::Error) -> Self {
+        let dir = std::fs::canonicalize(&dir).unwrap_or(dir);
+        let can_init = err.code() == ErrorCode::NotFound && dir.is_dir();
+        let message = if can_init {
+            format!(
+                "{} isn't in a git repository: {}",
+                dir.display(),
+                err.message()
+            )
+        } else if !dir.exists() {
+            format!("{} doesn't exist", dir.display())
+        } else {
+            format!(
+                "Can't open the repository of {}: {}",
+                dir.display(),
+                err.message()
+            )
+        };
+        NoRepo {
+            message,
+            can_init,
+            dir: Rc::new(dir),
+            created: Rc::new(Cell::new(false)),
+        }
+    }
+
+    /// `git init` the directory, returns false if it failed
+    fn init(&mut self) -> bool {
+        if !self.can_init {
+            return false;
+        }
+        match Repository::init(self.dir.as_path()) {
+            Ok(_) => {
+                info!("Created a repository in {}", self.dir.display());
+                self.created.set(true);
+                true
+            }
+            Err(err) => {
+                error!("Failed to create a repository: {}", err);
+                self.message = format!("Failed to create a repository: {}", err.message());
+                false
+            }
+        }
+    }
+}
+
+pub fn widget() -> impl Widget<NoRepo> {
+    let create = Button::new("Create repository")
+        .on_click(|ctx, data: &mut NoRepo, _env| {
+            if data.init() {
+                ctx.submit_command(druid::commands::QUIT_APP, None);
+            }
+        })
+        .padding(PADDING);
+
+    let buttons = Flex::row()
+        .with_child(Either::new(
+            |data: &NoRepo, _env| data.can_init,
+            create,
+            SizedBox::empty(),
+        ))
+        .with_child(
+            Button::new("Quit")
+                .on_click(|ctx, _data: &mut NoRepo, _env| {
+                    ctx.submit_command(druid::commands::QUIT_APP, None);
+                })
+                .padding(PADDING),
+        );
+
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(
+            Label::new(|data: &NoRepo, _env: &Env| data.message.clone())
+                .with_text_color(theme::RED)
+                .padding(PADDING),
+        )
+        .with_child(Either::new(
+            |data: &NoRepo, _env| data.can_init,
+            Label::new(|data: &NoRepo, _env: &Env| {
+                format!("Run git init in {}?", data.dir.display())
+            })
+            .with_text_color(theme::BASE_00)
+            .padding(PADDING),
+            SizedBox::empty(),
+        ))
+        .with_child(buttons)
+        .padding(PADDING)
+        .expand()
+        .background(theme::BASE_3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn init_should_create_the_repository_to_open() {
+        let td = TempDir::new().unwrap();
+        let dir = td.path().join("new");
+        std::fs::create_dir(&dir).unwrap();
+        let err = Repository::discover(&dir).err().unwrap();
+
+        let mut no_repo = NoRepo::new(dir.clone(), &err);
+        assert!(no_repo.message.contains("isn't in a git repository"));
+        assert!(no_repo.init());
+        assert!(no_repo.created.get());
+        assert!(Repository::discover(&dir).is_ok());
+    }
+
+    #[test]
+    fn missing_directory_should_not_be_created() {
+        let td = TempDir::new().unwrap();
+        let dir = td.path().join("mistyped");
+        let err = Repository::discover(&dir).err().unwrap();
+
+        let mut no_repo = NoRepo::new(dir.clone(), &err);
+        assert!(!no_repo.can_init);
+        assert!(no_repo.message.contains("doesn't exist"));
+        assert!(!no_repo.init());
+        assert!(!dir.exists());
+    }
+}
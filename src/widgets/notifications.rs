@@ -0,0 +1,59 @@
+use crate::state::AppState;
+use crate::theme;
+use druid::widget::{Controller, CrossAxisAlignment, Either, Flex, Label, List, SizedBox};
+use druid::{Env, Event, EventCtx, Widget, WidgetExt};
+use im::Vector;
+
+const PADDING: f64 = 4.0;
+
+fn messages() -> impl Widget<Vector<String>> {
+    List::new(|| {
+        Label::new(|message: &String, _env: &Env| message.clone())
+            .with_text_color(theme::RED)
+            .padding(PADDING)
+    })
+}
+
+/// Messages for the user and config errors shown above the header, until
+/// they're clicked away
+pub fn widget() -> impl Widget<AppState> {
+    let panel = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(messages().lens(AppState::config_errors))
+        .with_child(messages().lens(AppState::notifications))
+        .with_child(
+            Label::new("Click to dismiss")
+                .with_text_color(theme::BASE_1)
+                .padding(PADDING),
+        )
+        .expand_width()
+        .background(theme::BASE_2)
+        .controller(NotificationsController);
+
+    Either::new(
+        |data: &AppState, _env| data.notifications.is_empty() && data.config_errors.is_empty(),
+        SizedBox::empty(),
+        panel,
+    )
+}
+
+struct NotificationsController;
+
+impl<W: Widget<AppState>> Controller<AppState, W> for NotificationsController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &Env,
+    ) {
+        if let Event::MouseDown(_) = event {
+            data.notifications.clear();
+            data.config_errors.clear();
+            ctx.set_handled();
+            return;
+        }
+        child.event(ctx, event, data, env);
+    }
+}
@@ -0,0 +1,252 @@
+use crate::state::AppState;
+use crate::theme;
+use druid::widget::{Controller, CrossAxisAlignment, Either, Flex, Label, List, Scroll, SizedBox};
+use druid::{Data, Env, Event, EventCtx, Lens, TimerToken, Widget, WidgetExt};
+use im::Vector;
+use log::debug;
+use std::path::PathBuf;
+use std::process::Output;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::Duration;
+
+const PADDING: f64 = 4.0;
+const OUTPUT_HEIGHT: f64 = 200.0;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A shell command of the keymap and what it printed
+#[derive(Clone, Data, Lens, Debug, Default)]
+pub struct ShellOutput {
+    /// The command with its placeholders replaced, empty if there's nothing
+    /// to show
+    pub cmd: String,
+    /// Waiting to be started by the [`ShellRunner`]
+    pub pending: bool,
+    /// Given by the [`ShellRunner`] when it starts the command, for its
+    /// result not to end up in another output
+    pub run_id: u64,
+    pub running: bool,
+    pub status: String,
+    /// Lines of stdout followed by those of stderr
+    pub lines: Vector<String>,
+}
+
+impl ShellOutput {
+    pub fn start(cmd: String) -> Self {
+        ShellOutput {
+            cmd,
+            pending: true,
+            ..ShellOutput::default()
+        }
+    }
+
+    fn finish(&mut self, result: std::io::Result<Output>) {
+        self.running = false;
+        let output = match result {
+            Ok(output) => output,
+            Err(err) => {
+                self.status = format!("Failed to run: {}", err);
+                return;
+            }
+        };
+
+        self.status = match output.status.code() {
+            Some(0) => "Done".to_owned(),
+            Some(code) => format!("Exited with {}", code),
+            None => "Killed".to_owned(),
+        };
+        for bytes in [output.stdout, output.stderr].iter() {
+            let text = String::from_utf8_lossy(bytes);
+            self.lines.extend(text.lines().map(str::to_owned));
+        }
+    }
+}
+
+/// Output of the last shell command, shown until it's clicked away
+pub fn widget() -> impl Widget<AppState> {
+    let lines = List::new(|| {
+        Label::new(|line: &String, _env: &Env| line.clone())
+            .with_text_color(theme::BASE_00)
+            .padding((PADDING, 0.0))
+    });
+
+    let panel = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(
+            Label::new(|output: &ShellOutput, _env: &Env| format!("$ {}", output.cmd))
+                .with_text_color(theme::BLUE)
+                .padding(PADDING),
+        )
+        .with_child(
+            Scroll::new(lines.lens(ShellOutput::lines))
+                .vertical()
+                .fix_height(OUTPUT_HEIGHT),
+        )
+        .with_child(
+            Label::new(|output: &ShellOutput, _env: &Env| {
+                if output.running || output.pending {
+                    "Running...".to_owned()
+                } else {
+                    format!("{}, click to dismiss", output.status)
+                }
+            })
+            .with_text_color(theme::BASE_1)
+            .padding(PADDING),
+        )
+        .expand_width()
+        .background(theme::BASE_2)
+        .lens(AppState::output)
+        .controller(OutputController);
+
+    Either::new(
+        |data: &AppState, _env| data.output.cmd.is_empty(),
+        SizedBox::empty(),
+        panel,
+    )
+}
+
+struct OutputController;
+
+impl<W: Widget<AppState>> Controller<AppState, W> for OutputController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &Env,
+    ) {
+        if let Event::MouseDown(_) = event {
+            if !data.output.running && !data.output.pending {
+                data.output = ShellOutput::default();
+            }
+            ctx.set_handled();
+            return;
+        }
+        child.event(ctx, event, data, env);
+    }
+}
+
+/// Run the command in `dir` on a separate thread so the ui stays responsive
+fn spawn(cmd: String, dir: PathBuf) -> Receiver<std::io::Result<Output>> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        debug!("Running shell command: {}", cmd);
+        let result = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(&cmd)
+            .current_dir(dir)
+            .output();
+        let _ = tx.send(result);
+    });
+    rx
+}
+
+/// A command started by the [`ShellRunner`]
+struct Run {
+    id: u64,
+    timer: TimerToken,
+    rx: Receiver<std::io::Result<Output>>,
+}
+
+/// Starts the shell commands requested with [`AppState::run_shell`] once the
+/// event that requested them is handled, and refreshes the repository state
+/// when they're done. Commands run one at a time, one requested meanwhile
+/// replaces the output and starts once the running one is done.
+#[derive(Default)]
+pub struct ShellRunner {
+    run: Option<Run>,
+    last_id: u64,
+}
+
+impl ShellRunner {
+    fn start(&mut self, ctx: &mut EventCtx, data: &mut AppState) {
+        self.last_id += 1;
+        data.output.run_id = self.last_id;
+        data.output.pending = false;
+        data.output.running = true;
+        let dir = data.repo.workdir().unwrap_or_else(|| data.repo.path());
+        self.run = Some(Run {
+            id: self.last_id,
+            timer: ctx.request_timer(POLL_INTERVAL),
+            rx: spawn(data.output.cmd.clone(), dir.to_owned()),
+        });
+    }
+
+    fn poll(&mut self, ctx: &mut EventCtx, data: &mut AppState) {
+        let Run { id, rx, .. } = match self.run.take() {
+            Some(run) => run,
+            None => return,
+        };
+
+        let result = match rx.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => {
+                self.run = Some(Run {
+                    id,
+                    timer: ctx.request_timer(POLL_INTERVAL),
+                    rx,
+                });
+                return;
+            }
+            Err(TryRecvError::Disconnected) => Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Command didn't finish",
+            )),
+        };
+        // The output was replaced while the command ran
+        if data.output.run_id == id {
+            data.output.finish(result);
+        } else {
+            debug!("Dropping the result of a replaced shell command");
+        }
+        data.refresh();
+
+        if data.output.pending {
+            self.start(ctx, data);
+        }
+    }
+}
+
+impl<W: Widget<AppState>> Controller<AppState, W> for ShellRunner {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &Env,
+    ) {
+        if let Event::Timer(token) = event {
+            if self.run.as_ref().map(|run| run.timer) == Some(*token) {
+                self.poll(ctx, data);
+                ctx.set_handled();
+                return;
+            }
+        }
+
+        child.event(ctx, event, data, env);
+
+        if data.output.pending && self.run.is_none() {
+            self.start(ctx, data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_should_have_stdout_then_stderr() {
+        let dir = std::env::temp_dir();
+        let rx = spawn("echo out; echo err >&2; exit 3".to_owned(), dir);
+        let mut output = ShellOutput::start("test".to_owned());
+        output.finish(rx.recv().unwrap());
+
+        assert_eq!(output.status, "Exited with 3");
+        assert_eq!(
+            output.lines,
+            im::vector!["out".to_owned(), "err".to_owned()]
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use crate::git;
+use crate::theme;
+use anyhow::Result;
+use druid::widget::{EnvScope, Label, List};
+use druid::{Env, Widget};
+use git2::{Oid, Repository};
+use im::{vector, Vector};
+
+/// Renders the lines of a patch, coloring additions, deletions and hunk headers
+pub fn widget() -> impl Widget<Vector<String>> {
+    List::new(|| {
+        EnvScope::new(
+            |env, line: &String| {
+                let color = match line.chars().next() {
+                    Some('+') => env.get(theme::GREEN),
+                    Some('-') => env.get(theme::RED),
+                    Some('@') => env.get(theme::CYAN),
+                    _ => env.get(theme::BASE_00),
+                };
+                env.set(druid::theme::LABEL_COLOR, color);
+            },
+            Label::new(|line: &String, _env: &Env| line.to_owned()),
+        )
+    })
+}
+
+pub fn to_lines(patch: &str) -> Vector<String> {
+    patch.lines().map(|line| line.to_owned()).collect()
+}
+
+/// Header, message and patch of a commit, one line per item
+pub fn commit_detail(repo: &Repository, oid: Oid) -> Result<Vector<String>> {
+    let commit = repo.find_commit(oid)?;
+    let author = commit.author();
+
+    let mut lines = vector![
+        format!("commit {}", oid),
+        format!(
+            "Author: {} <{}>",
+            author.name().unwrap_or_default(),
+            author.email().unwrap_or_default()
+        ),
+        format!("Date:   {}", git::format_time(&commit.time())),
+        "".to_owned(),
+    ];
+    lines.extend(
+        commit
+            .message()
+            .unwrap_or_default()
+            .lines()
+            .map(|line| format!("    {}", line)),
+    );
+    lines.push_back("".to_owned());
+    lines.append(to_lines(&git::get_commit_patch(repo, oid)?));
+
+    Ok(lines)
+}
@@ -0,0 +1,66 @@
+use crate::git;
+use crate::state::Command;
+use crate::widgets::patch;
+use anyhow::{anyhow, Result};
+use git2::Repository;
+use im::Vector;
+use log::debug;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+
+const BRANCH_COMMITS: usize = 10;
+
+/// What the fuzzybar items of a command refer to, and so how to preview them
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PreviewKind {
+    Branch,
+    Commit,
+    Stash,
+}
+
+pub fn preview_kind(cmd: Command) -> Option<PreviewKind> {
+    match cmd {
+        Command::BranchCheckout | Command::BranchDelete | Command::WorktreeAdd => {
+            Some(PreviewKind::Branch)
+        }
+        Command::TagCheckout | Command::CommitCheckout | Command::CherryPick => {
+            Some(PreviewKind::Commit)
+        }
+        Command::StashApply => Some(PreviewKind::Stash),
+        _ => None,
+    }
+}
+
+/// Lines describing the item `name`: recent commits of a branch, the patch of
+/// a tag or commit, the diffstat of a stash
+pub fn build_preview(repo: &Repository, kind: PreviewKind, name: &str) -> Result<Vector<String>> {
+    match kind {
+        PreviewKind::Branch => Ok(git::get_branch_summary(repo, name, BRANCH_COMMITS)?
+            .into_iter()
+            .collect()),
+        PreviewKind::Commit => patch::commit_detail(repo, git::resolve_commit(repo, name)?),
+        PreviewKind::Stash => {
+            let index = git::parse_stash_index(name).ok_or(anyhow!("Not a stash: {}", name))?;
+            Ok(patch::to_lines(&git::get_stash_diffstat(repo, index)?))
+        }
+    }
+}
+
+/// Build a preview on a separate thread, large patches take a while to render
+/// and the fuzzybar has to keep up with typing. The repository is reopened on
+/// that thread as it can't be shared.
+pub fn spawn_preview(
+    path: PathBuf,
+    kind: PreviewKind,
+    name: String,
+) -> Receiver<Result<Vector<String>>> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        debug!("Building {:?} preview of {}", kind, name);
+        let result = Repository::open(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|repo| build_preview(&repo, kind, &name));
+        let _ = tx.send(result);
+    });
+    rx
+}
@@ -0,0 +1,286 @@
+use crate::git::{self, ReflogItem};
+use crate::state::{AppState, CmdArgs, Command, View};
+use crate::theme;
+use crate::widgets::listview::{ListRow, ListView};
+use anyhow::Result;
+use druid::widget::{Controller, CrossAxisAlignment, Flex, Label};
+use druid::{
+    Data, Env, Event, EventCtx, KbKey, Lens, LensExt, LifeCycle, LifeCycleCtx, Widget, WidgetExt,
+};
+use git2::{Oid, Repository, ResetType};
+use log::error;
+
+const KEY_HINTS: &str = "c checkout   b create branch   r reset   R reset --hard   esc back";
+const BRANCH_PREFIX: &str = "recovered-";
+
+#[derive(Clone, Data, Lens, Debug, PartialEq)]
+pub struct ReflogEntry {
+    pub old: String,
+    pub new: String,
+    pub message: String,
+    pub time: String,
+    pub selected: bool,
+}
+
+impl ReflogEntry {
+    fn new(item: &ReflogItem) -> Self {
+        ReflogEntry {
+            old: format!("{}", item.old),
+            new: format!("{}", item.new),
+            message: item.message.clone(),
+            time: git::format_time(&item.time),
+            selected: false,
+        }
+    }
+}
+
+impl ListRow for ReflogEntry {
+    fn text(&self) -> String {
+        format!(
+            "{}..{}  {}  {}",
+            &self.old[..7],
+            &self.new[..7],
+            self.time,
+            self.message
+        )
+    }
+
+    fn is_selected(&self) -> bool {
+        self.selected
+    }
+
+    fn set_selected(&mut self, selected: bool) {
+        self.selected = selected;
+    }
+}
+
+/// Reflog of HEAD or a local branch, with actions to get back to any of its
+/// entries
+#[derive(Clone, Data, Lens)]
+pub struct Reflog {
+    name: String,
+    entries: ListView<ReflogEntry>,
+    /// Entry a hard reset waits to be confirmed for, with a second `R`
+    confirm_reset: Option<String>,
+}
+
+impl Reflog {
+    pub fn new(repo: &Repository, name: &str) -> Self {
+        let entries = match git::get_reflog(repo, name) {
+            Ok(items) => items.iter().map(ReflogEntry::new).collect(),
+            Err(err) => {
+                error!("{}", err);
+                return Reflog {
+                    name: name.to_owned(),
+                    ..Self::default()
+                };
+            }
+        };
+
+        Reflog {
+            name: name.to_owned(),
+            entries: ListView::new(entries),
+            confirm_reset: None,
+        }
+    }
+
+    fn hints(&self) -> String {
+        match &self.confirm_reset {
+            Some(oid) => format!(
+                "R again to reset --hard to {}, discarding uncommitted changes   any other key \
+                 cancels",
+                &oid[..7]
+            ),
+            None => KEY_HINTS.to_owned(),
+        }
+    }
+
+    fn selected_oid(&self) -> Option<Oid> {
+        self.entries
+            .selected()
+            .and_then(|entry| Oid::from_str(&entry.new).ok())
+    }
+
+    /// Run the action bound to `key` on the selected entry. Returns None if
+    /// no action ran, a hard reset only runs once `R` is pressed twice on the
+    /// same entry.
+    fn run_action(&mut self, repo: &Repository, key: &str) -> Option<Result<()>> {
+        let confirm_reset = self.confirm_reset.take();
+        let oid = self.selected_oid()?;
+
+        match key {
+            "c" => Some(git::checkout_commit(repo, oid)),
+            "r" => Some(git::reset_to(repo, oid, ResetType::Mixed)),
+            "R" if confirm_reset == Some(oid.to_string()) => {
+                Some(git::reset_to(repo, oid, ResetType::Hard))
+            }
+            "R" => {
+                self.confirm_reset = Some(oid.to_string());
+                None
+            }
+            _ => None,
+        }
+    }
+
+    pub fn widget() -> impl Widget<AppState> {
+        let header = Label::dynamic(|app: &AppState, _| format!("Reflog of {}", app.reflog.name))
+            .with_text_color(theme::BLUE);
+
+        let entries = ListView::widget().lens(AppState::reflog.then(Reflog::entries));
+
+        let hints =
+            Label::dynamic(|app: &AppState, _| app.reflog.hints()).with_text_color(theme::BASE_1);
+
+        Flex::column()
+            .with_child(header)
+            .with_spacer(8.0)
+            .with_child(entries)
+            .with_spacer(8.0)
+            .with_child(hints)
+            .cross_axis_alignment(CrossAxisAlignment::Start)
+            .controller(ReflogController)
+    }
+}
+
+impl Default for Reflog {
+    fn default() -> Self {
+        Reflog {
+            name: "".to_owned(),
+            entries: ListView::default(),
+            confirm_reset: None,
+        }
+    }
+}
+
+struct ReflogController;
+
+impl<W: Widget<AppState>> Controller<AppState, W> for ReflogController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &Env,
+    ) {
+        match event {
+            Event::Command(cmd) if cmd.is(crate::consts::VIEW_TAKE_FOCUS) => {
+                ctx.request_focus();
+                ctx.set_handled();
+            }
+            Event::KeyDown(key_event) => {
+                if data.reflog.entries.navigate(&key_event.key) {
+                    data.reflog.confirm_reset = None;
+                    ctx.set_handled();
+                    return;
+                }
+
+                match &key_event.key {
+                    KbKey::Escape => {
+                        data.reflog.confirm_reset = None;
+                        data.view = View::Status;
+                        ctx.resign_focus();
+                        ctx.submit_command(crate::consts::CS_TAKE_FOCUS, None);
+                    }
+                    KbKey::Character(c) if c == "b" => {
+                        data.reflog.confirm_reset = None;
+                        if let Some(oid) = data.reflog.selected_oid() {
+                            let oid = oid.to_string();
+                            data.fuzzybar.is_hidden = false;
+                            data.fuzzybar.cmd = Command::BranchCreate;
+                            data.fuzzybar.query = format!("{}{}", BRANCH_PREFIX, &oid[..7]);
+                            data.fuzzybar.shell_source = None;
+                            data.fuzzybar.shell_cmd = None;
+                            data.fuzzybar.args = CmdArgs {
+                                start: Some(oid),
+                                ..CmdArgs::default()
+                            };
+                            ctx.resign_focus();
+                            ctx.submit_command(crate::consts::FB_TAKE_FOCUS, None);
+                        }
+                    }
+                    KbKey::Character(c) => {
+                        if let Some(result) = data.reflog.run_action(&data.repo, c) {
+                            if let Err(err) = result {
+                                error!("Reflog action failed: {:#}", err);
+                                data.notifications
+                                    .push_back(format!("Reflog action failed: {:#}", err));
+                            }
+                            data.refresh();
+                            let selected_idx = data.reflog.entries.selected_idx;
+                            data.reflog = Reflog::new(&data.repo, &data.reflog.name);
+                            data.reflog.entries.select(selected_idx);
+                        }
+                    }
+                    _ => data.reflog.confirm_reset = None,
+                }
+                ctx.set_handled();
+            }
+            _ => child.event(ctx, event, data, env),
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        child: &mut W,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &AppState,
+        env: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            ctx.register_for_focus();
+        }
+
+        child.lifecycle(ctx, event, data, env)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests;
+
+    #[test]
+    fn hard_reset_should_wait_for_confirmation() {
+        let (_td, repo) = tests::repo_init();
+        let c1 = tests::commit_file(&repo, "a.txt", "one");
+        let c2 = tests::commit_file(&repo, "a.txt", "two");
+        let workdir = repo.workdir().unwrap().to_owned();
+        std::fs::write(workdir.join("a.txt"), "uncommitted").unwrap();
+
+        let mut reflog = Reflog::new(&repo, "HEAD");
+        reflog.entries.select(1);
+        assert!(reflog.run_action(&repo, "R").is_none());
+        assert!(reflog.run_action(&repo, "x").is_none());
+        assert!(reflog.run_action(&repo, "R").is_none());
+        assert_eq!(repo.head().unwrap().target(), Some(c2));
+
+        reflog.run_action(&repo, "R").unwrap().unwrap();
+        assert_eq!(repo.head().unwrap().target(), Some(c1));
+        let content = std::fs::read_to_string(workdir.join("a.txt")).unwrap();
+        assert_eq!(content, "one");
+    }
+
+    #[test]
+    fn branch_should_start_at_the_entry() {
+        let (_td, repo) = tests::repo_init();
+        let c1 = tests::commit_file(&repo, "a.txt", "one");
+        let _ = tests::commit_file(&repo, "a.txt", "two");
+
+        let args = CmdArgs {
+            start: Some(c1.to_string()),
+            ..CmdArgs::default()
+        };
+        git::execute_cmd(
+            &repo,
+            Command::BranchCreate,
+            &args,
+            &["recovered".to_owned()],
+        );
+        let branch = repo
+            .find_branch("recovered", git2::BranchType::Local)
+            .unwrap();
+        assert_eq!(branch.get().target(), Some(c1));
+    }
+}
@@ -1,10 +1,10 @@
-use crate::git;
+use crate::git::{self, SubmoduleInfo};
 use crate::state::AppState;
 use crate::theme;
 use anyhow::Result;
 use druid::widget::{CrossAxisAlignment, Flex, Label, List};
 use druid::{Data, Lens, LensExt, Widget, WidgetExt};
-use git2::{Repository, Status};
+use git2::{Oid, Repository, Status};
 use im::{vector, Vector};
 use log::{debug, error, info};
 
@@ -13,6 +13,9 @@ pub const ST_MODIFIED: &str = "modified";
 pub const ST_RENAMED: &str = "renamed";
 pub const ST_DELETED: &str = "deleted";
 pub const ST_TYPECHANGE: &str = "typechange";
+pub const ST_UNINITIALIZED: &str = "uninitialized";
+pub const ST_DIRTY: &str = "dirty";
+pub const ST_CLEAN: &str = "clean";
 
 #[derive(Clone, Data, Lens)]
 pub struct RepoStatusDetail {
@@ -20,6 +23,7 @@ pub struct RepoStatusDetail {
     unstaged: Vector<(String, String)>,
     staged: Vector<(String, String)>,
     stashed: Vector<String>,
+    submodules: Vector<(String, String)>,
 }
 
 impl RepoStatusDetail {
@@ -31,10 +35,21 @@ impl RepoStatusDetail {
         }
         let mut statusdetail = RepoStatusDetail::default();
 
+        let submodules = git::get_submodules(repo).unwrap_or_else(|err| {
+            error!("Failed to get submodules: {}", err);
+            vec![]
+        });
+        statusdetail.submodules = submodules.iter().map(Self::submodule_line).collect();
+
         for s in statuses.unwrap().iter() {
             let path = s.path().unwrap().to_owned();
             let status = s.status();
 
+            // Submodules have their own section
+            if submodules.iter().any(|submodule| submodule.path == path) {
+                continue;
+            }
+
             if status == Status::WT_NEW {
                 statusdetail.untracked.push_back(path.clone())
             }
@@ -88,6 +103,35 @@ impl RepoStatusDetail {
         statusdetail
     }
 
+    fn submodule_line(submodule: &SubmoduleInfo) -> (String, String) {
+        let state = if !submodule.initialized {
+            ST_UNINITIALIZED
+        } else if submodule.recorded != submodule.checked_out {
+            ST_MODIFIED
+        } else if submodule.dirty {
+            ST_DIRTY
+        } else {
+            ST_CLEAN
+        };
+
+        let short = |oid: Option<Oid>| match oid {
+            Some(oid) => format!("{}", oid)[..7].to_owned(),
+            None => "-------".to_owned(),
+        };
+
+        let mut line = format!(
+            "{}\t\t{} -> {}",
+            submodule.path,
+            short(submodule.recorded),
+            short(submodule.checked_out)
+        );
+        if submodule.initialized && submodule.dirty {
+            line.push_str(" (dirty)");
+        }
+
+        (state.to_owned(), line)
+    }
+
     pub fn widget() -> impl Widget<AppState> {
         let untracked_header = Flex::row().with_flex_child(
             Label::new("Untracked files").with_text_color(theme::BLUE),
@@ -123,6 +167,16 @@ impl RepoStatusDetail {
             1.0,
         );
 
+        let submodules_header =
+            Flex::row().with_flex_child(Label::new("Submodules").with_text_color(theme::BLUE), 1.0);
+        let submodules = Flex::row().with_flex_child(
+            List::new(|| {
+                Label::new(|item: &(String, String), _env: &_| format!("{}\t\t{}", item.0, item.1))
+            })
+            .lens(AppState::repo_status.then(RepoStatusDetail::submodules)),
+            1.0,
+        );
+
         Flex::column()
             .with_child(untracked_header)
             .with_child(untracked_files)
@@ -132,6 +186,9 @@ impl RepoStatusDetail {
             .with_spacer(24.0)
             .with_child(staged_header)
             .with_child(staged_files)
+            .with_spacer(24.0)
+            .with_child(submodules_header)
+            .with_child(submodules)
             .cross_axis_alignment(CrossAxisAlignment::Start)
     }
 }
@@ -143,6 +200,7 @@ impl Default for RepoStatusDetail {
             unstaged: vector![],
             staged: vector![],
             stashed: vector![],
+            submodules: vector![],
         }
     }
 }
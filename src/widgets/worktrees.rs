@@ -0,0 +1,222 @@
+use crate::git::{self, WorktreeInfo};
+use crate::state::{AppState, CmdArgs, Command, View};
+use crate::theme;
+use crate::widgets::listview::{ListRow, ListView};
+use anyhow::Result;
+use druid::widget::{Controller, CrossAxisAlignment, Flex, Label};
+use druid::{
+    Data, Env, Event, EventCtx, KbKey, Lens, LensExt, LifeCycle, LifeCycleCtx, Widget, WidgetExt,
+};
+use git2::Repository;
+use log::{error, info};
+
+const KEY_HINTS: &str = "enter switch   a add   l lock/unlock   p prune   esc back";
+
+#[derive(Clone, Data, Lens, Debug, PartialEq)]
+pub struct WorktreeEntry {
+    pub name: Option<String>,
+    pub path: String,
+    pub head: String,
+    pub locked: bool,
+    pub prunable: bool,
+    pub active: bool,
+    pub selected: bool,
+}
+
+impl ListRow for WorktreeEntry {
+    fn text(&self) -> String {
+        let mut text = format!(
+            "{} {:24} {:24} {}",
+            if self.active { "*" } else { " " },
+            self.name.as_deref().unwrap_or("<main>"),
+            self.head,
+            self.path
+        );
+        if self.locked {
+            text.push_str("  [locked]");
+        }
+        if self.prunable {
+            text.push_str("  [prunable]");
+        }
+        text
+    }
+
+    fn is_selected(&self) -> bool {
+        self.selected
+    }
+
+    fn set_selected(&mut self, selected: bool) {
+        self.selected = selected;
+    }
+}
+
+/// Main and linked worktrees of the repository
+#[derive(Clone, Data, Lens)]
+pub struct Worktrees {
+    entries: ListView<WorktreeEntry>,
+}
+
+impl Worktrees {
+    pub fn new(repo: &Repository) -> Self {
+        let worktrees = match git::get_worktrees(repo) {
+            Ok(worktrees) => worktrees,
+            Err(err) => {
+                error!("Failed to list worktrees: {}", err);
+                return Self::default();
+            }
+        };
+
+        let active = git::get_worktree_name(repo);
+        let entries = worktrees
+            .into_iter()
+            .map(|info: WorktreeInfo| WorktreeEntry {
+                active: info.name == active,
+                name: info.name,
+                path: info.path.display().to_string(),
+                head: info.head,
+                locked: info.locked,
+                prunable: info.prunable,
+                selected: false,
+            })
+            .collect();
+
+        Worktrees {
+            entries: ListView::new(entries),
+        }
+    }
+
+    /// Run the action bound to `key` on the selected worktree. Returns false if
+    /// no action is bound to the key.
+    fn run_action(&self, repo: &Repository, key: &str) -> bool {
+        let entry = match self.entries.selected() {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        let result: Result<()> = match (key, &entry.name) {
+            ("l", Some(name)) => git::set_worktree_locked(repo, name, !entry.locked),
+            ("p", _) => git::prune_worktrees(repo).map(|pruned| {
+                info!("Pruned {} worktrees", pruned);
+            }),
+            _ => return false,
+        };
+
+        if let Err(err) = result {
+            error!("Worktree action failed: {:#}", err);
+        }
+        true
+    }
+
+    pub fn widget() -> impl Widget<AppState> {
+        let header = Label::new("Worktrees").with_text_color(theme::BLUE);
+
+        let entries = ListView::widget().lens(AppState::worktrees.then(Worktrees::entries));
+
+        let hints = Label::new(KEY_HINTS).with_text_color(theme::BASE_1);
+
+        Flex::column()
+            .with_child(header)
+            .with_spacer(8.0)
+            .with_child(entries)
+            .with_spacer(8.0)
+            .with_child(hints)
+            .cross_axis_alignment(CrossAxisAlignment::Start)
+            .controller(WorktreesController)
+    }
+}
+
+impl Default for Worktrees {
+    fn default() -> Self {
+        Worktrees {
+            entries: ListView::default(),
+        }
+    }
+}
+
+/// Point the whole app at the repository of another worktree
+fn switch_worktree(data: &mut AppState) {
+    let path = match data.worktrees.entries.selected() {
+        Some(entry) => entry.path.clone(),
+        None => return,
+    };
+
+    match Repository::open(&path) {
+        Ok(repo) => data.switch_repo(repo),
+        Err(err) => error!("Failed to open worktree {}: {}", path, err),
+    }
+}
+
+struct WorktreesController;
+
+impl<W: Widget<AppState>> Controller<AppState, W> for WorktreesController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &Env,
+    ) {
+        match event {
+            Event::Command(cmd) if cmd.is(crate::consts::VIEW_TAKE_FOCUS) => {
+                ctx.request_focus();
+                ctx.set_handled();
+            }
+            Event::KeyDown(key_event) => {
+                if data.worktrees.entries.navigate(&key_event.key) {
+                    ctx.set_handled();
+                    return;
+                }
+
+                match &key_event.key {
+                    KbKey::Escape => {
+                        data.view = View::Status;
+                        ctx.resign_focus();
+                        ctx.submit_command(crate::consts::CS_TAKE_FOCUS, None);
+                    }
+                    KbKey::Enter => {
+                        switch_worktree(data);
+                        if data.view == View::Status {
+                            ctx.resign_focus();
+                            ctx.submit_command(crate::consts::CS_TAKE_FOCUS, None);
+                        }
+                    }
+                    KbKey::Character(c) if c == "a" => {
+                        data.fuzzybar.is_hidden = false;
+                        data.fuzzybar.cmd = Command::WorktreeAdd;
+                        data.fuzzybar.shell_source = None;
+                        data.fuzzybar.shell_cmd = None;
+                        data.fuzzybar.args = CmdArgs::default();
+                        ctx.resign_focus();
+                        ctx.submit_command(crate::consts::FB_TAKE_FOCUS, None);
+                    }
+                    KbKey::Character(c) => {
+                        if data.worktrees.run_action(&data.repo, c) {
+                            let selected_idx = data.worktrees.entries.selected_idx;
+                            data.worktrees = Worktrees::new(&data.repo);
+                            data.worktrees.entries.select(selected_idx);
+                        }
+                    }
+                    _ => (),
+                }
+                ctx.set_handled();
+            }
+            _ => child.event(ctx, event, data, env),
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        child: &mut W,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &AppState,
+        env: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            ctx.register_for_focus();
+        }
+
+        child.lifecycle(ctx, event, data, env)
+    }
+}
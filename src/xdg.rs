@@ -0,0 +1,23 @@
+//! Locations from the XDG base directory specification
+
+use std::env;
+use std::path::PathBuf;
+
+/// Directory gitools keeps its state in, `$XDG_DATA_HOME/gitools`
+pub fn data_dir() -> Option<PathBuf> {
+    base_dir("XDG_DATA_HOME", ".local/share").map(|dir| dir.join("gitools"))
+}
+
+/// Directory of the user's config, `$XDG_CONFIG_HOME/gitools`
+pub fn config_dir() -> Option<PathBuf> {
+    base_dir("XDG_CONFIG_HOME", ".config").map(|dir| dir.join("gitools"))
+}
+
+/// Use `var` if it's set to an absolute path, else fall back to `default`
+/// under the home directory
+fn base_dir(var: &str, default: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(default)))
+}